@@ -0,0 +1,620 @@
+//! Key and chord detection for existing MIDI files.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::chord_symbol::PITCH_CLASS_NAMES;
+use crate::drums::DRUM_CHANNEL;
+use crate::error::{Error, Result};
+use crate::progression::{Key, Mode};
+
+// ---------------------------------------------------------------------
+// Analysis: chords and key of an existing MIDI file
+// ---------------------------------------------------------------------
+
+/// Chord qualities the detector recognises: symbol suffix and intervals.
+const QUALITIES: [(&str, &[u8]); 14] = [
+    ("", &[0, 4, 7]),
+    ("m", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("sus4", &[0, 5, 7]),
+    ("sus2", &[0, 2, 7]),
+    ("5", &[0, 7]),
+    ("7", &[0, 4, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("m7b5", &[0, 3, 6, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("6", &[0, 4, 7, 9]),
+    ("m6", &[0, 3, 7, 9]),
+];
+
+/// Krumhansl-Kessler key profiles: how strongly each scale degree suggests
+/// a major or minor key.
+const MAJOR_PROFILE: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// The most beats or bars [`analyze`] reads: over nine hours of 4/4 at 120
+/// BPM by the beat. Long deltas can stretch a small file far past this.
+const MAX_WINDOWS: u64 = 1 << 16;
+
+/// How much of each pitch class sounds, in ticks.
+pub type PitchWeights = [f64; 12];
+
+/// The stretch of music each detected chord covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Resolution {
+    /// One chord per beat.
+    Beat,
+    /// One chord per bar.
+    #[default]
+    Bar,
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "beat" | "beats" => Ok(Resolution::Beat),
+            "bar" | "bars" | "measure" => Ok(Resolution::Bar),
+            other => Err(format!(
+                "unknown resolution {other:?}, expected beat or bar"
+            )),
+        }
+    }
+}
+
+/// A chord heard in one beat or bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedChord {
+    /// Root pitch class (0 = C .. 11 = B).
+    pub root: u8,
+    /// Quality as a chord symbol suffix ("", "m7", "sus4", ...).
+    pub quality: &'static str,
+    /// Lowest sounding pitch class, when it is a chord tone other than the root.
+    pub bass: Option<u8>,
+}
+
+impl DetectedChord {
+    fn intervals(&self) -> &'static [u8] {
+        QUALITIES
+            .iter()
+            .find(|(suffix, _)| *suffix == self.quality)
+            .map_or(&[0, 4, 7], |quality| quality.1)
+    }
+
+    /// The chord symbol, e.g. "Am7" or "G/B".
+    pub fn symbol(&self) -> String {
+        let mut symbol = format!(
+            "{}{}",
+            PITCH_CLASS_NAMES[usize::from(self.root)],
+            self.quality
+        );
+        if let Some(bass) = self.bass {
+            symbol.push('/');
+            symbol.push_str(PITCH_CLASS_NAMES[usize::from(bass)]);
+        }
+        symbol
+    }
+
+    /// The Roman numeral in `key`, e.g. "ii7", "V", "bVII", "vii°". Degrees
+    /// of the key's mode are written plain; other roots as degrees of the
+    /// major scale on the same tonic, flattened or sharpened where that scale
+    /// has no note. The bass of an inversion is left out, and an added sixth
+    /// is written "add6" ("Iadd6", "viadd6") so it does not read as one.
+    pub fn numeral(&self, key: Key) -> String {
+        const NUMERALS: [&str; 7] = ["I", "II", "III", "IV", "V", "VI", "VII"];
+        const MAJOR_DEGREES: [(u8, &str); 12] = [
+            (1, ""),
+            (2, "b"),
+            (2, ""),
+            (3, "b"),
+            (3, ""),
+            (4, ""),
+            (4, "#"),
+            (5, ""),
+            (6, "b"),
+            (6, ""),
+            (7, "b"),
+            (7, ""),
+        ];
+        let offset = (self.root + 12 - key.tonic) % 12;
+        let (degree, accidental) = match key.mode.degrees().iter().position(|&d| d == offset) {
+            Some(index) => (index as u8 + 1, ""),
+            None => MAJOR_DEGREES[usize::from(offset)],
+        };
+        let numeral = NUMERALS[usize::from(degree - 1)];
+
+        let intervals = self.intervals();
+        let minor_third = intervals.contains(&3) && !intervals.contains(&4);
+        let suffix = match self.quality {
+            "dim" => "°",
+            "dim7" => "°7",
+            "m7b5" => "ø7",
+            "aug" => "+",
+            "m" => "",
+            "m7" => "7",
+            "6" | "m6" => "add6",
+            other => other,
+        };
+        if minor_third {
+            format!("{accidental}{}{suffix}", numeral.to_ascii_lowercase())
+        } else {
+            format!("{accidental}{numeral}{suffix}")
+        }
+    }
+}
+
+/// The best-matching chord for `weights`, preferring chords rooted on `bass`
+/// and simpler chords when the fit is otherwise equal. `None` for silence.
+pub fn detect_chord(weights: &PitchWeights, bass: Option<u8>) -> Option<DetectedChord> {
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let share = |pc: u8| weights[usize::from(pc % 12)] / total;
+
+    let mut best: Option<(f64, DetectedChord)> = None;
+    for root in 0..12u8 {
+        for &(quality, intervals) in &QUALITIES {
+            let covered: f64 = intervals.iter().map(|&i| share(root + i)).sum();
+            let missing = intervals
+                .iter()
+                .filter(|&&i| share(root + i) < 0.01)
+                .count();
+            let mut score = 2.0 * covered - 1.0 - 0.15 * missing as f64;
+            score -= 0.01 * intervals.len() as f64;
+            if bass == Some(root) {
+                score += 0.1;
+            }
+            if best.is_none_or(|(top, _)| score > top + 1e-9) {
+                let bass =
+                    bass.filter(|&b| b != root && intervals.contains(&((b + 12 - root) % 12)));
+                best = Some((
+                    score,
+                    DetectedChord {
+                        root,
+                        quality,
+                        bass,
+                    },
+                ));
+            }
+        }
+    }
+    best.map(|(_, chord)| chord)
+}
+
+/// The major or minor key whose profile correlates best with `weights`.
+pub fn detect_key(weights: &PitchWeights) -> Key {
+    let correlation = |profile: &[f64; 12], tonic: usize| {
+        let rotated: Vec<f64> = (0..12).map(|pc| profile[(pc + 12 - tonic) % 12]).collect();
+        let mean_w = weights.iter().sum::<f64>() / 12.0;
+        let mean_p = rotated.iter().sum::<f64>() / 12.0;
+        let (mut cov, mut var_w, mut var_p) = (0.0, 0.0, 0.0);
+        for pc in 0..12 {
+            let (w, p) = (weights[pc] - mean_w, rotated[pc] - mean_p);
+            cov += w * p;
+            var_w += w * w;
+            var_p += p * p;
+        }
+        if var_w <= 0.0 {
+            0.0
+        } else {
+            cov / (var_w * var_p).sqrt()
+        }
+    };
+
+    let mut best = (f64::MIN, Key::new(0, Mode::Major));
+    for tonic in 0..12 {
+        for (mode, profile) in [(Mode::Major, &MAJOR_PROFILE), (Mode::Minor, &MINOR_PROFILE)] {
+            let score = correlation(profile, tonic);
+            if score > best.0 + 1e-9 {
+                best = (score, Key::new(tonic as u8, mode));
+            }
+        }
+    }
+    best.1
+}
+
+/// Chords found in a MIDI file, one per beat or bar, and the key they are in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// The key the numerals are written in.
+    pub key: Key,
+    /// Whether `key` was detected from the notes rather than given.
+    pub key_detected: bool,
+    /// Whether `chords` holds one entry per beat or per bar.
+    pub resolution: Resolution,
+    /// Beats per bar, from the file's first time signature (4 without one).
+    pub beats_per_bar: usize,
+    /// One entry per beat or bar, up to the last one with a chord; `None`
+    /// where (next to) nothing sounds.
+    pub chords: Vec<Option<DetectedChord>>,
+}
+
+/// A sounding note gathered from the file.
+struct Note {
+    start: u64,
+    end: u64,
+    key: u8,
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidMidi {
+        reason: reason.into(),
+    }
+}
+
+/// Every pitched note in `smf` (the GM percussion channel is skipped), plus
+/// the first time signature as (numerator, denominator).
+fn collect_notes(smf: &Smf) -> (Vec<Note>, (u8, u32)) {
+    let mut notes = Vec::new();
+    let mut time_signature = None;
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        let mut sounding: BTreeMap<(u8, u8), Vec<u64>> = BTreeMap::new();
+        for event in track {
+            tick += u64::from(event.delta.as_int());
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, power, ..)) => {
+                    time_signature.get_or_insert((numerator, 1u32 << power.min(6)));
+                }
+                TrackEventKind::Midi { channel, message } if channel != DRUM_CHANNEL => {
+                    match message {
+                        MidiMessage::NoteOn { key, vel } if vel > 0 => sounding
+                            .entry((channel.as_int(), key.as_int()))
+                            .or_default()
+                            .push(tick),
+                        MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                            let open = sounding
+                                .entry((channel.as_int(), key.as_int()))
+                                .or_default();
+                            if !open.is_empty() {
+                                let start = open.remove(0);
+                                notes.push(Note {
+                                    start,
+                                    end: tick,
+                                    key: key.as_int(),
+                                });
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Notes never released sound until the end of their track.
+        for ((_, key), starts) in sounding {
+            notes.extend(starts.into_iter().map(|start| Note {
+                start,
+                end: tick,
+                key,
+            }));
+        }
+    }
+    (notes, time_signature.unwrap_or((4, 4)))
+}
+
+/// Detect the chords of `smf`, one per beat or bar, and label them against
+/// `key`, or against the key detected from all the file's notes when `key`
+/// is `None`.
+pub fn analyze(smf: &Smf, resolution: Resolution, key: Option<Key>) -> Result<Analysis> {
+    let Timing::Metrical(ppq) = smf.header.timing else {
+        return Err(invalid("timecode-based files have no beats to analyse"));
+    };
+    let (notes, (numerator, denominator)) = collect_notes(smf);
+    if notes.is_empty() {
+        return Err(invalid("no pitched notes to analyse"));
+    }
+
+    let beat = (u64::from(ppq.as_int()) * 4 / u64::from(denominator)).max(1);
+    let window = match resolution {
+        Resolution::Beat => beat,
+        Resolution::Bar => beat * u64::from(numerator.max(1)),
+    };
+    let length = notes.iter().map(|note| note.end).max().unwrap_or(0);
+    let count = length.div_ceil(window);
+    if count > MAX_WINDOWS {
+        let unit = match resolution {
+            Resolution::Beat => "beats",
+            Resolution::Bar => "bars",
+        };
+        return Err(invalid(format!(
+            "{count} {unit} of music is more than the {MAX_WINDOWS} that can be analysed"
+        )));
+    }
+    let count = count as usize;
+
+    let mut weights = vec![[0.0; 12]; count];
+    let mut lowest: Vec<Option<u8>> = vec![None; count];
+    let mut overall = [0.0; 12];
+    for note in &notes {
+        let pc = usize::from(note.key % 12);
+        overall[pc] += (note.end - note.start) as f64;
+        let first = (note.start / window) as usize;
+        let last = (note.end.saturating_sub(1) / window) as usize;
+        for index in first..=last.min(count.saturating_sub(1)) {
+            let from = note.start.max(index as u64 * window);
+            let to = note.end.min((index as u64 + 1) * window);
+            if to > from {
+                weights[index][pc] += (to - from) as f64;
+                lowest[index] = Some(lowest[index].map_or(note.key, |low| low.min(note.key)));
+            }
+        }
+    }
+
+    // A window needs an eighth of its length of sound to hold a chord, so
+    // notes ringing a few ticks past a change do not name one of their own.
+    let mut chords: Vec<Option<DetectedChord>> = weights
+        .iter()
+        .zip(&lowest)
+        .map(|(weights, low)| {
+            let heard = weights.iter().sum::<f64>() >= window as f64 / 8.0;
+            heard
+                .then(|| detect_chord(weights, low.map(|key| key % 12)))
+                .flatten()
+        })
+        .collect();
+    while chords.last() == Some(&None) {
+        chords.pop();
+    }
+
+    Ok(Analysis {
+        key: key.unwrap_or_else(|| detect_key(&overall)),
+        key_detected: key.is_none(),
+        resolution,
+        beats_per_bar: usize::from(numerator.max(1)),
+        chords,
+    })
+}
+
+impl Analysis {
+    /// Chord entries per bar.
+    fn per_bar(&self) -> usize {
+        match self.resolution {
+            Resolution::Beat => self.beats_per_bar,
+            Resolution::Bar => 1,
+        }
+    }
+
+    /// Bars of `label`led chords, four to a line; a label repeated within a
+    /// bar is written as ".". A beat or bar with no chord holds the chord
+    /// before it (the first chord, before any has sounded), so the chart
+    /// always reads back with [`crate::progression::parse_progression`].
+    fn chart_with(&self, label: impl Fn(&DetectedChord) -> String) -> String {
+        let mut held = self.chords.iter().flatten().next().map(&label);
+        let labels: Vec<String> = self
+            .chords
+            .iter()
+            .map(|chord| {
+                if let Some(chord) = chord {
+                    held = Some(label(chord));
+                }
+                held.clone().unwrap_or_default()
+            })
+            .collect();
+
+        let mut lines: Vec<String> = Vec::new();
+        for (bar, entries) in labels.chunks(self.per_bar()).enumerate() {
+            let tokens: Vec<&str> = entries
+                .iter()
+                .enumerate()
+                .map(|(index, text)| match text {
+                    _ if index > 0 && entries[index - 1] == *text => ".",
+                    text => text,
+                })
+                .collect();
+            let cell = format!(" {} |", tokens.join(" "));
+            match lines.last_mut() {
+                Some(line) if bar % 4 != 0 => line.push_str(&cell),
+                _ => lines.push(format!("|{cell}")),
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// The chords as a chart in progression syntax, bar lines included; a
+    /// chord held within a bar is written as ".", e.g. "| C . G/B . | Am |".
+    pub fn chart(&self) -> String {
+        self.chart_with(DetectedChord::symbol)
+    }
+
+    /// The same chart in Roman numerals against `key`.
+    pub fn numeral_chart(&self) -> String {
+        self.chart_with(|chord| chord.numeral(self.key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progression::parse_progression;
+    use crate::smf::{build_chord_smf, SmfSettings};
+    use midly::num::u28;
+    use midly::TrackEvent;
+
+    fn weights(notes: &[u8]) -> PitchWeights {
+        let mut weights = [0.0; 12];
+        for &note in notes {
+            weights[usize::from(note % 12)] += 1.0;
+        }
+        weights
+    }
+
+    fn symbol(notes: &[u8]) -> String {
+        detect_chord(&weights(notes), notes.iter().min().map(|n| n % 12))
+            .unwrap()
+            .symbol()
+    }
+
+    #[test]
+    fn test_detect_chords_and_numerals() {
+        assert_eq!(symbol(&[60, 64, 67]), "C");
+        assert_eq!(symbol(&[57, 60, 64, 67]), "Am7");
+        assert_eq!(symbol(&[64, 67, 72]), "C/E");
+        assert_eq!(symbol(&[55, 59, 62, 65]), "G7");
+        assert_eq!(symbol(&[59, 62, 65]), "Bdim");
+        assert_eq!(symbol(&[62, 67, 69]), "Dsus4");
+        assert_eq!(detect_chord(&[0.0; 12], None), None);
+
+        let c: Key = "C".parse().unwrap();
+        let a_minor: Key = "Am".parse().unwrap();
+        let chord = |root, quality| DetectedChord {
+            root,
+            quality,
+            bass: None,
+        };
+        assert_eq!(chord(2, "m7").numeral(c), "ii7");
+        assert_eq!(chord(10, "").numeral(c), "bVII");
+        assert_eq!(chord(11, "m7b5").numeral(c), "viiø7");
+        assert_eq!(chord(6, "dim").numeral(c), "#iv°");
+        assert_eq!(chord(0, "").numeral(a_minor), "III");
+        assert_eq!(chord(4, "7").numeral(a_minor), "V7");
+        assert_eq!(chord(8, "dim7").numeral(a_minor), "vii°7");
+    }
+
+    #[test]
+    fn test_added_sixths_are_not_read_as_inversions() {
+        let c: Key = "C".parse().unwrap();
+        let chord = |root, quality| DetectedChord {
+            root,
+            quality,
+            bass: None,
+        };
+        assert_eq!(chord(0, "6").numeral(c), "Iadd6");
+        assert_eq!(chord(9, "m6").numeral(c), "viadd6");
+        let again = parse_progression("Iadd6 viadd6", c).unwrap();
+        assert_eq!(again[0].intervals, [0, 4, 7, 9]);
+        assert_eq!(
+            (again[1].root % 12, &again[1].intervals[..]),
+            (9, &[0, 3, 7, 9][..])
+        );
+    }
+
+    #[test]
+    fn test_analyze_a_generated_file() {
+        let key: Key = "G".parse().unwrap();
+        let chords = parse_progression("| G . D/F# . | Em7 | C . D . | G |", key).unwrap();
+        let smf = build_chord_smf(&chords, &SmfSettings::default()).unwrap();
+
+        let bars = analyze(&smf, Resolution::Bar, None).unwrap();
+        assert_eq!(bars.key, key);
+        assert!(bars.key_detected);
+        assert_eq!(bars.chords.len(), 4);
+        assert_eq!(bars.chords[1].unwrap().symbol(), "Em7");
+
+        let beats = analyze(&smf, Resolution::Beat, None).unwrap();
+        assert_eq!(
+            beats.chart(),
+            "| G . D/F# . | Em7 . . . | C . D . | G . . . |"
+        );
+        assert_eq!(
+            beats.numeral_chart(),
+            "| I . V . | vi7 . . . | IV . V . | I . . . |"
+        );
+        // The chart reads back as the progression it came from.
+        let again = parse_progression(&beats.chart(), key).unwrap();
+        assert_eq!(again.len(), chords.len());
+
+        // Notes ringing a few ticks into a fifth bar do not add one.
+        let mut spilled = crate::timeline::Timeline::new();
+        spilled.note(0, 1930, 0.into(), 60.into(), 90.into());
+        spilled.note(0, 1930, 0.into(), 64.into(), 90.into());
+        let smf = Smf {
+            header: smf.header,
+            tracks: vec![spilled.to_track().unwrap()],
+        };
+        let bars = analyze(&smf, Resolution::Bar, None).unwrap();
+        assert_eq!(bars.chords.len(), 1);
+
+        let empty = Smf::new(midly::Header::new(
+            midly::Format::SingleTrack,
+            Timing::Metrical(480.into()),
+        ));
+        assert!(matches!(
+            analyze(&empty, Resolution::Bar, None),
+            Err(Error::InvalidMidi { .. })
+        ));
+    }
+
+    #[test]
+    fn test_long_deltas_are_rejected_not_allocated() {
+        // One note held through a chain of the longest deltas MIDI allows.
+        let channel = 0.into();
+        let mut track = vec![TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key: 60.into(),
+                    vel: 90.into(),
+                },
+            },
+        }];
+        for _ in 0..1000 {
+            track.push(TrackEvent {
+                delta: u28::max_value(),
+                kind: TrackEventKind::Meta(MetaMessage::Text(b"")),
+            });
+        }
+        track.push(TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff {
+                    key: 60.into(),
+                    vel: 0.into(),
+                },
+            },
+        });
+        let smf = Smf {
+            header: midly::Header::new(midly::Format::SingleTrack, Timing::Metrical(480.into())),
+            tracks: vec![track],
+        };
+        for resolution in [Resolution::Beat, Resolution::Bar] {
+            assert!(matches!(
+                analyze(&smf, resolution, None),
+                Err(Error::InvalidMidi { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_silent_windows_hold_a_chord() {
+        let key: Key = "C".parse().unwrap();
+        let chord = |root, quality| {
+            Some(DetectedChord {
+                root,
+                quality,
+                bass: None,
+            })
+        };
+        let analysis = Analysis {
+            key,
+            key_detected: false,
+            resolution: Resolution::Beat,
+            beats_per_bar: 4,
+            chords: vec![
+                None,
+                chord(0, ""),
+                None,
+                chord(7, ""),
+                None,
+                None,
+                chord(9, "m"),
+            ],
+        };
+        assert_eq!(analysis.chart(), "| C . . G | G . Am |");
+        assert_eq!(analysis.numeral_chart(), "| I . . V | V . vi |");
+        assert!(parse_progression(&analysis.chart(), key).is_ok());
+    }
+}
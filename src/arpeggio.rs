@@ -0,0 +1,314 @@
+//! Arpeggiated chord tracks, played in place of strums.
+
+use std::str::FromStr;
+
+use midly::num::u4;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::chord::Chord;
+use crate::error::Result;
+use crate::events::{tick_u28, value_u7};
+use crate::harmonic_rhythm::{chord_at, chord_spans, measure_count};
+use crate::tempo::Meter;
+use crate::timeline::Timeline;
+
+// ---------------------------------------------------------------------
+// Arpeggiator: chord notes one at a time instead of strummed together
+// ---------------------------------------------------------------------
+
+/// Order in which the chord notes are played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpMode {
+    /// Lowest note to highest.
+    Up,
+    /// Highest note to lowest.
+    Down,
+    /// Up then back down, without repeating the top and bottom notes.
+    UpDown,
+    /// A random note on every step, reproducible from the seed.
+    Random,
+    /// The order the chord lists its notes: slash bass, root, then each interval.
+    AsPlayed,
+}
+
+impl FromStr for ArpMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "up" => ArpMode::Up,
+            "down" => ArpMode::Down,
+            "up-down" | "updown" => ArpMode::UpDown,
+            "random" => ArpMode::Random,
+            "as-played" | "played" => ArpMode::AsPlayed,
+            other => return Err(format!("unknown arpeggio mode {other:?}")),
+        })
+    }
+}
+
+/// Note value of each arpeggio step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpRate {
+    /// Eighth notes.
+    Eighth,
+    /// Sixteenth notes.
+    Sixteenth,
+    /// Eighth-note triplets.
+    EighthTriplet,
+    /// Sixteenth-note triplets.
+    SixteenthTriplet,
+}
+
+impl ArpRate {
+    /// Steps per quarter note.
+    pub fn steps_per_quarter(self) -> u32 {
+        match self {
+            ArpRate::Eighth => 2,
+            ArpRate::EighthTriplet => 3,
+            ArpRate::Sixteenth => 4,
+            ArpRate::SixteenthTriplet => 6,
+        }
+    }
+}
+
+impl FromStr for ArpRate {
+    type Err = String;
+
+    /// "1/8", "1/16", "1/8t" or "1/16t".
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "1/8" | "8" => ArpRate::Eighth,
+            "1/16" | "16" => ArpRate::Sixteenth,
+            "1/8t" | "8t" => ArpRate::EighthTriplet,
+            "1/16t" | "16t" => ArpRate::SixteenthTriplet,
+            other => return Err(format!("unknown arpeggio rate {other:?}")),
+        })
+    }
+}
+
+/// Arpeggiator settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arpeggio {
+    /// Order the chord notes are played in.
+    pub mode: ArpMode,
+    /// Length of each step.
+    pub rate: ArpRate,
+    /// Fraction of each step the note sounds for, 0.0..=1.0.
+    pub gate: f32,
+    /// Octaves the pattern climbs through; 1 plays the chord as voiced.
+    pub octaves: u8,
+    /// Keep the pattern's position running across chord changes instead of
+    /// restarting it on every new chord.
+    pub latch: bool,
+    /// Seed for `ArpMode::Random`.
+    pub seed: u64,
+}
+
+impl Default for Arpeggio {
+    fn default() -> Self {
+        Arpeggio {
+            mode: ArpMode::Up,
+            rate: ArpRate::Sixteenth,
+            gate: 0.8,
+            octaves: 1,
+            latch: false,
+            seed: 0,
+        }
+    }
+}
+
+impl Arpeggio {
+    /// The notes one cycle of the pattern walks through, for `notes` as the
+    /// chord lists them. Random mode picks from this pool.
+    pub fn sequence(&self, notes: &[u8]) -> Vec<i32> {
+        let mut pool: Vec<i32> = Vec::with_capacity(notes.len() * usize::from(self.octaves));
+        for octave in 0..i32::from(self.octaves.max(1)) {
+            pool.extend(notes.iter().map(|&n| i32::from(n) + octave * 12));
+        }
+
+        match self.mode {
+            ArpMode::AsPlayed | ArpMode::Random => pool,
+            ArpMode::Up => {
+                pool.sort_unstable();
+                pool
+            }
+            ArpMode::Down => {
+                pool.sort_unstable_by(|a, b| b.cmp(a));
+                pool
+            }
+            ArpMode::UpDown => {
+                pool.sort_unstable();
+                let descent: Vec<i32> = pool
+                    .iter()
+                    .rev()
+                    .skip(1)
+                    .take(pool.len().saturating_sub(2))
+                    .copied()
+                    .collect();
+                pool.extend(descent);
+                pool
+            }
+        }
+    }
+}
+
+/// Arpeggiate `chords`, each lasting its own [`Chord::measures`] of `meter`,
+/// in place of the strum generator. Steps run on a steady grid and play
+/// whichever chord is sounding; the arpeggio's rate sets the step length.
+pub fn arpeggio_timeline(
+    chords: &[Chord],
+    arp: &Arpeggio,
+    start_tick: u32,
+    meter: Meter,
+    channel: u4,
+    velocity: u8,
+) -> Result<Timeline<'static>> {
+    let velocity = value_u7(i32::from(velocity))?;
+    let ticks_per_measure = meter.ticks_per_measure();
+
+    let steps_per_quarter = f64::from(arp.rate.steps_per_quarter());
+    let step_ticks = f64::from(meter.ppq) / steps_per_quarter;
+    let steps_per_measure = (f64::from(ticks_per_measure) / step_ticks).round().max(1.0) as u64;
+    let gate_ticks = (step_ticks * f64::from(arp.gate.clamp(0.0, 1.0)))
+        .round()
+        .max(1.0) as u64;
+
+    let sequences = chords
+        .iter()
+        .map(|chord| Ok(arp.sequence(&chord.notes()?)))
+        .collect::<Result<Vec<_>>>()?;
+    let spans = chord_spans(chords, start_tick, meter);
+
+    let mut rng = StdRng::seed_from_u64(arp.seed);
+    let mut timeline = Timeline::new();
+    let mut position = 0usize;
+    let mut current = None;
+    for measure in 0..measure_count(chords) as u64 {
+        let measure_start = u64::from(start_tick) + measure * u64::from(ticks_per_measure);
+        for step in 0..steps_per_measure {
+            let offset = (step as f64 * step_ticks).round() as u64;
+            let Some(index) = chord_at(&spans, measure_start + offset) else {
+                continue;
+            };
+            if current != Some(index) {
+                current = Some(index);
+                if !arp.latch {
+                    position = 0;
+                }
+            }
+            let sequence = &sequences[index];
+            let note = match arp.mode {
+                ArpMode::Random => sequence[rng.gen_range(0..sequence.len())],
+                _ => sequence[position % sequence.len()],
+            };
+            position += 1;
+            let tick = measure_start + offset;
+            let gate = gate_ticks.min(spans[index].end - tick) as u32;
+            timeline.note(
+                tick_u28(tick)?.as_int(),
+                gate,
+                channel,
+                value_u7(note)?,
+                velocity,
+            );
+        }
+    }
+    Ok(timeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{MidiMessage, TrackEventKind};
+
+    fn ons(timeline: &Timeline) -> Vec<(u32, u8)> {
+        let mut ons: Vec<(u32, u8)> = timeline
+            .iter()
+            .filter_map(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, .. },
+                    ..
+                } => Some((ev.tick, key.as_int())),
+                _ => None,
+            })
+            .collect();
+        ons.sort_unstable();
+        ons
+    }
+
+    fn arpeggiate(chords: &[Chord], arp: &Arpeggio) -> Vec<(u32, u8)> {
+        ons(&arpeggio_timeline(chords, arp, 0, Meter::default(), u4::from(0), 90).unwrap())
+    }
+
+    #[test]
+    fn test_sequences_per_mode() {
+        let notes = [64, 60, 67];
+        let arp = |mode| Arpeggio {
+            mode,
+            ..Arpeggio::default()
+        };
+        assert_eq!(arp(ArpMode::Up).sequence(&notes), vec![60, 64, 67]);
+        assert_eq!(arp(ArpMode::Down).sequence(&notes), vec![67, 64, 60]);
+        assert_eq!(arp(ArpMode::AsPlayed).sequence(&notes), vec![64, 60, 67]);
+        let two_octaves = Arpeggio {
+            mode: ArpMode::UpDown,
+            octaves: 2,
+            ..Arpeggio::default()
+        };
+        assert_eq!(
+            two_octaves.sequence(&notes),
+            vec![60, 64, 67, 72, 76, 79, 76, 72, 67, 64]
+        );
+    }
+
+    #[test]
+    fn test_rate_gate_and_latch() {
+        let chords = vec![Chord::new(60, vec![0, 4, 7]), Chord::new(65, vec![0, 4, 9])];
+        let eighths = Arpeggio {
+            rate: ArpRate::Eighth,
+            gate: 0.5,
+            ..Arpeggio::default()
+        };
+        let notes = arpeggiate(&chords, &eighths);
+        assert_eq!(notes.len(), 16);
+        assert_eq!(notes[..4], [(0, 60), (240, 64), (480, 67), (720, 60)]);
+        // Each chord restarts from its lowest note.
+        assert_eq!(notes[8], (1920, 65));
+        let timeline =
+            arpeggio_timeline(&chords, &eighths, 0, Meter::default(), u4::from(0), 90).unwrap();
+        assert_eq!(timeline.end_tick(), 3600 + 120);
+
+        let latched = Arpeggio {
+            latch: true,
+            ..eighths
+        };
+        // Eight steps into a three-note pattern, the second chord picks up on its third note.
+        assert_eq!(arpeggiate(&chords, &latched)[8], (1920, 74));
+
+        let triplets = Arpeggio {
+            rate: ArpRate::EighthTriplet,
+            ..Arpeggio::default()
+        };
+        let notes = arpeggiate(&chords[..1], &triplets);
+        assert_eq!(notes.len(), 12);
+        assert_eq!(notes[1].0, 160);
+    }
+
+    #[test]
+    fn test_random_mode_is_seeded() {
+        let chords = vec![Chord::new(60, vec![0, 4, 7, 11]); 2];
+        let random = |seed| Arpeggio {
+            mode: ArpMode::Random,
+            seed,
+            ..Arpeggio::default()
+        };
+        let first = arpeggiate(&chords, &random(7));
+        assert_eq!(first, arpeggiate(&chords, &random(7)));
+        assert_ne!(first, arpeggiate(&chords, &random(8)));
+        assert!(first.iter().all(|&(_, n)| [60, 64, 67, 71].contains(&n)));
+
+        assert!("1/16t".parse::<ArpRate>().is_ok());
+        assert!("1/32".parse::<ArpRate>().is_err());
+    }
+}
@@ -0,0 +1,408 @@
+//! Bass lines that follow the chords.
+
+use std::str::FromStr;
+
+use midly::num::{u4, u7};
+
+use crate::chord::Chord;
+use crate::chord_symbol::midi_note_name;
+use crate::error::{Error, Result};
+use crate::events::{tick_u28, value_u7};
+use crate::harmonic_rhythm::chord_spans;
+use crate::tempo::Meter;
+use crate::timeline::Timeline;
+use crate::voicing::Register;
+
+// ---------------------------------------------------------------------
+// Bass line generation
+// ---------------------------------------------------------------------
+
+/// How the bass plays through each chord.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BassStyle {
+    /// Root on the downbeat, fifth halfway through the measure.
+    RootFifth,
+    /// One note per beat through the chord tones, with a chromatic approach
+    /// into the next chord's root on the last beat.
+    Walking,
+    /// Eighth notes alternating between the root and its upper octave, as
+    /// many as the measure holds.
+    OctavePump,
+    /// Reggae one-drop: the downbeat is left empty and the root lands with
+    /// the drop halfway through the measure.
+    OneDrop,
+}
+
+impl FromStr for BassStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "root-fifth" | "rootfifth" | "root5" => BassStyle::RootFifth,
+            "walking" | "walk" => BassStyle::Walking,
+            "octave-pump" | "octave" | "pump" => BassStyle::OctavePump,
+            "one-drop" | "onedrop" | "reggae" => BassStyle::OneDrop,
+            other => return Err(format!("unknown bass style {other:?}")),
+        })
+    }
+}
+
+/// A bass part: style, range, channel and velocity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BassLine {
+    /// Rhythm and note choice of the line.
+    pub style: BassStyle,
+    /// Every bass note is placed inside this range.
+    pub register: Register,
+    /// Zero-based MIDI channel.
+    pub channel: u8,
+    /// Note-on velocity before any accents.
+    pub velocity: u8,
+}
+
+impl Default for BassLine {
+    fn default() -> Self {
+        BassLine {
+            style: BassStyle::RootFifth,
+            register: Register::BASS,
+            channel: 1,
+            velocity: 80,
+        }
+    }
+}
+
+/// The note of `pitch_class` inside `register` closest to `near`, or the lowest
+/// one when there is nothing to be near. The register spans at least an octave
+/// (see [`bass_track_timeline`]), so it holds every pitch class.
+fn place(pitch_class: i32, register: Register, near: Option<i32>) -> i32 {
+    let low = i32::from(register.low);
+    let high = i32::from(register.high);
+    let lowest = low + (pitch_class - low).rem_euclid(12);
+    let choices: Vec<i32> = (lowest..=high).step_by(12).collect();
+    match near {
+        Some(near) => *choices
+            .iter()
+            .min_by_key(|&&note| (note.abs_diff(near), note))
+            .expect("choices are not empty"),
+        None => choices[0],
+    }
+}
+
+/// Pitch class the bass treats as the chord's root: the slash bass if any.
+fn bass_pitch_class(chord: &Chord) -> i32 {
+    i32::from(chord.bass.unwrap_or(chord.root) % 12)
+}
+
+/// Chord tones above the bass note as pitch classes, in interval order.
+fn upper_tones(chord: &Chord) -> Vec<i32> {
+    let bass = bass_pitch_class(chord);
+    let root = i32::from(chord.root);
+    let mut tones: Vec<i32> = Vec::new();
+    for &interval in &chord.intervals {
+        let pc = (root + i32::from(interval)).rem_euclid(12);
+        if pc != bass && !tones.contains(&pc) {
+            tones.push(pc);
+        }
+    }
+    tones
+}
+
+/// Pitch class of the chord's fifth (diminished or augmented if that is what
+/// the chord has), relative to its root.
+fn fifth_pitch_class(chord: &Chord) -> i32 {
+    let fifth = [7, 6, 8]
+        .into_iter()
+        .find(|&f| chord.intervals.iter().any(|&i| i32::from(i % 12) == f))
+        .unwrap_or(7);
+    (i32::from(chord.root) + fifth).rem_euclid(12)
+}
+
+/// Notes of one chord lasting `length` ticks of `meter`, as
+/// (offset, length, note, velocity scale). A walking line continues from
+/// `previous`, the last note of the chord before.
+fn chord_figure(
+    chord: &Chord,
+    next: &Chord,
+    style: BassStyle,
+    register: Register,
+    meter: Meter,
+    length: u32,
+    previous: Option<i32>,
+) -> Vec<(u32, u32, i32, f32)> {
+    let near = if style == BassStyle::Walking {
+        previous
+    } else {
+        None
+    };
+    let root = place(bass_pitch_class(chord), register, near);
+    let fifth = place(fifth_pitch_class(chord), register, Some(root + 7));
+    let at = |fraction: f64| (f64::from(length) * fraction).round() as u32;
+    let beats = (f64::from(length) / f64::from(meter.beat_ticks().max(1)))
+        .round()
+        .max(1.0) as u32;
+    let beat = length / beats;
+
+    match style {
+        BassStyle::RootFifth => {
+            if beats < 2 {
+                return vec![(0, length, root, 1.0)];
+            }
+            let half = beats / 2 * beat;
+            vec![(0, half, root, 1.0), (half, length - half, fifth, 0.9)]
+        }
+        BassStyle::Walking => {
+            let tones = upper_tones(chord);
+            let mut notes = vec![(0, beat, root, 1.0)];
+            let mut previous = root;
+            for index in 1..beats {
+                let offset = index * beat;
+                let note = if index == beats - 1 {
+                    // Approach the next root by a half step from the side we are on.
+                    let target = place(bass_pitch_class(next), register, Some(previous));
+                    let below = target - 1;
+                    let above = target + 1;
+                    let from_below = previous < target || !register.contains(above);
+                    if from_below && register.contains(below) {
+                        below
+                    } else {
+                        above
+                    }
+                } else {
+                    let pc = tones
+                        .get((index as usize - 1) % tones.len().max(1))
+                        .copied()
+                        .unwrap_or(bass_pitch_class(chord));
+                    place(pc, register, Some(previous))
+                };
+                notes.push((offset, length - offset, note, 0.9));
+                previous = note;
+            }
+            // Each beat lasts until the next one.
+            for index in 0..notes.len().saturating_sub(1) {
+                notes[index].1 = notes[index + 1].0 - notes[index].0;
+            }
+            notes
+        }
+        BassStyle::OctavePump => {
+            let octave = if register.contains(root + 12) {
+                root + 12
+            } else {
+                root
+            };
+            let eighth = (u32::from(meter.ppq) / 2).max(1);
+            (0..(length / eighth).max(1))
+                .map(|index| {
+                    let (note, scale) = if index % 2 == 0 {
+                        (root, 1.0)
+                    } else {
+                        (octave, 0.85)
+                    };
+                    (index * eighth, eighth * 3 / 4, note, scale)
+                })
+                .collect()
+        }
+        BassStyle::OneDrop => {
+            let octave = if register.contains(root + 12) {
+                root + 12
+            } else {
+                root
+            };
+            vec![
+                (at(0.125), at(0.25), root, 0.9),
+                (at(0.5), at(0.125), root, 1.0),
+                (at(0.625), at(0.125), fifth, 0.85),
+                (at(0.75), at(0.25), octave, 0.9),
+            ]
+        }
+    }
+}
+
+/// Bass notes for `chords`, each lasting its own [`Chord::measures`] of
+/// `meter` from `start_tick`, on `channel`. The beats of `meter` set the
+/// walking pulse.
+///
+/// The register must span at least an octave, so that every chord's root
+/// fits in it.
+pub fn bass_track_timeline(
+    chords: &[Chord],
+    bass: &BassLine,
+    start_tick: u32,
+    meter: Meter,
+    channel: u4,
+) -> Result<Timeline<'static>> {
+    value_u7(i32::from(bass.velocity))?;
+    let Register { low, high } = bass.register;
+    if high < low.saturating_add(11) {
+        return Err(Error::InvalidRegister {
+            reason: format!(
+                "the bass register {}-{} spans less than an octave",
+                midi_note_name(low),
+                midi_note_name(high)
+            ),
+        });
+    }
+    let ticks_per_measure = meter.ticks_per_measure();
+
+    let mut timeline = Timeline::new();
+    let mut previous = None;
+    let spans = chord_spans(chords, start_tick, meter);
+    for (index, (chord, span)) in chords.iter().zip(&spans).enumerate() {
+        chord.notes()?;
+        let next = chords.get(index + 1).unwrap_or(chord);
+        // The figure fits the chord's written length; a push on the next chord
+        // cuts it short, and a push on this one moves its first note early.
+        let length = (chord.measures * f64::from(ticks_per_measure)).round() as u32;
+        let notes = chord_figure(
+            chord,
+            next,
+            bass.style,
+            bass.register,
+            meter,
+            length,
+            previous,
+        );
+        previous = notes.last().map(|&(_, _, note, _)| note);
+        for (position, (offset, length, note, scale)) in notes.into_iter().enumerate() {
+            let mut tick = span.downbeat + u64::from(offset);
+            if tick >= span.end {
+                continue;
+            }
+            let mut length = u64::from(length).min(span.end - tick);
+            if position == 0 && span.start < span.downbeat {
+                length += tick - span.start;
+                tick = span.start;
+            }
+            let velocity = (f32::from(bass.velocity) * scale).round().clamp(1.0, 127.0) as i32;
+            timeline.note(
+                tick_u28(tick)?.as_int(),
+                length.max(1) as u32,
+                channel,
+                value_u7(note)?,
+                u7::from(velocity as u8),
+            );
+        }
+    }
+    Ok(timeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progression::parse_progression;
+    use midly::{MidiMessage, TrackEventKind};
+
+    fn note_ons(timeline: &Timeline) -> Vec<(u32, u8)> {
+        let mut ons: Vec<(u32, u8)> = timeline
+            .iter()
+            .filter_map(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, .. },
+                    ..
+                } => Some((ev.tick, key.as_int())),
+                _ => None,
+            })
+            .collect();
+        ons.sort_unstable();
+        ons
+    }
+
+    fn bass_notes(progression: &str, style: BassStyle) -> Vec<(u32, u8)> {
+        let chords = parse_progression(progression, "C".parse().unwrap()).unwrap();
+        let bass = BassLine {
+            style,
+            ..BassLine::default()
+        };
+        let timeline =
+            bass_track_timeline(&chords, &bass, 0, Meter::default(), u4::from(1)).unwrap();
+        note_ons(&timeline)
+    }
+
+    #[test]
+    fn test_root_fifth_and_slash_bass() {
+        assert_eq!(
+            bass_notes("C G/B", BassStyle::RootFifth),
+            vec![(0, 36), (960, 43), (1920, 35), (2880, 38)]
+        );
+    }
+
+    #[test]
+    fn test_short_and_pushed_chords() {
+        // Three beats of C (root, then the fifth until G is pushed in early).
+        assert_eq!(
+            bass_notes("| C . . <G |", BassStyle::RootFifth),
+            vec![(0, 36), (480, 43), (1200, 31)]
+        );
+    }
+
+    #[test]
+    fn test_walking_bass_approaches_next_root() {
+        let notes = bass_notes("C F", BassStyle::Walking);
+        let ticks: Vec<u32> = notes.iter().map(|n| n.0).collect();
+        assert_eq!(ticks, vec![0, 480, 960, 1440, 1920, 2400, 2880, 3360]);
+        // C E G, then F# a half step above the F that follows.
+        assert_eq!(
+            notes[..5],
+            [(0, 36), (480, 40), (960, 43), (1440, 42), (1920, 41)]
+        );
+    }
+
+    #[test]
+    fn test_pump_and_one_drop_stay_in_register() {
+        let pump = bass_notes("Am", BassStyle::OctavePump);
+        assert_eq!(pump.len(), 8);
+        assert_eq!(pump[..2], [(0, 33), (240, 45)]);
+
+        let drop = bass_notes("Am", BassStyle::OneDrop);
+        assert!(drop.iter().all(|&(tick, _)| tick > 0));
+        assert!(drop.iter().any(|&(tick, _)| tick == 960));
+
+        let register = Register::BASS;
+        for style in [
+            BassStyle::Walking,
+            BassStyle::OctavePump,
+            BassStyle::OneDrop,
+        ] {
+            let notes = bass_notes("E B7 C#m A G#7", style);
+            assert!(
+                notes.iter().all(|&(_, n)| register.contains(i32::from(n))),
+                "{style:?}: {notes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pump_plays_eighth_notes_in_any_meter() {
+        let chords = parse_progression("Am", "C".parse().unwrap()).unwrap();
+        let bass = BassLine {
+            style: BassStyle::OctavePump,
+            ..BassLine::default()
+        };
+        for (time_signature, eighths) in [((6, 8), 6), ((3, 4), 6), ((5, 4), 10)] {
+            let meter = Meter::new(480, time_signature);
+            let timeline = bass_track_timeline(&chords, &bass, 0, meter, u4::from(1)).unwrap();
+            let ticks: Vec<u32> = note_ons(&timeline).iter().map(|n| n.0).collect();
+            assert_eq!(ticks, (0..eighths).map(|i| i * 240).collect::<Vec<u32>>());
+        }
+    }
+
+    #[test]
+    fn test_register_must_hold_an_octave() {
+        let chords = parse_progression("C G", "C".parse().unwrap()).unwrap();
+        let narrow = BassLine {
+            register: "E2-C3".parse().unwrap(),
+            ..BassLine::default()
+        };
+        assert!(matches!(
+            bass_track_timeline(&chords, &narrow, 0, Meter::default(), u4::from(1)),
+            Err(Error::InvalidRegister { .. })
+        ));
+        let octave = BassLine {
+            register: "C1-B1".parse().unwrap(),
+            ..BassLine::default()
+        };
+        let notes = note_ons(
+            &bass_track_timeline(&chords, &octave, 0, Meter::default(), u4::from(1)).unwrap(),
+        );
+        assert_eq!(notes, vec![(0, 24), (960, 31), (1920, 31), (2880, 26)]);
+    }
+}
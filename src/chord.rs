@@ -0,0 +1,81 @@
+//! The chord model: root, intervals, slash bass, duration and push.
+
+use crate::error::{Error, Result};
+use crate::events::value_u7;
+
+// ---------------------------------------------------------------------
+// Chord data structure
+// ---------------------------------------------------------------------
+
+/// A chord as MIDI note numbers: a root plus intervals above it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chord {
+    /// MIDI note number of the root.
+    pub root: u8,
+    /// Semitones above `root`, usually starting with 0 for the root itself;
+    /// voiced chords (see [`crate::voicing`]) may start on another chord tone.
+    pub intervals: Vec<u8>,
+    /// Slash-chord bass note, sounded below the chord (e.g. the F# in "D/F#").
+    pub bass: Option<u8>,
+    /// How long the chord lasts, in measures: 1.0 is a bar, 0.5 half a bar.
+    pub measures: f64,
+    /// Arrive early, taking [`Meter::push_ticks`] from the end of the chord
+    /// before.
+    ///
+    /// [`Meter::push_ticks`]: crate::tempo::Meter::push_ticks
+    pub push: bool,
+}
+
+impl Chord {
+    /// A one-bar chord without a slash bass.
+    pub fn new(root: u8, intervals: Vec<u8>) -> Self {
+        Chord {
+            root,
+            intervals,
+            bass: None,
+            measures: 1.0,
+            push: false,
+        }
+    }
+
+    /// All MIDI notes of the chord, bass first, then root plus each interval.
+    ///
+    /// Fails if the chord has no intervals or any note falls outside 0..=127.
+    pub fn notes(&self) -> Result<Vec<u8>> {
+        if self.intervals.is_empty() {
+            return Err(Error::InvalidChord {
+                reason: format!("chord rooted at {} has no intervals", self.root),
+            });
+        }
+        self.bass
+            .map(i32::from)
+            .into_iter()
+            .chain(
+                self.intervals
+                    .iter()
+                    .map(|&interval| i32::from(self.root) + i32::from(interval)),
+            )
+            .map(|note| value_u7(note).map(u8::from))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notes_are_checked() {
+        let mut chord = Chord::new(60, vec![0, 4, 7]);
+        chord.bass = Some(54);
+        assert_eq!(chord.notes().unwrap(), vec![54, 60, 64, 67]);
+        assert!(matches!(
+            Chord::new(122, vec![0, 4, 7]).notes(),
+            Err(Error::NoteOutOfRange { value: 129 })
+        ));
+        assert!(matches!(
+            Chord::new(60, vec![]).notes(),
+            Err(Error::InvalidChord { .. })
+        ));
+    }
+}
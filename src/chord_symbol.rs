@@ -0,0 +1,451 @@
+//! Chord symbols such as "Am7" or "D/F#", parsed and named.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chord::Chord;
+
+// ---------------------------------------------------------------------
+// Chord symbol parsing: "C", "Am7", "F#m7b5", "Bbmaj9#11", "D/F#", ...
+// ---------------------------------------------------------------------
+
+/// Octave the parsed root is placed in (C4 = MIDI 60).
+const ROOT_OCTAVE_BASE: u8 = 60;
+
+/// Display names of the twelve pitch classes, starting at C.
+pub const PITCH_CLASS_NAMES: [&str; 12] = [
+    "C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+
+/// Why a chord symbol could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseChordError {
+    /// The symbol or token as given.
+    pub symbol: String,
+    /// What is wrong with it.
+    pub reason: String,
+}
+
+impl ParseChordError {
+    pub(crate) fn new(symbol: &str, reason: impl Into<String>) -> Self {
+        ParseChordError {
+            symbol: symbol.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseChordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot parse chord symbol {:?}: {}",
+            self.symbol, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ParseChordError {}
+
+impl FromStr for Chord {
+    type Err = ParseChordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_chord(s)
+    }
+}
+
+/// Parse a note name such as "C", "F#", "Bb" or "Ebb" into a pitch class (0..12).
+///
+/// Returns the pitch class and the number of bytes consumed.
+pub fn parse_note_name(text: &str) -> Option<(u8, usize)> {
+    let mut chars = text.char_indices();
+    let (_, letter) = chars.next()?;
+    let natural: i32 = match letter.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    // Only an upper-case letter names a note; "b" alone is a flat sign.
+    if !letter.is_ascii_uppercase() {
+        return None;
+    }
+
+    let mut offset = 0i32;
+    let mut consumed = letter.len_utf8();
+    for (idx, c) in chars {
+        match c {
+            '#' | '♯' => offset += 1,
+            'b' | '♭' => offset -= 1,
+            _ => break,
+        }
+        consumed = idx + c.len_utf8();
+    }
+
+    Some(((natural + offset).rem_euclid(12) as u8, consumed))
+}
+
+/// Name a MIDI note in scientific pitch notation, e.g. 60 -> "C4", 70 -> "Bb4".
+pub fn midi_note_name(note: u8) -> String {
+    let octave = i32::from(note / 12) - 1;
+    format!("{}{octave}", PITCH_CLASS_NAMES[usize::from(note % 12)])
+}
+
+/// Parse a chord symbol into a `Chord` rooted in the octave starting at middle C.
+///
+/// Supports triads (`C`, `Cm`, `Cdim`, `Caug`, `C5`), sixths and sevenths
+/// (`C6`, `C7`, `Cmaj7`, `Cm7`, `CmMaj7`, `Cm7b5`, `Cdim7`), extensions
+/// (`C9`, `C11`, `C13`, `C6/9`), alterations (`C7b9`, `C7#5`, `Cmaj9#11`,
+/// `C7(b9,#11)`, `Cm7-b5`), suspensions (`Csus2`, `C7sus4`), add-chords (`Cadd9`) and
+/// slash bass notes (`D/F#`).
+pub fn parse_chord(symbol: &str) -> Result<Chord, ParseChordError> {
+    let trimmed = symbol.trim();
+    if trimmed.is_empty() {
+        return Err(ParseChordError::new(symbol, "empty symbol"));
+    }
+
+    let (root_pc, consumed) = parse_note_name(trimmed)
+        .ok_or_else(|| ParseChordError::new(symbol, "expected a root note A-G"))?;
+    let rest = &trimmed[consumed..];
+
+    // A trailing "/X" is a slash bass when X is a note name ("6/9" is not).
+    let (quality, bass_pc) = match rest.rfind('/') {
+        Some(slash) => match parse_note_name(&rest[slash + 1..]) {
+            Some((pc, len)) if slash + 1 + len == rest.len() => (&rest[..slash], Some(pc)),
+            Some(_) => {
+                return Err(ParseChordError::new(
+                    symbol,
+                    "unexpected text after bass note",
+                ))
+            }
+            None => (rest, None),
+        },
+        None => (rest, None),
+    };
+
+    let intervals =
+        parse_quality(quality).map_err(|reason| ParseChordError::new(symbol, reason))?;
+
+    Ok(chord_from_pitch_classes(root_pc, intervals, bass_pc))
+}
+
+/// Build a `Chord` from pitch classes, rooting it in the octave starting at middle C.
+/// The bass sits below the chord, within the octave under the root.
+pub(crate) fn chord_from_pitch_classes(
+    root_pc: u8,
+    intervals: Vec<u8>,
+    bass_pc: Option<u8>,
+) -> Chord {
+    let root = ROOT_OCTAVE_BASE + root_pc;
+    let bass = bass_pc.map(|pc| {
+        let below = (root_pc + 12 - pc) % 12;
+        root - if below == 0 { 12 } else { below }
+    });
+    Chord {
+        bass,
+        ..Chord::new(root, intervals)
+    }
+}
+
+/// Chord tones being assembled while scanning the quality suffix.
+struct Spelling {
+    third: Option<u8>,
+    fifth: Option<u8>,
+    seventh: Option<u8>,
+    extra: Vec<u8>,
+}
+
+impl Spelling {
+    fn intervals(mut self) -> Vec<u8> {
+        let mut out = vec![0];
+        out.extend(self.third);
+        out.extend(self.fifth);
+        out.extend(self.seventh);
+        out.append(&mut self.extra);
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+}
+
+/// Strip `prefix` from `text`, returning the remainder.
+fn eat<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    text.strip_prefix(prefix)
+}
+
+/// Consume the first matching prefix from `options`.
+fn eat_any<'a>(text: &'a str, options: &[&str]) -> Option<&'a str> {
+    options.iter().find_map(|p| text.strip_prefix(p))
+}
+
+/// Read a leading extension number (5, 6, 7, 9, 11, 13).
+fn eat_number(text: &str) -> Option<(u8, &str)> {
+    for (digits, value) in [
+        ("13", 13),
+        ("11", 11),
+        ("9", 9),
+        ("7", 7),
+        ("6", 6),
+        ("5", 5),
+    ] {
+        if let Some(rest) = text.strip_prefix(digits) {
+            return Some((value, rest));
+        }
+    }
+    None
+}
+
+/// Semitone offset of a scale degree used in alterations and add-chords.
+fn degree_interval(degree: u8) -> Option<u8> {
+    Some(match degree {
+        2 => 2,
+        4 => 5,
+        5 => 7,
+        6 => 9,
+        9 => 14,
+        11 => 17,
+        13 => 21,
+        _ => return None,
+    })
+}
+
+/// Parse the quality suffix of a chord symbol ("m7b5", "maj9#11", "") into intervals.
+pub(crate) fn parse_quality(quality: &str) -> Result<Vec<u8>, String> {
+    let mut sp = Spelling {
+        third: Some(4),
+        fifth: Some(7),
+        seventh: None,
+        extra: Vec::new(),
+    };
+    let mut major_seventh = false;
+    let mut q = quality;
+
+    // Base quality.
+    if let Some(rest) = eat_any(q, &["maj", "Maj", "M", "Δ", "^"]) {
+        major_seventh = true;
+        q = rest;
+        if !q.starts_with(|c: char| c.is_ascii_digit()) {
+            // "Cmaj" alone is just a major triad; "CΔ" implies a major seventh.
+            if quality.starts_with('Δ') || quality.starts_with('^') {
+                sp.seventh = Some(11);
+            }
+            major_seventh = false;
+        }
+    } else if let Some(rest) = eat_any(q, &["min", "mi", "m", "-"]) {
+        sp.third = Some(3);
+        q = rest;
+        // Minor-major seventh: "mMaj7", "mM7", "m(maj7)", "m/maj7".
+        if let Some(rest) = eat_any(q, &["Maj", "maj", "M", "(maj", "/maj", "Δ"]) {
+            major_seventh = true;
+            q = rest;
+        }
+    } else if let Some(rest) = eat_any(q, &["dim", "°", "o"]) {
+        sp.third = Some(3);
+        sp.fifth = Some(6);
+        q = rest;
+        if let Some(rest) = eat(q, "7") {
+            sp.seventh = Some(9);
+            q = rest;
+        }
+    } else if let Some(rest) = eat_any(q, &["ø", "Ø"]) {
+        sp.third = Some(3);
+        sp.fifth = Some(6);
+        sp.seventh = Some(10);
+        q = eat(rest, "7").unwrap_or(rest);
+    } else if let Some(rest) = eat_any(q, &["aug", "+"]) {
+        sp.fifth = Some(8);
+        q = rest;
+    }
+
+    // Extension number.
+    if let Some(rest) = eat(q, "6/9").or_else(|| eat(q, "69")) {
+        sp.extra.extend([9, 14]);
+        q = rest;
+    } else if let Some((n, rest)) = eat_number(q) {
+        q = rest;
+        let seventh = if major_seventh { 11 } else { 10 };
+        match n {
+            5 => {
+                sp.third = None;
+            }
+            6 => sp.extra.push(9),
+            7 => sp.seventh = Some(seventh),
+            9 => {
+                sp.seventh = Some(seventh);
+                sp.extra.push(14);
+            }
+            11 => {
+                sp.seventh = Some(seventh);
+                sp.extra.extend([14, 17]);
+            }
+            13 => {
+                sp.seventh = Some(seventh);
+                sp.extra.extend([14, 21]);
+                if sp.third == Some(3) {
+                    sp.extra.push(17);
+                }
+            }
+            _ => unreachable!(),
+        }
+        if major_seventh {
+            // Close a "m(maj7" parenthesis if one was opened.
+            q = eat(q, ")").unwrap_or(q);
+        }
+    } else if major_seventh {
+        return Err("expected 7, 9, 11 or 13 after major-seventh marker".to_string());
+    }
+
+    // Modifiers: suspensions, add-chords, alterations, omissions.
+    while !q.is_empty() {
+        if let Some(rest) = eat_any(q, &["(", ")", ",", " "]) {
+            q = rest;
+        } else if let Some(rest) = eat(q, "-").filter(|rest| rest.starts_with(['b', '♭', '#', '♯']))
+        {
+            // A dash before a written accidental only separates ("m7-b5").
+            q = rest;
+        } else if let Some(rest) = eat(q, "sus") {
+            let (interval, rest) = if let Some(rest) = eat(rest, "2") {
+                (2, rest)
+            } else {
+                (5, eat(rest, "4").unwrap_or(rest))
+            };
+            sp.third = None;
+            sp.extra.push(interval);
+            q = rest;
+        } else if let Some(rest) = eat(q, "add") {
+            let (degree, rest) = eat_degree(rest).ok_or("expected a degree after 'add'")?;
+            let interval = degree_interval(degree).ok_or(format!("cannot add degree {degree}"))?;
+            sp.extra.push(interval);
+            q = rest;
+        } else if let Some(rest) = eat(q, "no") {
+            let (degree, rest) = eat_degree(rest).ok_or("expected a degree after 'no'")?;
+            match degree {
+                3 => sp.third = None,
+                5 => sp.fifth = None,
+                _ => return Err(format!("cannot omit degree {degree}")),
+            }
+            q = rest;
+        } else if let Some((shift, rest)) = eat_any(q, &["b", "♭", "-"])
+            .map(|r| (-1i8, r))
+            .or_else(|| eat_any(q, &["#", "♯", "+"]).map(|r| (1i8, r)))
+        {
+            let (degree, rest) = eat_degree(rest).ok_or("expected a degree after alteration")?;
+            let natural = degree_interval(degree).ok_or(format!("cannot alter degree {degree}"))?;
+            let altered = (natural as i8 + shift) as u8;
+            if degree == 5 {
+                sp.fifth = Some(altered);
+            } else {
+                if degree == 9 || degree == 11 || degree == 13 {
+                    // Altered tensions imply a seventh.
+                    sp.seventh
+                        .get_or_insert(if major_seventh { 11 } else { 10 });
+                }
+                sp.extra.retain(|&i| i != natural);
+                sp.extra.push(altered);
+            }
+            q = rest;
+        } else {
+            return Err(format!("unrecognised quality {q:?}"));
+        }
+    }
+
+    Ok(sp.intervals())
+}
+
+/// Read a scale degree number (2, 3, 4, 5, 6, 9, 11, 13).
+fn eat_degree(text: &str) -> Option<(u8, &str)> {
+    for (digits, value) in [
+        ("13", 13),
+        ("11", 11),
+        ("9", 9),
+        ("6", 6),
+        ("5", 5),
+        ("4", 4),
+        ("3", 3),
+        ("2", 2),
+    ] {
+        if let Some(rest) = text.strip_prefix(digits) {
+            return Some((value, rest));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intervals(symbol: &str) -> Vec<u8> {
+        parse_chord(symbol).unwrap().intervals
+    }
+
+    #[test]
+    fn test_demo_chords_match_literal_triads() {
+        let chords: Vec<Chord> = ["C", "G", "F"]
+            .iter()
+            .map(|symbol| parse_chord(symbol).unwrap())
+            .collect();
+        let roots: Vec<u8> = chords.iter().map(|c| c.root).collect();
+        assert_eq!(roots, vec![60, 67, 65]);
+        assert!(chords.iter().all(|c| c.intervals == vec![0, 4, 7]));
+    }
+
+    #[test]
+    fn test_parse_triads_and_sevenths() {
+        assert_eq!(parse_chord("C").unwrap().root, 60);
+        assert_eq!(intervals("C"), vec![0, 4, 7]);
+        assert_eq!(intervals("Am"), vec![0, 3, 7]);
+        assert_eq!(parse_chord("Am").unwrap().root, 69);
+        assert_eq!(intervals("Bdim"), vec![0, 3, 6]);
+        assert_eq!(intervals("Caug"), vec![0, 4, 8]);
+        assert_eq!(intervals("G7"), vec![0, 4, 7, 10]);
+        assert_eq!(intervals("Am7"), vec![0, 3, 7, 10]);
+        assert_eq!(intervals("Cmaj7"), vec![0, 4, 7, 11]);
+        assert_eq!(intervals("CmMaj7"), vec![0, 3, 7, 11]);
+        assert_eq!(intervals("Bdim7"), vec![0, 3, 6, 9]);
+        assert_eq!(intervals("E5"), vec![0, 7]);
+    }
+
+    #[test]
+    fn test_parse_extensions_alterations_and_suspensions() {
+        let c = parse_chord("F#m7b5").unwrap();
+        assert_eq!(c.root, 66);
+        assert_eq!(c.intervals, vec![0, 3, 6, 10]);
+
+        let c = parse_chord("Bbmaj9#11").unwrap();
+        assert_eq!(c.root, 70);
+        assert_eq!(c.intervals, vec![0, 4, 7, 11, 14, 18]);
+
+        assert_eq!(intervals("G7sus4"), vec![0, 5, 7, 10]);
+        assert_eq!(intervals("Dsus2"), vec![0, 2, 7]);
+        assert_eq!(intervals("Cadd9"), vec![0, 4, 7, 14]);
+        assert_eq!(intervals("C6/9"), vec![0, 4, 7, 9, 14]);
+        assert_eq!(intervals("C7(b9,#11)"), vec![0, 4, 7, 10, 13, 18]);
+        assert_eq!(intervals("C13"), vec![0, 4, 7, 10, 14, 21]);
+    }
+
+    #[test]
+    fn test_parse_slash_bass() {
+        let c = parse_chord("D/F#").unwrap();
+        assert_eq!(c.root, 62);
+        assert_eq!(c.intervals, vec![0, 4, 7]);
+        assert_eq!(c.bass, Some(54));
+        assert_eq!(parse_chord("C/C").unwrap().bass, Some(48));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_chord("").is_err());
+        assert!(parse_chord("H7").is_err());
+        let err = parse_chord("Cxyz").unwrap_err();
+        assert_eq!(err.symbol, "Cxyz");
+        assert!(err.to_string().contains("xyz"));
+        assert!(parse_chord("D/F#q").is_err());
+        assert!("Am7".parse::<Chord>().is_ok());
+    }
+}
@@ -0,0 +1,719 @@
+use mdmidio1p::analysis::Resolution;
+use mdmidio1p::arpeggio::{ArpMode, Arpeggio};
+use mdmidio1p::bass::{BassLine, BassStyle};
+use mdmidio1p::drums::{DrumPart, DrumStyle};
+use mdmidio1p::groove::{Groove, SwingGrid};
+use mdmidio1p::humanize::{Distribution, Humanize};
+use mdmidio1p::melody::Melody;
+use mdmidio1p::pattern::StrumPattern;
+use mdmidio1p::progression::Key;
+use mdmidio1p::smf::SmfSettings;
+use mdmidio1p::strum::StrumSpread;
+use mdmidio1p::voicing::{Voicing, VoicingStyle};
+
+// ---------------------------------------------------------------------
+// Command-line parsing
+// ---------------------------------------------------------------------
+
+pub const USAGE: &str = "\
+usage: mdmidio1p <command> [options]
+
+commands:
+  generate   write the progression to a MIDI file
+  render     print the resolved chords and note events without writing a file
+  inspect    summarise an existing MIDI file: inspect <file.mid>
+  train      learn a chord model from a directory of .txt chord charts:
+             train <dir> [-o chords.model] [--order <n>]
+  analyze    detect the key and chords of a MIDI file and print them as a chart:
+             analyze <file.mid> [--per beat|bar] [-k <key>]
+  help       show this message
+
+options (generate, render):
+  -p, --progression <text>   chords, Roman numerals or Nashville numbers [default: \"I V IV\"]
+                             one per bar, or split bars with '|': \"| C G/B | Am . . <F |\"
+                             ('.' holds the chord, '<' pushes it an eighth early)
+      --random <bars>        generate a progression of this many bars from the seed instead
+      --phrase <bars>        bars per generated phrase, at least 2, each ending on a cadence [default: 4]
+      --model <path>         sample the progression from a trained chord model [default bars: 8]
+      --song <path>          play a song chart: [section] headers with bars=, pattern= and
+                             dynamics= (p, mf, or a hairpin like p<f), their progressions,
+                             and an \"arrangement:\" line
+  -k, --key <key>            key for numerals and the key signature, e.g. C, Am, \"D dorian\" [default: C]
+  -t, --tempo <bpm>          tempo in beats per minute [default: 120]
+      --tempo-change <bar:bpm>  change tempo at the start of a bar (1-based); repeatable
+      --ppq <n>              ticks per quarter note [default: 480]
+      --time-sig <n/d>       time signature [default: 4/4]
+  -c, --channel <1-16>       MIDI channel [default: 1]
+  -v, --velocity <1-127>     note-on velocity [default: 64]
+      --pattern <p>          strum pattern name or notation, e.g. folk, \"D.DU.UDU\" [default: quarters]
+                             (written over a 4/4 bar; other meters repeat or cut it)
+      --spread <n[ms]>       delay between strummed strings, in ticks or with an ms suffix [default: 0]
+      --taper <0-1>          velocity drop across the strings of a strum [default: 0]
+      --arp <mode>           arpeggiate instead of strumming: up, down, up-down, random or as-played
+      --arp-rate <rate>      arpeggio step: 1/8, 1/16, 1/8t or 1/16t [default: 1/16]
+      --gate <0-1>           fraction of each arpeggio step a note sounds [default: 0.8]
+      --octaves <n>          octaves the arpeggio climbs through [default: 1]
+      --latch                keep the arpeggio running across chord changes
+      --voicing <style>      close, drop2, drop3, spread, shell or quartal [default: as written]
+      --inversion <n>        chord tone at the bottom of the voicing, 0 = root position [default: 0]
+      --register <low-high>  note range for voiced chords, e.g. C3-G5 or 48-79 [default: C3-G5]
+      --voice-lead           pick voicings that move each voice as little as possible
+      --no-parallels         with --voice-lead, avoid parallel fifths and octaves
+      --melody               add a lead melody track generated from the seed
+      --melody-channel <1-16>  MIDI channel of the melody track [default: 3]
+      --melody-register <low-high>  note range of the melody [default: C4-C6]
+      --bass <style>         add a bass track: root-fifth, walking, octave-pump or one-drop
+      --bass-channel <1-16>  MIDI channel of the bass track [default: 2]
+      --bass-register <low-high>  note range of the bass line, at least an octave [default: E1-G3]
+      --drums <style>        add a drum track on channel 10: rock, funk, shuffle, bossa,
+                             half-time or four-on-the-floor
+      --fill-every <bars>    drum fill every n bars and at the end of each song section, 0 for none [default: 4]
+      --swing <percent>      swing the off-beat eighths, 50 (straight) to 72 [default: none]
+      --shuffle              play the off-beat eighths as triplets
+      --groove <name>        groove template: straight, swing, swing16, shuffle, laid-back,
+                             push, or swing:<percent> / swing16:<percent>
+      --groove-from <file>   take the groove (16th timing and velocities) from a MIDI file
+                             whose measure is as long as the song's
+      --accents <map>        beat accents: downbeat, backbeat, or a scale per beat (\"1.2 0.9 1 0.9\")
+      --envelope <points>    velocity envelope as bar:scale points, e.g. \"1:0.6 5:1\" for a
+                             crescendo over the first four bars
+      --expression           write the envelope as CC11 expression instead of scaling velocities;
+                             needs --envelope
+      --humanize <ticks>     move each note up to this many ticks early or late, from the seed
+                             (at most a sixteenth note: PPQ/4)
+      --humanize-velocity <n>  vary each note's velocity by up to n [default: 8]
+      --gaussian             humanize with a Gaussian spread instead of a uniform one
+  -s, --seed <n>             seed for randomised generators [default: picked and printed]
+  -o, --output <path>        output file (generate only) [default: output.mid]
+";
+
+/// Settings shared by `generate` and `render`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerateOptions {
+    pub progression: String,
+    /// Bars to generate from the seed, replacing `progression`.
+    pub random_bars: Option<usize>,
+    pub phrase_bars: usize,
+    /// Chord model (see `train`) to sample the progression from.
+    pub model: Option<String>,
+    /// Song chart with sections and an arrangement, replacing the progression.
+    pub song: Option<String>,
+    /// MIDI file to extract the groove from, replacing `settings.groove`.
+    pub groove_from: Option<String>,
+    /// Timing, key and playback; the command line takes channels 1-16.
+    pub settings: SmfSettings,
+    pub seed: Option<u64>,
+    pub output: String,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            progression: "I V IV".to_string(),
+            random_bars: None,
+            phrase_bars: 4,
+            model: None,
+            song: None,
+            groove_from: None,
+            settings: SmfSettings::default(),
+            seed: None,
+            output: "output.mid".to_string(),
+        }
+    }
+}
+
+/// Arguments of `analyze`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyzeOptions {
+    pub path: String,
+    /// One chord per beat or per bar.
+    pub resolution: Resolution,
+    /// Key for the Roman numerals; `None` detects it from the notes.
+    pub key: Option<Key>,
+}
+
+/// Arguments of `train`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainOptions {
+    /// Directory of chord charts.
+    pub dir: String,
+    pub output: String,
+    /// Chords of context each prediction looks back on.
+    pub order: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Generate(GenerateOptions),
+    Render(GenerateOptions),
+    Inspect { path: String },
+    Train(TrainOptions),
+    Analyze(AnalyzeOptions),
+    Help,
+}
+
+/// Parse the arguments following the program name.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(Command::Help);
+    };
+    match command.as_str() {
+        "generate" => parse_generate_options(rest).map(Command::Generate),
+        "render" => parse_generate_options(rest).map(Command::Render),
+        "inspect" => match rest {
+            [path] => Ok(Command::Inspect { path: path.clone() }),
+            _ => Err("inspect takes exactly one MIDI file path".to_string()),
+        },
+        "train" => parse_train_options(rest).map(Command::Train),
+        "analyze" => parse_analyze_options(rest).map(Command::Analyze),
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        other => Err(format!("unknown command {other:?}")),
+    }
+}
+
+fn parse_generate_options(args: &[String]) -> Result<GenerateOptions, String> {
+    let mut options = GenerateOptions::default();
+    let mut iter = args.iter();
+
+    while let Some(flag) = iter.next() {
+        // Accept both "--flag value" and "--flag=value".
+        let (name, inline) = match flag.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (flag.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| iter.next().cloned())
+                .ok_or_else(|| format!("{name} needs a value"))
+        };
+
+        match name {
+            "-p" | "--progression" => options.progression = value()?,
+            "--random" => {
+                let bars: usize = parse_number(name, &value()?)?;
+                if bars == 0 {
+                    return Err(format!("{name} needs at least one bar"));
+                }
+                options.random_bars = Some(bars);
+            }
+            "--model" => options.model = Some(value()?),
+            "--song" => options.song = Some(value()?),
+            "--phrase" => {
+                let bars: usize = parse_number(name, &value()?)?;
+                if bars < 2 {
+                    return Err(format!("{name} needs at least two bars"));
+                }
+                options.phrase_bars = bars;
+            }
+            "-k" | "--key" => options.settings.key = value()?.parse()?,
+            "-t" | "--tempo" => options.settings.tempo_bpm = parse_tempo(name, &value()?)?,
+            "--tempo-change" => {
+                let text = value()?;
+                let (bar, bpm) = text
+                    .split_once(':')
+                    .ok_or_else(|| format!("{name} {text:?} should look like 9:140"))?;
+                let bar: u32 = parse_number(name, bar)?;
+                let bpm = parse_tempo(name, bpm)?;
+                if bar == 0 {
+                    return Err(format!("{name}: bars are numbered from 1"));
+                }
+                options.settings.tempo_changes.push((bar - 1, bpm));
+            }
+            "--ppq" => {
+                let ppq: u16 = parse_number(name, &value()?)?;
+                if ppq == 0 || ppq > 0x7fff {
+                    return Err(format!("{name} must be between 1 and 32767"));
+                }
+                options.settings.ppq = ppq;
+            }
+            "--time-sig" => options.settings.time_signature = parse_time_signature(&value()?)?,
+            "-c" | "--channel" => {
+                let channel: u8 = parse_number(name, &value()?)?;
+                if !(1..=16).contains(&channel) {
+                    return Err(format!("{name} must be between 1 and 16"));
+                }
+                options.settings.channel = channel - 1;
+            }
+            "-v" | "--velocity" => {
+                let velocity: u8 = parse_number(name, &value()?)?;
+                if !(1..=127).contains(&velocity) {
+                    return Err(format!("{name} must be between 1 and 127"));
+                }
+                options.settings.velocity = velocity;
+            }
+            "--pattern" => {
+                let pattern: Result<StrumPattern, _> = value()?.parse();
+                options.settings.pattern = pattern.map_err(|err| err.to_string())?;
+            }
+            "--spread" => {
+                let text = value()?;
+                options.settings.strum_spread = match text.strip_suffix("ms") {
+                    Some(ms) => {
+                        let ms: f64 = parse_number(name, ms)?;
+                        if !(0.0..=1000.0).contains(&ms) {
+                            return Err(format!("{name} must be between 0 and 1000 ms"));
+                        }
+                        StrumSpread::Millis(ms)
+                    }
+                    None => StrumSpread::Ticks(parse_number(name, &text)?),
+                };
+            }
+            "--taper" => {
+                let taper: f32 = parse_number(name, &value()?)?;
+                if !(0.0..=1.0).contains(&taper) {
+                    return Err(format!("{name} must be between 0 and 1"));
+                }
+                options.settings.strum_taper = taper;
+            }
+            "--arp" => {
+                let mode: ArpMode = value()?.parse()?;
+                options
+                    .settings
+                    .arpeggio
+                    .get_or_insert_with(Arpeggio::default)
+                    .mode = mode;
+            }
+            "--arp-rate" => {
+                let rate = value()?.parse()?;
+                options
+                    .settings
+                    .arpeggio
+                    .get_or_insert_with(Arpeggio::default)
+                    .rate = rate;
+            }
+            "--gate" => {
+                let gate: f32 = parse_number(name, &value()?)?;
+                if !(gate > 0.0 && gate <= 1.0) {
+                    return Err(format!("{name} must be above 0 and at most 1"));
+                }
+                options
+                    .settings
+                    .arpeggio
+                    .get_or_insert_with(Arpeggio::default)
+                    .gate = gate;
+            }
+            "--octaves" => {
+                let octaves: u8 = parse_number(name, &value()?)?;
+                if !(1..=4).contains(&octaves) {
+                    return Err(format!("{name} must be between 1 and 4"));
+                }
+                options
+                    .settings
+                    .arpeggio
+                    .get_or_insert_with(Arpeggio::default)
+                    .octaves = octaves;
+            }
+            "--latch" => {
+                options
+                    .settings
+                    .arpeggio
+                    .get_or_insert_with(Arpeggio::default)
+                    .latch = true
+            }
+            "--voicing" => {
+                let style: VoicingStyle = value()?.parse()?;
+                let inversion = options.settings.voicing.map_or(0, |v| v.inversion);
+                options.settings.voicing = Some(Voicing::new(style, inversion));
+            }
+            "--inversion" => {
+                let inversion = parse_number(name, &value()?)?;
+                let style = options
+                    .settings
+                    .voicing
+                    .map_or(VoicingStyle::Close, |v| v.style);
+                options.settings.voicing = Some(Voicing::new(style, inversion));
+            }
+            "--register" => options.settings.register = value()?.parse()?,
+            "--voice-lead" => options.settings.voice_leading = true,
+            "--no-parallels" => {
+                options.settings.voice_leading = true;
+                options.settings.avoid_parallels = true;
+            }
+            "--melody" => {
+                options.settings.melody.get_or_insert_with(Melody::default);
+            }
+            "--melody-channel" => {
+                let channel: u8 = parse_number(name, &value()?)?;
+                if !(1..=16).contains(&channel) {
+                    return Err(format!("{name} must be between 1 and 16"));
+                }
+                options
+                    .settings
+                    .melody
+                    .get_or_insert_with(Melody::default)
+                    .channel = channel - 1;
+            }
+            "--melody-register" => {
+                let register = value()?.parse()?;
+                options
+                    .settings
+                    .melody
+                    .get_or_insert_with(Melody::default)
+                    .register = register;
+            }
+            "--bass" => {
+                let style: BassStyle = value()?.parse()?;
+                options
+                    .settings
+                    .bass
+                    .get_or_insert_with(BassLine::default)
+                    .style = style;
+            }
+            "--bass-channel" => {
+                let channel: u8 = parse_number(name, &value()?)?;
+                if !(1..=16).contains(&channel) {
+                    return Err(format!("{name} must be between 1 and 16"));
+                }
+                options
+                    .settings
+                    .bass
+                    .get_or_insert_with(BassLine::default)
+                    .channel = channel - 1;
+            }
+            "--bass-register" => {
+                let register = value()?.parse()?;
+                options
+                    .settings
+                    .bass
+                    .get_or_insert_with(BassLine::default)
+                    .register = register;
+            }
+            "--drums" => {
+                let style: DrumStyle = value()?.parse()?;
+                options
+                    .settings
+                    .drums
+                    .get_or_insert_with(DrumPart::default)
+                    .style = style;
+            }
+            "--fill-every" => {
+                let bars = parse_number(name, &value()?)?;
+                options
+                    .settings
+                    .drums
+                    .get_or_insert_with(DrumPart::default)
+                    .fill_every = bars;
+            }
+            "--swing" => {
+                let percent: f64 = parse_number(name, &value()?)?;
+                if !(50.0..=72.0).contains(&percent) {
+                    return Err(format!("{name} must be between 50 and 72"));
+                }
+                options.settings.groove = Some(Groove::swing(percent, SwingGrid::Eighth));
+            }
+            "--shuffle" => options.settings.groove = Some(Groove::shuffle()),
+            "--groove" => options.settings.groove = Some(value()?.parse()?),
+            "--groove-from" => options.groove_from = Some(value()?),
+            "--accents" => options.settings.accents = Some(value()?.parse()?),
+            "--envelope" => options.settings.envelope = Some(value()?.parse()?),
+            "--expression" => options.settings.expression = true,
+            "--humanize" => {
+                let ticks = parse_number(name, &value()?)?;
+                options
+                    .settings
+                    .humanize
+                    .get_or_insert_with(Humanize::default)
+                    .timing = ticks;
+            }
+            "--humanize-velocity" => {
+                let amount = parse_number(name, &value()?)?;
+                if amount > 127 {
+                    return Err(format!("{name} must be between 0 and 127"));
+                }
+                options
+                    .settings
+                    .humanize
+                    .get_or_insert_with(Humanize::default)
+                    .velocity = amount;
+            }
+            "--gaussian" => {
+                options
+                    .settings
+                    .humanize
+                    .get_or_insert_with(Humanize::default)
+                    .distribution = Distribution::Gaussian;
+            }
+            "-s" | "--seed" => options.seed = Some(parse_number(name, &value()?)?),
+            "-o" | "--output" => options.output = value()?,
+            other => return Err(format!("unknown option {other:?}")),
+        }
+    }
+
+    if options.settings.expression && options.settings.envelope.is_none() {
+        return Err("--expression needs an --envelope to write".to_string());
+    }
+
+    // Past a sixteenth note, humanizing scrambles the rhythm rather than loosening it.
+    if let Some(humanize) = &options.settings.humanize {
+        let limit = u32::from(options.settings.ppq) / 4;
+        if humanize.timing > limit {
+            return Err(format!(
+                "--humanize must be at most {limit} ticks, a sixteenth note at {} PPQ",
+                options.settings.ppq
+            ));
+        }
+    }
+    Ok(options)
+}
+
+fn parse_analyze_options(args: &[String]) -> Result<AnalyzeOptions, String> {
+    let mut path = None;
+    let mut resolution = Resolution::default();
+    let mut key = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{arg} needs a value"))
+        };
+        match arg.as_str() {
+            "--per" => resolution = value()?.parse()?,
+            "-k" | "--key" => key = Some(value()?.parse()?),
+            other if other.starts_with('-') => return Err(format!("unknown option {other:?}")),
+            other if path.is_none() => path = Some(other.to_string()),
+            _ => return Err("analyze takes exactly one MIDI file path".to_string()),
+        }
+    }
+    Ok(AnalyzeOptions {
+        path: path.ok_or("analyze needs a MIDI file")?,
+        resolution,
+        key,
+    })
+}
+
+fn parse_train_options(args: &[String]) -> Result<TrainOptions, String> {
+    let mut dir = None;
+    let mut output = "chords.model".to_string();
+    let mut order = 1;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{arg} needs a value"))
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = value()?,
+            "--order" => {
+                order = parse_number(arg, &value()?)?;
+                if !(1..=4).contains(&order) {
+                    return Err(format!("{arg} must be between 1 and 4"));
+                }
+            }
+            other if other.starts_with('-') => return Err(format!("unknown option {other:?}")),
+            other if dir.is_none() => dir = Some(other.to_string()),
+            _ => return Err("train takes exactly one chart directory".to_string()),
+        }
+    }
+    Ok(TrainOptions {
+        dir: dir.ok_or("train needs a directory of chord charts")?,
+        output,
+        order,
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, text: &str) -> Result<T, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{flag}: {text:?} is not a valid number"))
+}
+
+fn parse_tempo(flag: &str, text: &str) -> Result<f64, String> {
+    let bpm: f64 = parse_number(flag, text)?;
+    // Tempo is stored in 24 bits of microseconds per quarter.
+    if !(4.0..=1000.0).contains(&bpm) {
+        return Err(format!("{flag} must be between 4 and 1000 BPM"));
+    }
+    Ok(bpm)
+}
+
+/// Parse "3/4", "6/8", ... The denominator must be a power of two.
+fn parse_time_signature(text: &str) -> Result<(u8, u8), String> {
+    let (numerator, denominator) = text
+        .split_once('/')
+        .ok_or_else(|| format!("time signature {text:?} should look like 4/4"))?;
+    let numerator: u8 = parse_number("--time-sig", numerator)?;
+    let denominator: u8 = parse_number("--time-sig", denominator)?;
+    if numerator == 0 || !denominator.is_power_of_two() || denominator > 64 {
+        return Err(format!("time signature {text:?} is not valid"));
+    }
+    Ok((numerator, denominator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_generate_flags() {
+        let command = parse_args(&args(&[
+            "generate",
+            "-p",
+            "ii V I",
+            "--key=Bb",
+            "--tempo",
+            "96",
+            "--tempo-change",
+            "9:140",
+            "--ppq",
+            "960",
+            "--time-sig",
+            "3/4",
+            "-c",
+            "10",
+            "-v",
+            "100",
+            "--seed",
+            "7",
+            "--pattern",
+            "bossa",
+            "--spread",
+            "12ms",
+            "--taper",
+            "0.3",
+            "--inversion",
+            "1",
+            "--voicing",
+            "drop2",
+            "--register",
+            "E2-E5",
+            "--no-parallels",
+            "--bass-channel",
+            "3",
+            "--bass",
+            "walking",
+            "--drums=bossa",
+            "--arp",
+            "random",
+            "--arp-rate=1/8t",
+            "--latch",
+            "--fill-every",
+            "8",
+            "--melody-channel=4",
+            "--song",
+            "song.txt",
+            "--swing=62",
+            "--groove-from",
+            "played.mid",
+            "--gaussian",
+            "--humanize=6",
+            "--accents",
+            "backbeat",
+            "--envelope=1:0.5 9:1",
+            "--expression",
+            "-o",
+            "song.mid",
+        ]))
+        .unwrap();
+        let Command::Generate(options) = command else {
+            panic!("expected generate");
+        };
+        assert_eq!(options.progression, "ii V I");
+        assert_eq!(options.settings.key.tonic, 10);
+        assert_eq!(options.settings.tempo_changes, vec![(8, 140.0)]);
+        assert_eq!(options.settings.ppq, 960);
+        assert_eq!(options.settings.ticks_per_measure(), 2880);
+        assert_eq!(options.settings.micros_per_quarter(), 625_000);
+        assert_eq!(options.settings.channel, 9);
+        assert_eq!(options.settings.velocity, 100);
+        assert_eq!(options.settings.pattern, "bossa".parse().unwrap());
+        assert_eq!(options.settings.strum_spread, StrumSpread::Millis(12.0));
+        assert_eq!(options.settings.strum_taper, 0.3);
+        assert_eq!(
+            options.settings.voicing,
+            Some(Voicing::new(VoicingStyle::Drop2, 1))
+        );
+        assert_eq!(options.settings.register.low, 40);
+        assert!(options.settings.voice_leading && options.settings.avoid_parallels);
+        let bass = options.settings.bass.unwrap();
+        assert_eq!((bass.style, bass.channel), (BassStyle::Walking, 2));
+        let drums = options.settings.drums.unwrap();
+        assert_eq!((drums.style, drums.fill_every), (DrumStyle::Bossa, 8));
+        let arpeggio = options.settings.arpeggio.unwrap();
+        assert_eq!(arpeggio.mode, ArpMode::Random);
+        assert!(arpeggio.latch);
+        let melody = options.settings.melody.unwrap();
+        assert_eq!(melody.channel, 3);
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.song.as_deref(), Some("song.txt"));
+        assert_eq!(
+            options.settings.groove,
+            Some(Groove::swing(62.0, SwingGrid::Eighth))
+        );
+        assert_eq!(options.groove_from.as_deref(), Some("played.mid"));
+        assert_eq!(options.settings.accents, "backbeat".parse().ok());
+        assert_eq!(
+            options.settings.envelope.unwrap().points,
+            [(0.0, 0.5), (8.0, 1.0)]
+        );
+        assert!(options.settings.expression);
+        assert_eq!(
+            options.settings.humanize,
+            Some(Humanize {
+                timing: 6,
+                velocity: 8,
+                distribution: Distribution::Gaussian,
+                seed: 0,
+            })
+        );
+        assert_eq!(options.output, "song.mid");
+    }
+
+    #[test]
+    fn test_parse_train() {
+        let command = parse_args(&args(&["train", "charts", "--order", "2"])).unwrap();
+        assert_eq!(
+            command,
+            Command::Train(TrainOptions {
+                dir: "charts".to_string(),
+                output: "chords.model".to_string(),
+                order: 2,
+            })
+        );
+        assert!(parse_args(&args(&["train"])).is_err());
+        assert!(parse_args(&args(&["train", "a", "b"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_analyze() {
+        let command = parse_args(&args(&["analyze", "song.mid", "--per", "beat", "-k", "Em"]));
+        assert_eq!(
+            command.unwrap(),
+            Command::Analyze(AnalyzeOptions {
+                path: "song.mid".to_string(),
+                resolution: Resolution::Beat,
+                key: "Em".parse().ok(),
+            })
+        );
+        assert!(parse_args(&args(&["analyze"])).is_err());
+        assert!(parse_args(&args(&["analyze", "a.mid", "--per", "tick"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_args(&[]).unwrap(), Command::Help);
+        assert!(parse_args(&args(&["bogus"])).is_err());
+        assert!(parse_args(&args(&["generate", "-c", "17"])).is_err());
+        assert!(parse_args(&args(&["generate", "--time-sig", "4/3"])).is_err());
+        assert!(parse_args(&args(&["generate", "--tempo"])).is_err());
+        assert!(parse_args(&args(&["generate", "--tempo-change", "0:90"])).is_err());
+        assert!(parse_args(&args(&["generate", "--tempo-change", "90"])).is_err());
+        assert!(parse_args(&args(&["inspect"])).is_err());
+        assert!(parse_args(&args(&["generate", "--pattern", "D?"])).is_err());
+        assert!(parse_args(&args(&["generate", "--taper", "2"])).is_err());
+        assert!(parse_args(&args(&["generate", "--voicing", "cluster"])).is_err());
+        assert!(parse_args(&args(&["generate", "--bass", "slap"])).is_err());
+        assert!(parse_args(&args(&["generate", "--gate", "0"])).is_err());
+        assert!(parse_args(&args(&["generate", "--register", "G5-C3"])).is_err());
+        assert!(parse_args(&args(&["generate", "--phrase", "1"])).is_err());
+        assert!(parse_args(&args(&["generate", "--humanize", "121"])).is_err());
+        assert!(parse_args(&args(&["generate", "--expression"])).is_err());
+        assert!(parse_args(&args(&["generate", "--humanize", "240", "--ppq", "960"])).is_ok());
+    }
+}
@@ -0,0 +1,433 @@
+//! General MIDI drum grooves and fills.
+
+use std::str::FromStr;
+
+use midly::num::{u4, u7};
+
+use crate::error::Result;
+use crate::events::{tick_u28, value_u7};
+use crate::tempo::Meter;
+use crate::timeline::Timeline;
+
+// ---------------------------------------------------------------------
+// General MIDI drum patterns
+// ---------------------------------------------------------------------
+
+/// Zero-based channel General MIDI reserves for percussion (channel 10).
+pub const DRUM_CHANNEL: u8 = 9;
+
+/// General MIDI percussion key numbers.
+pub mod gm {
+    /// Bass drum 1.
+    pub const KICK: u8 = 36;
+    /// Side stick (rim click).
+    pub const SIDE_STICK: u8 = 37;
+    /// Acoustic snare.
+    pub const SNARE: u8 = 38;
+    /// Hand clap.
+    pub const CLAP: u8 = 39;
+    /// Low floor tom.
+    pub const FLOOR_TOM: u8 = 41;
+    /// Closed hi-hat.
+    pub const CLOSED_HAT: u8 = 42;
+    /// Pedal hi-hat.
+    pub const PEDAL_HAT: u8 = 44;
+    /// Low tom.
+    pub const LOW_TOM: u8 = 45;
+    /// Open hi-hat.
+    pub const OPEN_HAT: u8 = 46;
+    /// Low-mid tom.
+    pub const MID_TOM: u8 = 47;
+    /// High tom.
+    pub const HIGH_TOM: u8 = 50;
+    /// Crash cymbal 1.
+    pub const CRASH: u8 = 49;
+    /// Ride cymbal 1.
+    pub const RIDE: u8 = 51;
+}
+
+/// Groove played in every measure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrumStyle {
+    /// Eighth-note hi-hats over a backbeat snare.
+    Rock,
+    /// Sixteenth-note hi-hats, a syncopated kick and ghosted snares.
+    Funk,
+    /// Shuffled ride, a dotted eighth and a sixteenth to each beat, over a
+    /// backbeat.
+    Shuffle,
+    /// Side stick on the bossa nova clave over a steady kick.
+    Bossa,
+    /// Snare on beat 3 only, for a half-time feel.
+    HalfTime,
+    /// Kick on every beat, clap on 2 and 4, open hi-hats on the offbeats.
+    FourOnTheFloor,
+}
+
+impl FromStr for DrumStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "rock" => DrumStyle::Rock,
+            "funk" => DrumStyle::Funk,
+            "shuffle" => DrumStyle::Shuffle,
+            "bossa" => DrumStyle::Bossa,
+            "half-time" | "halftime" => DrumStyle::HalfTime,
+            "four-on-the-floor" | "four" | "disco" => DrumStyle::FourOnTheFloor,
+            other => return Err(format!("unknown drum style {other:?}")),
+        })
+    }
+}
+
+/// One 4/4 measure of a groove: a line per instrument, one character per
+/// sixteenth note. `X` is an accent, `x` a normal hit, `g` a ghost note and `.`
+/// a rest.
+type Groove = &'static [(u8, &'static str)];
+
+const ROCK: Groove = &[
+    (gm::CLOSED_HAT, "x.x.x.x.x.x.x.x."),
+    (gm::SNARE, "....X.......X..."),
+    (gm::KICK, "X.......X.X....."),
+];
+
+const FUNK: Groove = &[
+    (gm::CLOSED_HAT, "XxxxXxxxXxxxXxxx"),
+    (gm::SNARE, "....X..g.g..X..g"),
+    (gm::KICK, "X.X....X..X....."),
+];
+
+const SHUFFLE: Groove = &[
+    (gm::RIDE, "X..xX..xX..xX..x"),
+    (gm::SNARE, "....X.......X..."),
+    (gm::KICK, "X.......X......x"),
+];
+
+const BOSSA: Groove = &[
+    (gm::CLOSED_HAT, "xxxxxxxxxxxxxxxx"),
+    (gm::SIDE_STICK, "x..x..x...x..x.."),
+    (gm::KICK, "X..xX..xX..xX..x"),
+];
+
+const HALF_TIME: Groove = &[
+    (gm::CLOSED_HAT, "x.x.x.x.x.x.x.x."),
+    (gm::SNARE, "........X......."),
+    (gm::KICK, "X.........x....."),
+];
+
+const FOUR_ON_THE_FLOOR: Groove = &[
+    (gm::OPEN_HAT, "..x...x...x...x."),
+    (gm::CLAP, "....X.......X..."),
+    (gm::KICK, "X...X...X...X..."),
+];
+
+/// Instruments of a fill from its first hit to its last. A fill plays
+/// sixteenths through the last half of the measure's beats; shorter fills
+/// step through the list.
+const FILL: [u8; 8] = [
+    gm::SNARE,
+    gm::SNARE,
+    gm::HIGH_TOM,
+    gm::HIGH_TOM,
+    gm::MID_TOM,
+    gm::LOW_TOM,
+    gm::FLOOR_TOM,
+    gm::FLOOR_TOM,
+];
+
+impl DrumStyle {
+    fn groove(self) -> Groove {
+        match self {
+            DrumStyle::Rock => ROCK,
+            DrumStyle::Funk => FUNK,
+            DrumStyle::Shuffle => SHUFFLE,
+            DrumStyle::Bossa => BOSSA,
+            DrumStyle::HalfTime => HALF_TIME,
+            DrumStyle::FourOnTheFloor => FOUR_ON_THE_FLOOR,
+        }
+    }
+}
+
+/// A drum part: groove, fills and velocity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrumPart {
+    /// Groove played in every measure.
+    pub style: DrumStyle,
+    /// Section length in measures: the last measure of each section (and of
+    /// the song, or of each section of a song chart) ends with a fill, and the
+    /// next section opens with a crash. 0 disables fills.
+    pub fill_every: u32,
+    /// Velocity of accented hits; normal and ghost hits are scaled down from it.
+    pub velocity: u8,
+}
+
+impl Default for DrumPart {
+    fn default() -> Self {
+        DrumPart {
+            style: DrumStyle::Rock,
+            fill_every: 4,
+            velocity: 100,
+        }
+    }
+}
+
+/// Velocity scale of a groove character, or `None` for a rest.
+fn hit_scale(c: char) -> Option<f32> {
+    match c {
+        'X' => Some(1.0),
+        'x' => Some(0.75),
+        'g' => Some(0.4),
+        _ => None,
+    }
+}
+
+impl DrumPart {
+    /// Whether `measure` (zero-based) of a `measures`-long part ends with a fill.
+    pub fn is_fill(&self, measure: usize, measures: usize) -> bool {
+        self.fill_every > 0
+            && ((measure + 1).is_multiple_of(self.fill_every as usize) || measure + 1 == measures)
+    }
+
+    /// Hits of one measure of `meter` as (offset, key, velocity scale).
+    ///
+    /// A sixteenth note is a quarter of a beat at any PPQ. The groove spans
+    /// four quarter notes, so it starts again to fill a longer measure and
+    /// stops at the bar line of a shorter one. A fill takes the last half of
+    /// the measure's beats (at least one), so it always starts on a beat.
+    fn measure_hits(&self, measure: usize, measures: usize, meter: Meter) -> Vec<(u32, u8, f32)> {
+        let ticks_per_measure = meter.ticks_per_measure();
+        let sixteenth = (u32::from(meter.ppq) / 4).max(1);
+        let beats = meter.beats_per_measure().max(1);
+        let fill = self.is_fill(measure, measures);
+        let fill_start = (beats - (beats / 2).max(1)) * meter.beat_ticks();
+
+        let mut hits = Vec::new();
+        for &(key, line) in self.style.groove() {
+            let line = line.as_bytes();
+            for step in 0..ticks_per_measure.div_ceil(sixteenth) {
+                let Some(scale) = hit_scale(char::from(line[step as usize % line.len()])) else {
+                    continue;
+                };
+                let offset = step * sixteenth;
+                if !(fill && offset >= fill_start) {
+                    hits.push((offset, key, scale));
+                }
+            }
+        }
+
+        // A crash on the downbeat after a fill opens the next section.
+        if measure > 0 && self.is_fill(measure - 1, measures) {
+            hits.push((0, gm::CRASH, 1.0));
+        }
+        if fill {
+            let count = ((ticks_per_measure - fill_start) / sixteenth).max(1);
+            for index in 0..count {
+                let key = FILL[index as usize * FILL.len() / count as usize];
+                let scale = 0.7 + 0.3 * index as f32 / (count - 1).max(1) as f32;
+                hits.push((fill_start + index * sixteenth, key, scale));
+            }
+        }
+        hits
+    }
+}
+
+/// Drum hits for `measures` measures of `meter` starting at `start_tick`, on
+/// `channel` (normally [`DRUM_CHANNEL`]). Grooves keep their sixteenth-note
+/// grid in every meter: 3/4 plays the first three beats of a 4/4 groove.
+pub fn drum_track_timeline(
+    measures: usize,
+    drums: &DrumPart,
+    start_tick: u32,
+    meter: Meter,
+    channel: u4,
+) -> Result<Timeline<'static>> {
+    value_u7(i32::from(drums.velocity))?;
+
+    // Drum hits are short; GM kits ignore note length but note-offs are still sent.
+    let ticks_per_measure = meter.ticks_per_measure();
+    let length = (u32::from(meter.ppq) / 4).max(1);
+    let mut timeline = Timeline::new();
+    for measure in 0..measures {
+        let measure_start = u64::from(start_tick) + measure as u64 * u64::from(ticks_per_measure);
+        for (offset, key, scale) in drums.measure_hits(measure, measures, meter) {
+            let tick = tick_u28(measure_start + u64::from(offset))?.as_int();
+            let velocity = (f32::from(drums.velocity) * scale)
+                .round()
+                .clamp(1.0, 127.0) as u8;
+            timeline.note(tick, length, channel, u7::from(key), u7::from(velocity));
+        }
+    }
+    Ok(timeline)
+}
+
+/// Drum hits for sections of `section_bars` measures played back to back from
+/// `start_tick`. Each section is a part of its own for [`DrumPart::fill_every`]:
+/// it ends with a fill, and the sections after the first open with a crash.
+pub fn drum_sections_timeline(
+    section_bars: &[usize],
+    drums: &DrumPart,
+    start_tick: u32,
+    meter: Meter,
+    channel: u4,
+) -> Result<Timeline<'static>> {
+    let ticks_per_measure = meter.ticks_per_measure();
+    let mut timeline = Timeline::new();
+    let mut start = u64::from(start_tick);
+    for (index, &bars) in section_bars.iter().enumerate() {
+        let tick = tick_u28(start)?.as_int();
+        timeline.merge(drum_track_timeline(bars, drums, tick, meter, channel)?);
+        if index > 0 && bars > 0 && drums.fill_every > 0 {
+            let length = (u32::from(meter.ppq) / 4).max(1);
+            let velocity = value_u7(i32::from(drums.velocity))?;
+            timeline.note(tick, length, channel, u7::from(gm::CRASH), velocity);
+        }
+        start += bars as u64 * u64::from(ticks_per_measure);
+    }
+    Ok(timeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{MidiMessage, TrackEventKind};
+
+    fn hits(timeline: &Timeline, key: u8) -> Vec<u32> {
+        let mut ticks: Vec<u32> = timeline
+            .iter()
+            .filter_map(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key: k, .. },
+                    ..
+                } if k == key => Some(ev.tick),
+                _ => None,
+            })
+            .collect();
+        ticks.sort_unstable();
+        ticks
+    }
+
+    #[test]
+    fn test_grooves_are_well_formed() {
+        for style in [
+            DrumStyle::Rock,
+            DrumStyle::Funk,
+            DrumStyle::Shuffle,
+            DrumStyle::Bossa,
+            DrumStyle::HalfTime,
+            DrumStyle::FourOnTheFloor,
+        ] {
+            let groove = style.groove();
+            assert!(groove.iter().all(|(_, line)| line.len() == 16), "{style:?}");
+            assert!(groove
+                .iter()
+                .all(|(_, line)| line.chars().all(|c| c == '.' || hit_scale(c).is_some())));
+        }
+        assert_eq!(
+            "half-time".parse::<DrumStyle>().unwrap(),
+            DrumStyle::HalfTime
+        );
+        assert!("polka".parse::<DrumStyle>().is_err());
+    }
+
+    #[test]
+    fn test_rock_groove_lines_up_with_measures() {
+        let drums = DrumPart {
+            fill_every: 0,
+            ..DrumPart::default()
+        };
+        let timeline =
+            drum_track_timeline(2, &drums, 0, Meter::default(), u4::from(DRUM_CHANNEL)).unwrap();
+        assert_eq!(hits(&timeline, gm::SNARE), vec![480, 1440, 2400, 3360]);
+        assert_eq!(
+            hits(&timeline, gm::KICK),
+            vec![0, 960, 1200, 1920, 2880, 3120]
+        );
+        assert_eq!(hits(&timeline, gm::CLOSED_HAT).len(), 16);
+        assert!(hits(&timeline, gm::CRASH).is_empty());
+    }
+
+    #[test]
+    fn test_fills_end_sections_and_crash_follows() {
+        let drums = DrumPart {
+            style: DrumStyle::FourOnTheFloor,
+            fill_every: 2,
+            velocity: 100,
+        };
+        let timeline =
+            drum_track_timeline(3, &drums, 0, Meter::default(), u4::from(DRUM_CHANNEL)).unwrap();
+        assert!(drums.is_fill(1, 3) && drums.is_fill(2, 3) && !drums.is_fill(0, 3));
+
+        // The second half of measure 2 is all fill; the groove stops at 960.
+        let kicks = hits(&timeline, gm::KICK);
+        assert!(kicks.contains(&(1920 + 480)));
+        assert!(!kicks.contains(&(1920 + 960)));
+        assert_eq!(hits(&timeline, gm::FLOOR_TOM).len(), 4);
+        assert_eq!(hits(&timeline, gm::CRASH), vec![3840]);
+
+        let channels_ok = timeline.iter().all(|ev| match ev.kind {
+            TrackEventKind::Midi { channel, .. } => channel == DRUM_CHANNEL,
+            _ => false,
+        });
+        assert!(channels_ok);
+    }
+
+    #[test]
+    fn test_grooves_keep_the_sixteenth_grid_in_three_four() {
+        let drums = DrumPart {
+            fill_every: 2,
+            ..DrumPart::default()
+        };
+        let meter = Meter::new(480, (3, 4));
+        let timeline = drum_track_timeline(2, &drums, 0, meter, u4::from(DRUM_CHANNEL)).unwrap();
+        // The first three beats of the 4/4 groove; the fill takes beat 3 of bar 2.
+        assert_eq!(
+            hits(&timeline, gm::CLOSED_HAT),
+            vec![0, 240, 480, 720, 960, 1200, 1440, 1680, 1920, 2160]
+        );
+        assert_eq!(hits(&timeline, gm::SNARE), vec![480, 1920, 2400]);
+        assert_eq!(hits(&timeline, gm::KICK), vec![0, 960, 1200, 1440]);
+        assert_eq!(hits(&timeline, gm::HIGH_TOM), vec![2520]);
+        assert_eq!(hits(&timeline, gm::FLOOR_TOM), vec![2760]);
+    }
+
+    #[test]
+    fn test_grooves_keep_the_sixteenth_grid_in_six_eight() {
+        let drums = DrumPart {
+            style: DrumStyle::Funk,
+            fill_every: 1,
+            ..DrumPart::default()
+        };
+        let meter = Meter::new(480, (6, 8));
+        let timeline = drum_track_timeline(1, &drums, 0, meter, u4::from(DRUM_CHANNEL)).unwrap();
+        // Sixteenth hats up to the fill, which starts on the second dotted quarter.
+        let hats: Vec<u32> = (0..6).map(|step| step * 120).collect();
+        assert_eq!(hits(&timeline, gm::CLOSED_HAT), hats);
+        assert_eq!(hits(&timeline, gm::KICK), vec![0, 240]);
+        let fill: Vec<u32> = [
+            gm::SNARE,
+            gm::HIGH_TOM,
+            gm::MID_TOM,
+            gm::LOW_TOM,
+            gm::FLOOR_TOM,
+        ]
+        .iter()
+        .flat_map(|&key| hits(&timeline, key))
+        .filter(|&tick| tick >= 720)
+        .collect();
+        assert_eq!(fill, vec![720, 840, 960, 1080, 1200, 1320]);
+    }
+
+    #[test]
+    fn test_sections_end_with_fills() {
+        let drums = DrumPart::default();
+        let timeline =
+            drum_sections_timeline(&[3, 2], &drums, 0, Meter::default(), u4::from(DRUM_CHANNEL))
+                .unwrap();
+        // Fills close bar 3 and bar 5; the second section opens with a crash.
+        let toms = hits(&timeline, gm::FLOOR_TOM);
+        assert_eq!(toms.len(), 4);
+        assert!(toms[..2].iter().all(|&tick| (4800..5760).contains(&tick)));
+        assert!(toms[2..].iter().all(|&tick| (8640..9600).contains(&tick)));
+        assert_eq!(hits(&timeline, gm::CRASH), vec![5760]);
+    }
+}
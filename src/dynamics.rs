@@ -0,0 +1,311 @@
+//! Beat accents, velocity envelopes and CC11 expression.
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use midly::num::{u4, u7};
+use midly::{MidiMessage, TrackEventKind};
+
+use crate::events::scale_note_on;
+use crate::timeline::Timeline;
+
+// ---------------------------------------------------------------------
+// Dynamics: beat accents, velocity envelopes and CC11 expression
+// ---------------------------------------------------------------------
+
+/// General MIDI expression controller.
+pub const EXPRESSION_CC: u8 = 11;
+
+/// Velocity scale for each beat of a measure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccentMap {
+    /// Scale of notes on each beat, from the downbeat; beats past the end are
+    /// left as they are.
+    pub beats: Vec<f64>,
+    /// Scale of notes that fall between beats.
+    pub offbeat: f64,
+}
+
+impl AccentMap {
+    /// "downbeat" (a stronger first beat) or "backbeat" (2 and 4 over 1 and 3).
+    pub fn named(name: &str) -> Option<Self> {
+        let beats = match name.to_ascii_lowercase().as_str() {
+            "downbeat" => vec![1.2],
+            "backbeat" => vec![0.95, 1.15, 0.95, 1.15],
+            _ => return None,
+        };
+        Some(AccentMap {
+            beats,
+            offbeat: 0.9,
+        })
+    }
+
+    fn scale(&self, tick: u32, ticks_per_measure: u32, beats_per_measure: u32) -> f64 {
+        let beat_ticks = (ticks_per_measure / beats_per_measure.max(1)).max(1);
+        let offset = tick % ticks_per_measure.max(1);
+        if !offset.is_multiple_of(beat_ticks) {
+            return self.offbeat;
+        }
+        let beat = (offset / beat_ticks) as usize;
+        self.beats.get(beat).copied().unwrap_or(1.0)
+    }
+
+    /// Scale each note-on of `timeline` by the beat it lands on, measures
+    /// counted from tick 0.
+    pub fn apply(&self, timeline: &mut Timeline, ticks_per_measure: u32, beats_per_measure: u32) {
+        for event in timeline.iter_mut() {
+            let scale = self.scale(event.tick, ticks_per_measure, beats_per_measure);
+            scale_note_on(&mut event.kind, scale);
+        }
+    }
+}
+
+impl FromStr for AccentMap {
+    type Err = String;
+
+    /// A name, or scales per beat separated by spaces or commas: "1.2 0.9 1.1 0.9".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(map) = AccentMap::named(s.trim()) {
+            return Ok(map);
+        }
+        let beats = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .map(|token| match token.parse::<f64>() {
+                Ok(scale) if scale >= 0.0 => Ok(scale),
+                _ => Err(format!("bad accent {token:?} in {s:?}")),
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        if beats.is_empty() {
+            return Err(format!("unknown accent map {s:?}"));
+        }
+        Ok(AccentMap {
+            beats,
+            offbeat: 1.0,
+        })
+    }
+}
+
+/// Velocity scale over time, as (zero-based measure, scale) breakpoints with
+/// straight lines between them. Before the first point and after the last the
+/// scale holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    /// Breakpoints as (zero-based measure, scale), measures increasing.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Envelope {
+    /// A ramp from `from` to `to` over `measures` measures starting at
+    /// `start`: a crescendo when `to` is larger, a decrescendo otherwise.
+    pub fn ramp(start: f64, measures: f64, from: f64, to: f64) -> Self {
+        Envelope {
+            points: vec![(start, from), (start + measures, to)],
+        }
+    }
+
+    /// The scale at `measure` (fractional measures count).
+    pub fn scale_at(&self, measure: f64) -> f64 {
+        let Some(&(first, first_scale)) = self.points.first() else {
+            return 1.0;
+        };
+        if measure <= first {
+            return first_scale;
+        }
+        for pair in self.points.windows(2) {
+            let ((from, from_scale), (to, to_scale)) = (pair[0], pair[1]);
+            if measure <= to {
+                let fraction = if to > from {
+                    (measure - from) / (to - from)
+                } else {
+                    1.0
+                };
+                return from_scale + (to_scale - from_scale) * fraction;
+            }
+        }
+        self.points.last().map_or(1.0, |point| point.1)
+    }
+
+    /// Scale each note-on of `timeline` by the envelope where it starts.
+    pub fn apply(&self, timeline: &mut Timeline, ticks_per_measure: u32) {
+        for event in timeline.iter_mut() {
+            let measure = f64::from(event.tick) / f64::from(ticks_per_measure.max(1));
+            scale_note_on(&mut event.kind, self.scale_at(measure));
+        }
+    }
+
+    /// The envelope as CC11 expression on every channel `timeline` plays
+    /// notes on, up to its last event: full expression at the envelope's
+    /// peak, written every `step` ticks where the value changes.
+    pub fn expression(
+        &self,
+        timeline: &Timeline,
+        ticks_per_measure: u32,
+        step: u32,
+    ) -> Timeline<'static> {
+        let channels: BTreeSet<u8> = timeline
+            .iter()
+            .filter_map(|event| match event.kind {
+                TrackEventKind::Midi {
+                    channel,
+                    message: MidiMessage::NoteOn { .. },
+                } => Some(channel.as_int()),
+                _ => None,
+            })
+            .collect();
+        let peak = self.points.iter().map(|point| point.1).fold(0.0, f64::max);
+
+        let mut curve = Timeline::new();
+        if peak <= 0.0 {
+            return curve;
+        }
+        let mut previous = None;
+        for tick in (0..=timeline.end_tick()).step_by(step.max(1) as usize) {
+            let measure = f64::from(tick) / f64::from(ticks_per_measure.max(1));
+            let value = (127.0 * self.scale_at(measure) / peak)
+                .round()
+                .clamp(0.0, 127.0) as u8;
+            if previous == Some(value) {
+                continue;
+            }
+            previous = Some(value);
+            for &channel in &channels {
+                curve.push(
+                    tick,
+                    TrackEventKind::Midi {
+                        channel: u4::from(channel),
+                        message: MidiMessage::Controller {
+                            controller: u7::from(EXPRESSION_CC),
+                            value: u7::from(value),
+                        },
+                    },
+                );
+            }
+        }
+        curve
+    }
+}
+
+impl FromStr for Envelope {
+    type Err = String;
+
+    /// Breakpoints as `bar:scale`, bars counted from 1: "1:0.6 5:1 9:0.8"
+    /// swells over the first four bars and eases back over the next four.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut points = Vec::new();
+        for token in s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+        {
+            let point = token.split_once(':').and_then(|(bar, scale)| {
+                let bar: f64 = bar.parse().ok()?;
+                let scale: f64 = scale.parse().ok()?;
+                (bar >= 1.0 && scale >= 0.0).then_some((bar - 1.0, scale))
+            });
+            match point {
+                Some(point)
+                    if points
+                        .last()
+                        .is_none_or(|last: &(f64, f64)| last.0 < point.0) =>
+                {
+                    points.push(point)
+                }
+                Some(_) => return Err(format!("envelope bars must increase: {s:?}")),
+                None => return Err(format!("bad envelope point {token:?}, expected bar:scale")),
+            }
+        }
+        if points.is_empty() {
+            return Err("empty envelope".to_string());
+        }
+        Ok(Envelope { points })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn velocities(timeline: &Timeline) -> Vec<(u32, u8)> {
+        let mut on: Vec<(u32, u8)> = timeline
+            .iter()
+            .filter_map(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { vel, .. },
+                    ..
+                } => Some((ev.tick, vel.as_int())),
+                _ => None,
+            })
+            .collect();
+        on.sort_unstable();
+        on
+    }
+
+    /// Eighth notes at velocity 100 for `measures` bars of 4/4.
+    fn eighths(measures: u32) -> Timeline<'static> {
+        let mut timeline = Timeline::new();
+        for step in 0..measures * 8 {
+            timeline.note(step * 240, 240, u4::from(0), u7::from(60), u7::from(100));
+        }
+        timeline
+    }
+
+    #[test]
+    fn test_accent_maps() {
+        let mut timeline = eighths(1);
+        "backbeat"
+            .parse::<AccentMap>()
+            .unwrap()
+            .apply(&mut timeline, 1920, 4);
+        let vels: Vec<u8> = velocities(&timeline).iter().map(|on| on.1).collect();
+        assert_eq!(vels, [95, 90, 115, 90, 95, 90, 115, 90]);
+
+        // In 3/4 the beats past the map are untouched.
+        let mut timeline = eighths(1);
+        "1.2, 0.8"
+            .parse::<AccentMap>()
+            .unwrap()
+            .apply(&mut timeline, 1440, 3);
+        let vels: Vec<u8> = velocities(&timeline).iter().map(|on| on.1).collect();
+        assert_eq!(vels, [120, 100, 80, 100, 100, 100, 120, 100]);
+        assert!("loud".parse::<AccentMap>().is_err());
+        assert!("1 -1".parse::<AccentMap>().is_err());
+    }
+
+    #[test]
+    fn test_envelope_and_expression() {
+        let envelope: Envelope = "1:0.5 3:1 4:0.75".parse().unwrap();
+        assert_eq!(envelope.points, [(0.0, 0.5), (2.0, 1.0), (3.0, 0.75)]);
+        assert_eq!(envelope.scale_at(1.0), 0.75);
+        assert_eq!(envelope.scale_at(9.0), 0.75);
+        assert!((Envelope::ramp(0.0, 4.0, 0.4, 0.8).scale_at(2.0) - 0.6).abs() < 1e-9);
+        assert!("3:1 2:1".parse::<Envelope>().is_err());
+        assert!("0:1".parse::<Envelope>().is_err());
+
+        let mut timeline = eighths(4);
+        envelope.apply(&mut timeline, 1920);
+        let vels = velocities(&timeline);
+        assert_eq!(vels[0], (0, 50));
+        assert_eq!(vels[8], (1920, 75));
+        assert_eq!(vels[16], (3840, 100));
+        assert_eq!(vels[24], (5760, 75));
+
+        let curve = envelope.expression(&eighths(4), 1920, 480);
+        let values: Vec<(u32, u8)> = curve
+            .iter()
+            .map(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    channel,
+                    message: MidiMessage::Controller { controller, value },
+                } => {
+                    assert_eq!((channel.as_int(), controller.as_int()), (0, 11));
+                    (ev.tick, value.as_int())
+                }
+                _ => panic!("unexpected {ev:?}"),
+            })
+            .collect();
+        assert_eq!(values[0], (0, 64));
+        assert_eq!(values[4], (1920, 95));
+        assert_eq!(values[8], (3840, 127));
+        assert_eq!(values.last(), Some(&(5760, 95)));
+    }
+}
@@ -0,0 +1,156 @@
+//! The crate-wide error type.
+
+use std::fmt;
+use std::io;
+
+use crate::chord_symbol::ParseChordError;
+
+// ---------------------------------------------------------------------
+// Crate-wide error type
+// ---------------------------------------------------------------------
+
+/// Everything that can go wrong while generating or writing MIDI.
+#[derive(Debug)]
+pub enum Error {
+    /// An event was placed before the previous one, so its delta would be negative.
+    TimeUnderflow {
+        /// What was being placed.
+        context: &'static str,
+        /// Tick of the offending event.
+        tick: u32,
+        /// Tick of the event before it.
+        previous: u32,
+    },
+    /// An absolute tick does not fit in the 28 bits MIDI allows.
+    TickOverflow {
+        /// The tick that overflowed.
+        tick: u64,
+    },
+    /// A note or velocity is outside the 7-bit MIDI range 0..=127.
+    NoteOutOfRange {
+        /// The value that does not fit.
+        value: i32,
+    },
+    /// A channel outside the zero-based range 0..=15.
+    InvalidChannel {
+        /// The channel given.
+        channel: u8,
+    },
+    /// A tempo a MIDI `Tempo` event cannot hold: not positive, or a quarter
+    /// note shorter than 1 or longer than 2^24 - 1 microseconds.
+    InvalidTempo {
+        /// The tempo given, in BPM.
+        bpm: f64,
+    },
+    /// A chord that cannot be played (e.g. it has no intervals).
+    InvalidChord {
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// A strum pattern name or notation that could not be parsed.
+    InvalidPattern {
+        /// The pattern as given.
+        pattern: String,
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// A saved Markov model that could not be read back.
+    InvalidModel {
+        /// 1-based line number of the problem.
+        line: usize,
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// A song chart or arrangement that could not be read.
+    InvalidSong {
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// A groove template that could not be built or extracted.
+    InvalidGroove {
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// A note range too narrow for the part played in it.
+    InvalidRegister {
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// A MIDI file that could not be read or analysed.
+    InvalidMidi {
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// A chord symbol or progression token that could not be parsed.
+    ChordSymbol(ParseChordError),
+    /// Reading or writing a file failed.
+    Io(io::Error),
+}
+
+/// Shorthand for results carrying the crate [`Error`].
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TimeUnderflow {
+                context,
+                tick,
+                previous,
+            } => write!(
+                f,
+                "{context}: event at tick {tick} comes before the previous event at tick {previous}"
+            ),
+            Error::TickOverflow { tick } => {
+                write!(f, "tick {tick} is past the largest MIDI time (2^28 - 1)")
+            }
+            Error::NoteOutOfRange { value } => {
+                write!(f, "value {value} is outside the MIDI range 0..=127")
+            }
+            Error::InvalidChannel { channel } => {
+                write!(
+                    f,
+                    "channel {channel} is outside the zero-based range 0..=15"
+                )
+            }
+            Error::InvalidTempo { bpm } => {
+                write!(f, "tempo {bpm} BPM does not fit in a MIDI tempo event")
+            }
+            Error::InvalidChord { reason } => write!(f, "invalid chord: {reason}"),
+            Error::InvalidPattern { pattern, reason } => {
+                write!(f, "invalid strum pattern {pattern:?}: {reason}")
+            }
+            Error::InvalidModel { line, reason } => {
+                write!(f, "invalid chord model at line {line}: {reason}")
+            }
+            Error::InvalidSong { reason } => write!(f, "invalid song: {reason}"),
+            Error::InvalidGroove { reason } => write!(f, "invalid groove: {reason}"),
+            Error::InvalidRegister { reason } => write!(f, "invalid register: {reason}"),
+            Error::InvalidMidi { reason } => write!(f, "invalid MIDI file: {reason}"),
+            Error::ChordSymbol(err) => err.fmt(f),
+            Error::Io(err) => write!(f, "i/o error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ChordSymbol(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseChordError> for Error {
+    fn from(err: ParseChordError) -> Self {
+        Error::ChordSymbol(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
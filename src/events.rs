@@ -0,0 +1,119 @@
+//! Checked MIDI integers and `'static` track events.
+
+use midly::num::{u28, u4, u7};
+use midly::{MidiMessage, TrackEvent, TrackEventKind};
+
+use crate::error::{Error, Result};
+
+// ---------------------------------------------------------------------
+// Checked conversions into MIDI's restricted integer types
+// ---------------------------------------------------------------------
+
+/// Delta from `previous` to `tick`, failing instead of wrapping when `tick` is earlier.
+pub fn delta_ticks(tick: u32, previous: u32, context: &'static str) -> Result<u28> {
+    let delta = tick.checked_sub(previous).ok_or(Error::TimeUnderflow {
+        context,
+        tick,
+        previous,
+    })?;
+    tick_u28(u64::from(delta))
+}
+
+/// An absolute or relative tick as a 28-bit MIDI time.
+pub fn tick_u28(tick: u64) -> Result<u28> {
+    u32::try_from(tick)
+        .ok()
+        .and_then(u28::try_from)
+        .ok_or(Error::TickOverflow { tick })
+}
+
+/// A note number or velocity as a 7-bit MIDI value.
+pub fn value_u7(value: i32) -> Result<u7> {
+    u8::try_from(value)
+        .ok()
+        .and_then(u7::try_from)
+        .ok_or(Error::NoteOutOfRange { value })
+}
+
+/// A zero-based channel number as a 4-bit MIDI channel.
+pub fn channel_u4(channel: u8) -> Result<u4> {
+    u4::try_from(channel).ok_or(Error::InvalidChannel { channel })
+}
+
+// ---------------------------------------------------------------------
+// Helpers: create 'static TrackEvents
+// ---------------------------------------------------------------------
+
+/// A simple Note On event
+pub fn note_on_event(delta: u28, channel: u4, note: u7, velocity: u7) -> TrackEvent<'static> {
+    TrackEvent {
+        delta,
+        kind: TrackEventKind::Midi {
+            channel,
+            message: MidiMessage::NoteOn {
+                key: note,
+                vel: velocity,
+            },
+        },
+    }
+}
+
+/// A simple Note Off event
+pub fn note_off_event(delta: u28, channel: u4, note: u7, velocity: u7) -> TrackEvent<'static> {
+    TrackEvent {
+        delta,
+        kind: TrackEventKind::Midi {
+            channel,
+            message: MidiMessage::NoteOff {
+                key: note,
+                vel: velocity,
+            },
+        },
+    }
+}
+
+/// Multiply the velocity of a sounding note-on by `scale`, keeping it audible.
+pub(crate) fn scale_note_on(kind: &mut TrackEventKind, scale: f64) {
+    if let TrackEventKind::Midi {
+        message: MidiMessage::NoteOn { vel, .. },
+        ..
+    } = kind
+    {
+        if *vel > 0 {
+            let scaled = (f64::from(vel.as_int()) * scale).round().clamp(1.0, 127.0);
+            *vel = u7::from(scaled as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_conversions() {
+        assert_eq!(delta_ticks(100, 40, "test").unwrap(), u28::from(60));
+        assert!(matches!(
+            delta_ticks(40, 100, "test"),
+            Err(Error::TimeUnderflow {
+                tick: 40,
+                previous: 100,
+                ..
+            })
+        ));
+        assert!(matches!(tick_u28(1 << 28), Err(Error::TickOverflow { .. })));
+        assert_eq!(value_u7(127).unwrap(), u7::from(127));
+        assert!(matches!(
+            value_u7(128),
+            Err(Error::NoteOutOfRange { value: 128 })
+        ));
+        assert!(matches!(
+            value_u7(-1),
+            Err(Error::NoteOutOfRange { value: -1 })
+        ));
+        assert!(matches!(
+            channel_u4(16),
+            Err(Error::InvalidChannel { channel: 16 })
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+//! Seeded progressions from a weighted functional-harmony model.
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::chord::Chord;
+use crate::chord_symbol::ParseChordError;
+use crate::progression::{parse_progression, Key, Mode};
+
+// ---------------------------------------------------------------------
+// Seeded progression generator: weighted functional harmony
+// ---------------------------------------------------------------------
+
+/// Weighted moves from each scale degree (index 0 = degree 1). Tonic chords
+/// (1, 3, 6) lead to subdominants (2, 4), subdominants to dominants (5, 7),
+/// and dominants resolve home, with a few common deceptive and retrogressive
+/// moves at lower weight.
+const TRANSITIONS: [&[(u8, u32)]; 7] = [
+    &[(4, 3), (5, 3), (6, 2), (2, 2), (3, 1)],
+    &[(5, 5), (7, 1), (4, 1)],
+    &[(6, 3), (4, 2)],
+    &[(5, 4), (1, 2), (2, 2), (7, 1)],
+    &[(1, 5), (6, 2), (4, 1)],
+    &[(2, 3), (4, 3), (5, 1), (3, 1)],
+    &[(1, 4), (5, 1)],
+];
+
+/// Shape of a generated progression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeneratorSettings {
+    /// Number of bars, one chord per bar.
+    pub bars: usize,
+    /// Bars per phrase. Phrases end on a half cadence (V) and the song on an
+    /// authentic cadence (V-I); phrases shorter than two bars have no cadence
+    /// of their own.
+    pub phrase_bars: usize,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            bars: 8,
+            phrase_bars: 4,
+        }
+    }
+}
+
+/// Weight of moving from `from` to `to` (degrees 1..=7), 0 if the model never does.
+fn transition_weight(from: u8, to: u8) -> u32 {
+    TRANSITIONS[usize::from(from - 1)]
+        .iter()
+        .find(|&&(degree, _)| degree == to)
+        .map_or(0, |&(_, weight)| weight)
+}
+
+/// Scale degrees (1..=7) of a generated progression. The same settings and
+/// seed always give the same degrees.
+pub fn generate_degrees(settings: &GeneratorSettings, seed: u64) -> Vec<u8> {
+    let bars = settings.bars;
+    let phrase = settings.phrase_bars;
+
+    // Cadence chords fixed in advance; the walk steers towards them. The song
+    // opens on I, so V-I needs three bars; two bars end on a half cadence.
+    let mut forced: Vec<Option<u8>> = vec![None; bars];
+    for end in 0..bars {
+        let phrase_end = phrase >= 2 && (end + 1) % phrase == 0 && end + 2 < bars;
+        if end + 1 == bars && bars >= 3 {
+            forced[end - 1] = Some(5);
+            forced[end] = Some(1);
+        } else if phrase_end || end + 1 == bars {
+            forced[end] = Some(5);
+        }
+    }
+    if let Some(first) = forced.first_mut() {
+        *first = Some(1);
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut degrees: Vec<u8> = Vec::with_capacity(bars);
+    for bar in 0..bars {
+        let degree = match (forced[bar], degrees.last()) {
+            (Some(degree), _) => degree,
+            (None, None) => 1,
+            (None, Some(&previous)) => {
+                let choices = TRANSITIONS[usize::from(previous - 1)];
+                // Favour chords that lead well into a fixed chord in the next bar.
+                let ahead = forced.get(bar + 1).copied().flatten();
+                let steered: Vec<u32> = choices
+                    .iter()
+                    .map(|&(degree, weight)| {
+                        ahead.map_or(weight, |next| weight * transition_weight(degree, next))
+                    })
+                    .collect();
+                let weights: Vec<u32> = if steered.iter().any(|&w| w > 0) {
+                    steered
+                } else {
+                    choices.iter().map(|&(_, weight)| weight).collect()
+                };
+                let index = WeightedIndex::new(&weights)
+                    .expect("every degree has a weighted move")
+                    .sample(&mut rng);
+                choices[index].0
+            }
+        };
+        degrees.push(degree);
+    }
+    degrees
+}
+
+/// Roman numeral of the diatonic triad on `degree` (1..=7) of `key`: upper
+/// case for major, lower case for minor, "°" for diminished.
+pub fn diatonic_numeral(key: Key, degree: u8) -> String {
+    const NUMERALS: [&str; 7] = ["I", "II", "III", "IV", "V", "VI", "VII"];
+    let scale = key.mode.degrees();
+    let index = usize::from((degree - 1) % 7);
+    let step = |offset: usize| (scale[(index + offset) % 7] + 12 - scale[index]) % 12;
+    let numeral = NUMERALS[index];
+    match (step(2), step(4)) {
+        (4, _) => numeral.to_string(),
+        (3, 6) => format!("{}°", numeral.to_lowercase()),
+        _ => numeral.to_lowercase(),
+    }
+}
+
+/// Roman numerals of a generated progression in `key`. In minor keys the
+/// dominant is major, as in harmonic minor, so cadences still resolve.
+pub fn generate_numerals(settings: &GeneratorSettings, key: Key, seed: u64) -> Vec<String> {
+    generate_degrees(settings, seed)
+        .into_iter()
+        .map(|degree| {
+            if key.mode == Mode::Minor && degree == 5 {
+                "V".to_string()
+            } else {
+                diatonic_numeral(key, degree)
+            }
+        })
+        .collect()
+}
+
+/// A generated progression in `key` as chords.
+pub fn generate_progression(
+    settings: &GeneratorSettings,
+    key: Key,
+    seed: u64,
+) -> Result<Vec<Chord>, ParseChordError> {
+    parse_progression(&generate_numerals(settings, key, seed).join(" "), key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_progression() {
+        let settings = GeneratorSettings {
+            bars: 16,
+            phrase_bars: 4,
+        };
+        let first = generate_degrees(&settings, 42);
+        assert_eq!(first, generate_degrees(&settings, 42));
+        let others = (0..8).filter(|&seed| generate_degrees(&settings, seed) != first);
+        assert!(others.count() > 0);
+    }
+
+    #[test]
+    fn test_cadences_at_phrase_ends() {
+        let settings = GeneratorSettings {
+            bars: 12,
+            phrase_bars: 4,
+        };
+        for seed in 0..20 {
+            let degrees = generate_degrees(&settings, seed);
+            assert_eq!(degrees.len(), 12);
+            assert_eq!(degrees[0], 1);
+            assert_eq!(degrees[3], 5, "half cadence, seed {seed}: {degrees:?}");
+            assert_eq!(degrees[7], 5);
+            assert_eq!(&degrees[10..], &[5, 1], "authentic cadence, seed {seed}");
+            for pair in degrees.windows(2) {
+                assert_ne!(pair[0], pair[1], "seed {seed}: {degrees:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_short_phrases_and_songs_get_one_cadence() {
+        let one_bar_phrases = GeneratorSettings {
+            bars: 8,
+            phrase_bars: 1,
+        };
+        for seed in 0..20 {
+            let degrees = generate_degrees(&one_bar_phrases, seed);
+            assert_eq!(&degrees[6..], &[5, 1], "seed {seed}: {degrees:?}");
+            assert!(
+                degrees[1..6].iter().any(|&degree| degree != 5),
+                "seed {seed}"
+            );
+            for pair in degrees.windows(2) {
+                assert_ne!(pair[0], pair[1], "seed {seed}: {degrees:?}");
+            }
+        }
+        let bars = |bars| {
+            generate_degrees(
+                &GeneratorSettings {
+                    bars,
+                    phrase_bars: 4,
+                },
+                0,
+            )
+        };
+        assert_eq!(bars(1), vec![1]);
+        assert_eq!(bars(2), vec![1, 5]);
+        assert_eq!(bars(3), vec![1, 5, 1]);
+    }
+
+    #[test]
+    fn test_numerals_follow_mode() {
+        let major: Key = "C".parse().unwrap();
+        let numerals: Vec<String> = (1..=7).map(|d| diatonic_numeral(major, d)).collect();
+        assert_eq!(numerals, ["I", "ii", "iii", "IV", "V", "vi", "vii°"]);
+
+        let minor: Key = "A minor".parse().unwrap();
+        assert_eq!(diatonic_numeral(minor, 5), "v");
+        let chords = generate_progression(&GeneratorSettings::default(), minor, 3).unwrap();
+        // The closing V-i has a major dominant: E G# B.
+        let dominant = &chords[chords.len() - 2];
+        assert_eq!(dominant.root % 12, 4);
+        assert_eq!(dominant.intervals, vec![0, 4, 7]);
+        assert_eq!(chords.last().unwrap().intervals, vec![0, 3, 7]);
+    }
+}
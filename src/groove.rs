@@ -0,0 +1,446 @@
+//! Swing, shuffle and groove templates, including ones taken from MIDI files.
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use midly::num::u7;
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::error::{Error, Result};
+use crate::timeline::Timeline;
+
+// ---------------------------------------------------------------------
+// Groove: swing, shuffle and timing/velocity templates on the 16th grid
+// ---------------------------------------------------------------------
+
+/// How one sixteenth of a groove template is played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrooveStep {
+    /// Timing offset as a fraction of a sixteenth; positive is late. Notes
+    /// keep their order as long as no step is more than a whole sixteenth
+    /// earlier than the one before it.
+    pub timing: f64,
+    /// Velocity scale for notes on this step (1.0 = unchanged).
+    pub velocity: f64,
+}
+
+impl Default for GrooveStep {
+    fn default() -> Self {
+        GrooveStep {
+            timing: 0.0,
+            velocity: 1.0,
+        }
+    }
+}
+
+/// Which notes swing moves: the off-beat eighths or every other sixteenth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwingGrid {
+    /// Delay the off-beat eighths.
+    Eighth,
+    /// Delay every other sixteenth.
+    Sixteenth,
+}
+
+/// Timing and velocity offsets per sixteenth, repeating every `steps.len()`
+/// sixteenths from the start of the track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Groove {
+    /// Offsets for each sixteenth, from the first of the pattern.
+    pub steps: Vec<GrooveStep>,
+}
+
+/// How early a played note can be and still count toward a step when a groove
+/// is extracted; anything up to a sixteenth after that belongs to the step
+/// too. Grooves lean late far more than early, and a triplet shuffle sits two
+/// thirds of a sixteenth behind the grid.
+const EARLIEST: f64 = 0.25;
+
+/// Built-in templates, by name, as (timing, velocity) per sixteenth of a 4/4 bar.
+const TEMPLATES: [(&str, [(f64, f64); 16]); 2] = [
+    // Backbeats dragged late, ghosted sixteenths.
+    (
+        "laid-back",
+        [
+            (0.0, 1.0),
+            (0.05, 0.7),
+            (0.1, 0.85),
+            (0.05, 0.7),
+            (0.2, 1.05),
+            (0.05, 0.7),
+            (0.1, 0.85),
+            (0.05, 0.7),
+            (0.0, 1.0),
+            (0.05, 0.7),
+            (0.1, 0.85),
+            (0.05, 0.7),
+            (0.2, 1.05),
+            (0.05, 0.7),
+            (0.1, 0.85),
+            (0.05, 0.7),
+        ],
+    ),
+    // Off-beats rushed, downbeats accented.
+    (
+        "push",
+        [
+            (0.0, 1.1),
+            (-0.05, 0.75),
+            (-0.15, 0.9),
+            (-0.05, 0.75),
+            (0.0, 1.0),
+            (-0.05, 0.75),
+            (-0.15, 0.9),
+            (-0.05, 0.75),
+            (0.0, 1.05),
+            (-0.05, 0.75),
+            (-0.15, 0.9),
+            (-0.05, 0.75),
+            (0.0, 1.0),
+            (-0.05, 0.75),
+            (-0.15, 0.9),
+            (-0.05, 0.75),
+        ],
+    ),
+];
+
+impl Groove {
+    /// Swing at `percent` (50 = straight, 66.7 = triplet feel, capped at 72):
+    /// where the second of each pair of eighths (or sixteenths) falls, as a
+    /// percentage of the pair.
+    pub fn swing(percent: f64, grid: SwingGrid) -> Self {
+        let late = (percent.clamp(50.0, 72.0) / 100.0) * 2.0 - 1.0;
+        let timing: &[f64] = match grid {
+            SwingGrid::Eighth => &[0.0, late, late * 2.0, late],
+            SwingGrid::Sixteenth => &[0.0, late],
+        };
+        Groove {
+            steps: timing
+                .iter()
+                .map(|&timing| GrooveStep {
+                    timing,
+                    ..GrooveStep::default()
+                })
+                .collect(),
+        }
+    }
+
+    /// Triplet shuffle: off-beat eighths on the last triplet of each beat.
+    pub fn shuffle() -> Self {
+        Groove::swing(200.0 / 3.0, SwingGrid::Eighth)
+    }
+
+    /// A built-in template: "straight", "swing", "swing16", "shuffle",
+    /// "laid-back" or "push".
+    pub fn named(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "straight" => Some(Groove::swing(50.0, SwingGrid::Eighth)),
+            "swing" => Some(Groove::swing(60.0, SwingGrid::Eighth)),
+            "swing16" => Some(Groove::swing(58.0, SwingGrid::Sixteenth)),
+            "shuffle" => Some(Groove::shuffle()),
+            other => TEMPLATES
+                .iter()
+                .find(|(name, _)| *name == other)
+                .map(|(_, steps)| Groove {
+                    steps: steps
+                        .iter()
+                        .map(|&(timing, velocity)| GrooveStep { timing, velocity })
+                        .collect(),
+                }),
+        }
+    }
+
+    /// Extract a template of `steps` sixteenths, one measure of the song it
+    /// will be applied to, from `smf`: the average offset of the note-ons
+    /// played on each step (from a quarter sixteenth early to three quarters
+    /// late), and their average velocity relative to the whole file. Steps no
+    /// note falls on stay straight. The file's measure, from its first time
+    /// signature (4/4 without one), must be `steps` sixteenths long too.
+    pub fn extract(smf: &Smf, steps: usize) -> Result<Self> {
+        let invalid = |reason: &str| Error::InvalidGroove {
+            reason: reason.to_string(),
+        };
+        let Timing::Metrical(ppq) = smf.header.timing else {
+            return Err(invalid("timecode-based files have no beat grid"));
+        };
+        let sixteenth = f64::from(ppq.as_int()) / 4.0;
+        let steps = steps.max(1);
+
+        let (numerator, denominator) = first_time_signature(smf);
+        let measure = 16.0 * f64::from(numerator) / denominator;
+        if measure != steps as f64 {
+            return Err(invalid(&format!(
+                "the file's {numerator}/{denominator} measure is {measure} sixteenths, \
+                 not the {steps} of the song's"
+            )));
+        }
+
+        // Per step: summed offset, summed velocity, note count.
+        let mut totals = vec![(0.0, 0.0, 0u32); steps];
+        for track in &smf.tracks {
+            let mut tick = 0u64;
+            for event in track {
+                tick += u64::from(event.delta.as_int());
+                if let TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { vel, .. },
+                    ..
+                } = event.kind
+                {
+                    if vel == 0 {
+                        continue;
+                    }
+                    let position = tick as f64 / sixteenth;
+                    let step = (position + EARLIEST).floor();
+                    let total = &mut totals[step as usize % steps];
+                    total.0 += position - step;
+                    total.1 += f64::from(vel.as_int());
+                    total.2 += 1;
+                }
+            }
+        }
+
+        let notes: u32 = totals.iter().map(|total| total.2).sum();
+        if notes == 0 {
+            return Err(invalid("no notes to take the groove from"));
+        }
+        let average = totals.iter().map(|total| total.1).sum::<f64>() / f64::from(notes);
+        Ok(Groove {
+            steps: totals
+                .iter()
+                .map(|&(offset, velocity, count)| match count {
+                    0 => GrooveStep::default(),
+                    count => GrooveStep {
+                        timing: offset / f64::from(count),
+                        velocity: velocity / f64::from(count) / average,
+                    },
+                })
+                .collect(),
+        })
+    }
+
+    /// Extract a template from the MIDI file at `path` (see [`Groove::extract`]).
+    pub fn from_midi_file(path: impl AsRef<Path>, steps: usize) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        let smf = Smf::parse(&bytes).map_err(|err| Error::InvalidGroove {
+            reason: format!("cannot read MIDI file: {err}"),
+        })?;
+        Groove::extract(&smf, steps)
+    }
+
+    fn step(&self, index: i64) -> GrooveStep {
+        let len = self.steps.len().max(1) as i64;
+        self.steps
+            .get(index.rem_euclid(len) as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Where `tick` lands in the groove. Times between sixteenths move by an
+    /// amount interpolated between their neighbours, so a note ending where
+    /// the next begins still meets it.
+    fn warp(&self, tick: u32, sixteenth: f64) -> u32 {
+        let position = f64::from(tick) / sixteenth;
+        let index = position.floor();
+        let fraction = position - index;
+        let timing = |index: f64| self.step(index as i64).timing;
+        let offset = timing(index) * (1.0 - fraction) + timing(index + 1.0) * fraction;
+        ((position + offset) * sixteenth).round().max(0.0) as u32
+    }
+
+    /// Move every channel event of `timeline` onto the groove and scale
+    /// note-on velocities by the step they fall nearest to. `ppq` sets the
+    /// length of a sixteenth; meta events stay where they are.
+    pub fn apply(&self, timeline: &mut Timeline, ppq: u16) {
+        if self.steps.is_empty() {
+            return;
+        }
+        let sixteenth = f64::from(ppq) / 4.0;
+        for event in timeline.iter_mut() {
+            let TrackEventKind::Midi { message, .. } = &mut event.kind else {
+                continue;
+            };
+            if let MidiMessage::NoteOn { vel, .. } = message {
+                if *vel > 0 {
+                    let nearest = (f64::from(event.tick) / sixteenth).round() as i64;
+                    let scaled = f64::from(vel.as_int()) * self.step(nearest).velocity;
+                    *vel = u7::from(scaled.round().clamp(1.0, 127.0) as u8);
+                }
+            }
+            event.tick = self.warp(event.tick, sixteenth);
+        }
+    }
+}
+
+impl FromStr for Groove {
+    type Err = String;
+
+    /// A template name, or `swing:<percent>` / `swing16:<percent>`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((kind, percent)) = s.split_once(':') {
+            let percent: f64 = percent
+                .parse()
+                .map_err(|_| format!("bad swing percentage {percent:?}"))?;
+            return match kind.to_ascii_lowercase().as_str() {
+                "swing" => Ok(Groove::swing(percent, SwingGrid::Eighth)),
+                "swing16" => Ok(Groove::swing(percent, SwingGrid::Sixteenth)),
+                _ => Err(format!("unknown groove {s:?}")),
+            };
+        }
+        Groove::named(s).ok_or_else(|| format!("unknown groove {s:?}"))
+    }
+}
+
+/// Numerator and denominator of the earliest time signature in `smf`, or 4/4
+/// when it has none.
+fn first_time_signature(smf: &Smf) -> (u8, f64) {
+    let mut first = None;
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        for event in track {
+            tick += u64::from(event.delta.as_int());
+            if let TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, power, ..)) =
+                event.kind
+            {
+                if first.is_none_or(|(earliest, _, _)| tick < earliest) {
+                    first = Some((tick, numerator, power));
+                }
+                break;
+            }
+        }
+    }
+    first.map_or((4, 4.0), |(_, numerator, power)| {
+        (numerator, 2f64.powi(i32::from(power)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::u4;
+    use midly::{Format, Header};
+
+    fn note_ons(timeline: &Timeline) -> Vec<(u32, u8)> {
+        let mut ons: Vec<(u32, u8)> = timeline
+            .iter()
+            .filter_map(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { vel, .. },
+                    ..
+                } => Some((ev.tick, vel.as_int())),
+                _ => None,
+            })
+            .collect();
+        ons.sort_unstable();
+        ons
+    }
+
+    fn eighths() -> Timeline<'static> {
+        let mut timeline = Timeline::new();
+        for step in 0..4 {
+            timeline.note(step * 240, 240, u4::from(0), u7::from(60), u7::from(100));
+        }
+        timeline
+    }
+
+    #[test]
+    fn test_swing_and_shuffle_move_the_offbeats() {
+        let mut swung = eighths();
+        Groove::shuffle().apply(&mut swung, 480);
+        let ticks: Vec<u32> = note_ons(&swung).iter().map(|on| on.0).collect();
+        assert_eq!(ticks, [0, 320, 480, 800]);
+        // Each note still ends where the next begins.
+        let offs: Vec<u32> = swung
+            .iter()
+            .filter(|ev| {
+                matches!(
+                    ev.kind,
+                    TrackEventKind::Midi {
+                        message: MidiMessage::NoteOff { .. },
+                        ..
+                    }
+                )
+            })
+            .map(|ev| ev.tick)
+            .collect();
+        assert_eq!(offs, [320, 480, 800, 960]);
+
+        let mut straight = eighths();
+        "straight"
+            .parse::<Groove>()
+            .unwrap()
+            .apply(&mut straight, 480);
+        assert_eq!(straight, eighths());
+        let mut sixteenths = eighths();
+        "swing16:75"
+            .parse::<Groove>()
+            .unwrap()
+            .apply(&mut sixteenths, 480);
+        assert_eq!(note_ons(&sixteenths)[1].0, 240);
+        assert!("swing:fast".parse::<Groove>().is_err());
+        assert!("samba".parse::<Groove>().is_err());
+    }
+
+    #[test]
+    fn test_extract_and_apply_a_template() {
+        // A played part: the "and" of each beat a little late and soft.
+        let mut played = Timeline::new();
+        for beat in 0..8 {
+            let channel = u4::from(9);
+            played.note(beat * 480, 100, channel, u7::from(36), u7::from(120));
+            played.note(beat * 480 + 270, 100, channel, u7::from(42), u7::from(60));
+        }
+        let smf = Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(480.into())),
+            tracks: vec![played.to_track().unwrap()],
+        };
+        let groove = Groove::extract(&smf, 16).unwrap();
+        assert_eq!(groove.steps[0].timing, 0.0);
+        assert!((groove.steps[2].timing - 0.25).abs() < 1e-9);
+        assert!((groove.steps[0].velocity - 120.0 / 90.0).abs() < 1e-9);
+        assert_eq!(groove.steps[1], GrooveStep::default());
+
+        let mut ours = eighths();
+        groove.apply(&mut ours, 480);
+        assert_eq!(
+            note_ons(&ours),
+            [(0, 127), (270, 67), (480, 127), (750, 67)]
+        );
+
+        let empty = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(480.into()),
+        ));
+        assert!(matches!(
+            Groove::extract(&empty, 16),
+            Err(Error::InvalidGroove { .. })
+        ));
+    }
+
+    #[test]
+    fn test_extract_needs_the_files_measure_to_match() {
+        let mut played = Timeline::new();
+        played.push(
+            0,
+            TrackEventKind::Meta(MetaMessage::TimeSignature(3, 2, 24, 8)),
+        );
+        for beat in 0..6 {
+            let channel = u4::from(9);
+            played.note(beat * 480 + 30, 100, channel, u7::from(36), u7::from(100));
+        }
+        let smf = Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(480.into())),
+            tracks: vec![played.to_track().unwrap()],
+        };
+        assert!(matches!(
+            Groove::extract(&smf, 16),
+            Err(Error::InvalidGroove { .. })
+        ));
+        let groove = Groove::extract(&smf, 12).unwrap();
+        assert_eq!(groove.steps.len(), 12);
+        assert!([0, 4, 8]
+            .iter()
+            .all(|&step| (groove.steps[step].timing - 0.25).abs() < 1e-9));
+    }
+}
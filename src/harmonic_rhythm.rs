@@ -0,0 +1,136 @@
+//! Where each chord of a progression sounds, in ticks.
+
+use crate::chord::Chord;
+use crate::tempo::Meter;
+
+// ---------------------------------------------------------------------
+// Harmonic rhythm: where each chord of a progression sounds
+// ---------------------------------------------------------------------
+
+/// The ticks one chord of a progression occupies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChordSpan {
+    /// First tick the chord sounds: its downbeat, or earlier when pushed.
+    pub start: u64,
+    /// Where the chord would start without a push.
+    pub downbeat: u64,
+    /// Where the next chord takes over (or the progression ends).
+    pub end: u64,
+}
+
+impl ChordSpan {
+    /// Whether the chord sounds at `tick`.
+    pub fn contains(&self, tick: u64) -> bool {
+        (self.start..self.end).contains(&tick)
+    }
+}
+
+/// Length of the progression in measures.
+pub fn total_measures(chords: &[Chord]) -> f64 {
+    chords.iter().map(|chord| chord.measures.max(0.0)).sum()
+}
+
+/// Number of bars the progression touches, counting a partly filled last bar.
+pub fn measure_count(chords: &[Chord]) -> usize {
+    (total_measures(chords) - 1e-9).ceil().max(0.0) as usize
+}
+
+/// Lay `chords` out from `start_tick`, each lasting its `measures` of `meter`.
+/// A pushed chord starts [`Meter::push_ticks`] early and shortens the one
+/// before it; a push on the first chord has nothing to borrow from and is
+/// ignored.
+pub fn chord_spans(chords: &[Chord], start_tick: u32, meter: Meter) -> Vec<ChordSpan> {
+    let ticks_per_measure = meter.ticks_per_measure();
+    let tick_at = |position: f64| {
+        u64::from(start_tick) + (position.max(0.0) * f64::from(ticks_per_measure)).round() as u64
+    };
+
+    let mut spans: Vec<ChordSpan> = Vec::with_capacity(chords.len());
+    let mut position = 0.0;
+    for chord in chords {
+        let downbeat = tick_at(position);
+        let start = match spans.last() {
+            Some(previous) if chord.push => downbeat
+                .saturating_sub(u64::from(meter.push_ticks()))
+                .max(previous.start),
+            _ => downbeat,
+        };
+        if let Some(previous) = spans.last_mut() {
+            previous.end = start;
+        }
+        position += chord.measures.max(0.0);
+        spans.push(ChordSpan {
+            start,
+            downbeat,
+            end: tick_at(position),
+        });
+    }
+    spans
+}
+
+/// Index of the span sounding at `tick`, if any.
+pub fn chord_at(spans: &[ChordSpan], tick: u64) -> Option<usize> {
+    let index = spans
+        .partition_point(|span| span.start <= tick)
+        .checked_sub(1)?;
+    spans[index].contains(tick).then_some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progression::parse_progression;
+
+    fn chord(measures: f64, push: bool) -> Chord {
+        Chord {
+            measures,
+            push,
+            ..Chord::new(60, vec![0, 4, 7])
+        }
+    }
+
+    #[test]
+    fn test_spans_follow_durations_and_pushes() {
+        let chords = [
+            chord(0.5, true),
+            chord(0.5, false),
+            chord(2.0, true),
+            chord(0.25, false),
+        ];
+        assert_eq!(total_measures(&chords), 3.25);
+        assert_eq!(measure_count(&chords), 4);
+        let spans = chord_spans(&chords, 100, Meter::default());
+        let starts: Vec<(u64, u64, u64)> = spans
+            .iter()
+            .map(|span| (span.start, span.downbeat, span.end))
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                (100, 100, 1060),
+                (1060, 1060, 1780),
+                (1780, 2020, 5860),
+                (5860, 5860, 6340)
+            ]
+        );
+        assert_eq!(chord_at(&spans, 99), None);
+        assert_eq!(chord_at(&spans, 1780), Some(2));
+        assert_eq!(chord_at(&spans, 1779), Some(1));
+        assert_eq!(chord_at(&spans, 6340), None);
+    }
+
+    #[test]
+    fn test_push_is_an_eighth_note_in_any_meter() {
+        let key = "C".parse().unwrap();
+        let starts = |chart: &str, time_signature: (u8, u8)| -> Vec<u64> {
+            let chords = parse_progression(chart, key).unwrap();
+            chord_spans(&chords, 0, Meter::new(480, time_signature))
+                .iter()
+                .map(|span| span.start)
+                .collect()
+        };
+        assert_eq!(starts("| C . <G |", (3, 4)), [0, 720]);
+        assert_eq!(starts("| C <G |", (6, 8)), [0, 480]);
+        assert_eq!(starts("| C <G |", (4, 4)), [0, 720]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Seeded timing and velocity variation.
+
+use std::collections::BTreeMap;
+use std::f64::consts::TAU;
+use std::str::FromStr;
+
+use midly::num::u7;
+use midly::{MidiMessage, TrackEventKind};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::timeline::Timeline;
+
+// ---------------------------------------------------------------------
+// Humanize: seeded timing and velocity variation
+// ---------------------------------------------------------------------
+
+/// Shape of the random offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Distribution {
+    /// Every offset within the bound is equally likely.
+    #[default]
+    Uniform,
+    /// Most offsets stay small; the bound sits at two standard deviations
+    /// and nothing goes past it.
+    Gaussian,
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "uniform" => Ok(Distribution::Uniform),
+            "gaussian" | "normal" => Ok(Distribution::Gaussian),
+            other => Err(format!("unknown distribution {other:?}")),
+        }
+    }
+}
+
+/// Random, reproducible variation in when notes start and how hard they are
+/// played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Humanize {
+    /// Largest shift of a note, earlier or later, in ticks.
+    pub timing: u32,
+    /// Largest change of a note-on velocity, up or down.
+    pub velocity: u8,
+    /// Shape of the random offsets.
+    pub distribution: Distribution,
+    /// Seed for the offsets; the same seed gives the same performance.
+    pub seed: u64,
+}
+
+impl Default for Humanize {
+    fn default() -> Self {
+        Humanize {
+            timing: 10,
+            velocity: 8,
+            distribution: Distribution::default(),
+            seed: 0,
+        }
+    }
+}
+
+/// The SplitMix64 finaliser: nearby inputs give unrelated outputs.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A note-on and the note-off that ends it, as positions in the sorted timeline.
+struct Note {
+    on: usize,
+    off: Option<usize>,
+    start: u32,
+    end: u32,
+}
+
+impl Humanize {
+    /// A random offset within `-bound..=bound`.
+    fn offset(&self, rng: &mut StdRng, bound: f64) -> f64 {
+        match self.distribution {
+            Distribution::Uniform => rng.gen_range(-bound..=bound),
+            Distribution::Gaussian => {
+                // Box-Muller; `1.0 - gen()` keeps the logarithm away from zero.
+                let radius = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt();
+                let normal = radius * (TAU * rng.gen::<f64>()).cos();
+                (normal * bound / 2.0).clamp(-bound, bound)
+            }
+        }
+    }
+
+    /// Shift each note of `timeline` (note-on and note-off together) and vary
+    /// its velocity. `stream` gives each part its own sequence from the same
+    /// seed. Nothing moves before tick 0, and a note never starts before, or
+    /// still sounds over, the previous note of the same pitch on its channel:
+    /// that note is cut short instead. Other events stay where they are.
+    pub fn apply(&self, timeline: &mut Timeline, stream: u64) {
+        let mut rng = StdRng::seed_from_u64(mix(mix(self.seed) ^ stream));
+        timeline.sort();
+        let mut events: Vec<_> = timeline.iter_mut().collect();
+
+        // Pair each note-on with the first note-off of its channel and key.
+        let mut notes: Vec<Note> = Vec::new();
+        let mut sounding: BTreeMap<(u8, u8), Vec<usize>> = BTreeMap::new();
+        for (index, event) in events.iter().enumerate() {
+            let TrackEventKind::Midi { channel, message } = event.kind else {
+                continue;
+            };
+            match message {
+                MidiMessage::NoteOn { key, vel } if vel > 0 => {
+                    sounding
+                        .entry((channel.as_int(), key.as_int()))
+                        .or_default()
+                        .push(notes.len());
+                    notes.push(Note {
+                        on: index,
+                        off: None,
+                        start: event.tick,
+                        end: event.tick,
+                    });
+                }
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                    let open = sounding
+                        .entry((channel.as_int(), key.as_int()))
+                        .or_default();
+                    if !open.is_empty() {
+                        let note = &mut notes[open.remove(0)];
+                        note.off = Some(index);
+                        note.end = event.tick;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Move every note, in the order they were played.
+        let mut previous: BTreeMap<(u8, u8), usize> = BTreeMap::new();
+        for current in 0..notes.len() {
+            let shift = self.offset(&mut rng, f64::from(self.timing)).round() as i64;
+            let change = self.offset(&mut rng, f64::from(self.velocity)).round() as i32;
+
+            let note = &notes[current];
+            let TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, vel },
+            } = events[note.on].kind
+            else {
+                continue;
+            };
+            let pitch = (channel.as_int(), key.as_int());
+            let length = note.end - note.start;
+            let mut start = (i64::from(note.start) + shift).max(0) as u32;
+            if let Some(&before) = previous.get(&pitch) {
+                let before: &mut Note = &mut notes[before];
+                start = start.max(before.start + 1);
+                before.end = before.end.min(start);
+            }
+
+            let note = &mut notes[current];
+            note.start = start;
+            note.end = start + length;
+            let vel = (i32::from(vel.as_int()) + change).clamp(1, 127);
+            events[note.on].kind = TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key,
+                    vel: u7::from(vel as u8),
+                },
+            };
+            previous.insert(pitch, current);
+        }
+
+        for note in &notes {
+            events[note.on].tick = note.start;
+            if let Some(off) = note.off {
+                events[off].tick = note.end;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::u4;
+
+    /// (start, end, key, velocity) of every note, in order.
+    fn notes(timeline: &Timeline) -> Vec<(u32, u32, u8, u8)> {
+        let mut sorted = timeline.clone();
+        sorted.sort();
+        let mut open: BTreeMap<u8, Vec<(u32, u8)>> = BTreeMap::new();
+        let mut notes = Vec::new();
+        for ev in sorted.iter() {
+            match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, vel },
+                    ..
+                } => open
+                    .entry(key.as_int())
+                    .or_default()
+                    .push((ev.tick, vel.as_int())),
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOff { key, .. },
+                    ..
+                } => {
+                    let (start, vel) = open.get_mut(&key.as_int()).unwrap().remove(0);
+                    notes.push((start, ev.tick, key.as_int(), vel));
+                }
+                _ => {}
+            }
+        }
+        notes.sort_unstable();
+        notes
+    }
+
+    /// Repeated sixteenths on two pitches, each ending where the next begins.
+    fn part() -> Timeline<'static> {
+        let mut timeline = Timeline::new();
+        for step in 0..64 {
+            let key = u7::from(if step % 2 == 0 { 60 } else { 64 });
+            timeline.note(step * 60, 120, u4::from(0), key, u7::from(100));
+        }
+        timeline
+    }
+
+    #[test]
+    fn test_humanize_is_bounded_and_seeded() {
+        for distribution in [Distribution::Uniform, Distribution::Gaussian] {
+            let humanize = Humanize {
+                timing: 12,
+                velocity: 10,
+                distribution,
+                seed: 5,
+            };
+            let mut first = part();
+            humanize.apply(&mut first, 0);
+            let mut again = part();
+            humanize.apply(&mut again, 0);
+            assert_eq!(first, again);
+            let mut other = part();
+            humanize.apply(&mut other, 1);
+            assert_ne!(first, other);
+            // Neighbouring seeds do not share each other's streams.
+            let mut next_seed = part();
+            Humanize {
+                seed: 4,
+                ..humanize
+            }
+            .apply(&mut next_seed, 1);
+            assert_ne!(first, next_seed);
+
+            let played = notes(&first);
+            assert_ne!(notes(&part()), played);
+            for (step, &(start, _, _, vel)) in played.iter().enumerate() {
+                assert!(start.abs_diff(step as u32 * 60) <= 12, "{distribution:?}");
+                assert!((90..=110).contains(&vel), "{distribution:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_humanize_never_goes_negative_or_overlaps() {
+        let humanize = Humanize {
+            timing: 200,
+            velocity: 127,
+            distribution: Distribution::Uniform,
+            seed: 9,
+        };
+        let mut timeline = part();
+        humanize.apply(&mut timeline, 0);
+        assert!(timeline.to_track().is_ok());
+        let played = notes(&timeline);
+        assert_eq!(played.len(), 64);
+        for key in [60, 64] {
+            let same: Vec<_> = played.iter().filter(|note| note.2 == key).collect();
+            for pair in same.windows(2) {
+                assert!(pair[0].1 <= pair[1].0, "{pair:?}");
+                assert!(pair[0].0 < pair[0].1, "{pair:?}");
+            }
+        }
+        assert!(played.iter().all(|note| (1..=127).contains(&note.3)));
+    }
+}
@@ -0,0 +1,73 @@
+//! Chord-track MIDI generation.
+//!
+//! The crate turns chord progressions into Standard MIDI Files:
+//!
+//! - [`chord`]: the [`Chord`] model (root, intervals, slash bass, duration and push).
+//! - [`chord_symbol`]: parse chord symbols such as `"Am7"` or `"D/F#"`.
+//! - [`progression`]: keys, modes and key-relative progressions (`"I vi IV V"`, `"1 6m 4 5"`).
+//! - [`scale`]: scales and modes, their diatonic triads and seventh chords, and pitch quantizing.
+//! - [`harmonic_rhythm`]: where each chord sounds, from its duration and any push.
+//! - [`generator`]: seeded progressions from a weighted functional-harmony model.
+//! - [`markov`]: progressions sampled from a Markov model trained on chord charts.
+//! - [`song`]: songs as named sections (progression, pattern, length, dynamics) in an arrangement.
+//! - [`pattern`]: strum patterns, by name or in compact notation (`"D.DU.UDU"`).
+//! - [`voicing`]: inversions, drop-2/drop-3, spread, shell and quartal voicings in a register.
+//! - [`voice_leading`]: choose voicings that move each voice as little as possible.
+//! - [`strum`]: strummed chord-track note events.
+//! - [`bass`]: bass lines following the chords (root-fifth, walking, octave pump, one-drop).
+//! - [`drums`]: General MIDI drum grooves with section fills, on channel 10.
+//! - [`melody`]: seeded lead melodies: chord tones on the beat, scale steps between, phrase contours.
+//! - [`arpeggio`]: arpeggiated chord tracks (up, down, up-down, random, as played).
+//! - [`dynamics`]: beat accent maps, velocity envelopes (crescendo, decrescendo) and CC11 expression.
+//! - [`groove`]: swing, triplet shuffle and timing/velocity templates, also extracted from MIDI files.
+//! - [`humanize`]: seeded, bounded timing and velocity variation, uniform or Gaussian.
+//! - [`analysis`]: detect the key and the chords of an existing MIDI file, per beat or bar.
+//! - [`tempo`]: tempo map, time signature and key signature meta events.
+//! - [`timeline`]: events at absolute ticks, merged and sorted before delta conversion.
+//! - [`events`]: helpers for building `'static` midly track events and checked MIDI integers.
+//! - [`error`]: the crate-wide [`Error`] type.
+//! - [`smf`]: assemble generated events into an [`Smf`](midly::Smf).
+//!
+//! ```
+//! use mdmidio1p::progression::{parse_progression, Key};
+//! use mdmidio1p::smf::{build_chord_smf, SmfSettings};
+//!
+//! let key: Key = "G".parse().unwrap();
+//! let chords = parse_progression("I vi IV V", key).unwrap();
+//! let smf = build_chord_smf(&chords, &SmfSettings::default()).unwrap();
+//! assert_eq!(smf.tracks.len(), 1);
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod analysis;
+pub mod arpeggio;
+pub mod bass;
+pub mod chord;
+pub mod chord_symbol;
+pub mod drums;
+pub mod dynamics;
+pub mod error;
+pub mod events;
+pub mod generator;
+pub mod groove;
+pub mod harmonic_rhythm;
+pub mod humanize;
+pub mod markov;
+pub mod melody;
+pub mod pattern;
+pub mod progression;
+pub mod scale;
+pub mod smf;
+pub mod song;
+pub mod strum;
+pub mod tempo;
+pub mod timeline;
+pub mod voice_leading;
+pub mod voicing;
+
+pub use chord::Chord;
+pub use error::{Error, Result};
+pub use pattern::StrumPattern;
+pub use strum::generate_chord_track_events;
+pub use timeline::Timeline;
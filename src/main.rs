@@ -1,208 +1,420 @@
-use midly::{
-    Format, Header, MetaMessage, MidiMessage, Smf, Timing, Track, TrackEvent,
-    TrackEventKind,
-};
-use midly::num::{u4, u7, u28, u15};
-use std::io::Result as IoResult;
+use midly::{MetaMessage, MidiMessage, Smf, TrackEvent, TrackEventKind};
+use std::error::Error;
 
-// ---------------------------------------------------------------------
-// 1) Debug helper: safe_sub_u28
-// ---------------------------------------------------------------------
-fn safe_sub_u28(a: u28, b: u28, context: &str) -> u28 {
-    if b > a {
-        eprintln!("DEBUG: Underflow about to happen in {context}!");
-        eprintln!("DEBUG:   a={:?}, b={:?}", a, b);
-        panic!("attempting to subtract with overflow in {context}!");
-    }
-    a - b
-}
+use mdmidio1p::analysis::analyze;
+use mdmidio1p::arpeggio::ArpMode;
+use mdmidio1p::chord::Chord;
+use mdmidio1p::chord_symbol::midi_note_name;
+use mdmidio1p::generator::{generate_numerals, GeneratorSettings};
+use mdmidio1p::groove::Groove;
+use mdmidio1p::harmonic_rhythm::chord_spans;
+use mdmidio1p::markov::MarkovModel;
+use mdmidio1p::progression::parse_progression;
+use mdmidio1p::smf::{build_chord_smf, build_song_smf, write_smf};
+use mdmidio1p::song::Song;
+
+mod cli;
+
+use cli::{AnalyzeOptions, Command, GenerateOptions, TrainOptions};
 
 // ---------------------------------------------------------------------
-// 2) Helpers: create 'static TrackEvents
+// Command-line front end: generate / render / inspect / train / analyze
 // ---------------------------------------------------------------------
 
-/// A simple Note On event
-fn note_on_event(delta: u28, channel: u4, note: u7, velocity: u7) -> TrackEvent<'static> {
-    TrackEvent {
-        delta,
-        kind: TrackEventKind::Midi {
-            channel,
-            message: MidiMessage::NoteOn { key: note, vel: velocity },
-        },
+/// Pick a seed when a randomised generator is in use and none was given, so
+/// the run can be repeated with `--seed`.
+fn with_seed(mut options: GenerateOptions) -> GenerateOptions {
+    let random_arpeggio = options
+        .settings
+        .arpeggio
+        .is_some_and(|arpeggio| arpeggio.mode == ArpMode::Random);
+    let random_progression = options.random_bars.is_some() || options.model.is_some();
+    let melody = options.settings.melody.is_some();
+    let humanize = options.settings.humanize.is_some();
+    if options.seed.is_none() && (random_progression || random_arpeggio || melody || humanize) {
+        options.seed = Some(rand::random());
     }
+    if let Some(seed) = options.seed {
+        if let Some(arpeggio) = options.settings.arpeggio.as_mut() {
+            arpeggio.seed = seed;
+        }
+        if let Some(melody) = options.settings.melody.as_mut() {
+            melody.seed = seed;
+        }
+        if let Some(humanize) = options.settings.humanize.as_mut() {
+            humanize.seed = seed;
+        }
+    }
+    options
 }
 
-/// A simple Note Off event
-fn note_off_event(delta: u28, channel: u4, note: u7, velocity: u7) -> TrackEvent<'static> {
-    TrackEvent {
-        delta,
-        kind: TrackEventKind::Midi {
-            channel,
-            message: MidiMessage::NoteOff { key: note, vel: velocity },
-        },
+/// The progression to play: sampled from a chord model with `--model`,
+/// generated from the seed with `--random`, otherwise `--progression`.
+fn progression_text(options: &GenerateOptions) -> Result<String, Box<dyn Error>> {
+    let seed = options.seed.unwrap_or_default();
+    if let Some(path) = &options.model {
+        let model = MarkovModel::load(path)?;
+        if model.is_empty() {
+            return Err(format!("chord model {path} has no chords").into());
+        }
+        let bars = options.random_bars.unwrap_or(8);
+        return Ok(model
+            .sample_symbols(bars, options.settings.key, seed)
+            .join(" "));
     }
+    Ok(match options.random_bars {
+        Some(bars) => {
+            let settings = GeneratorSettings {
+                bars,
+                phrase_bars: options.phrase_bars,
+            };
+            generate_numerals(&settings, options.settings.key, seed).join(" ")
+        }
+        None => options.progression.clone(),
+    })
 }
 
-// ---------------------------------------------------------------------
-// 3) Example chord data structure
-// ---------------------------------------------------------------------
-
-#[derive(Clone)]
-struct Chord {
-    root: u8,
-    intervals: Vec<u8>,
+fn resolve_chords(options: &GenerateOptions) -> Result<Vec<Chord>, Box<dyn Error>> {
+    Ok(parse_progression(
+        &progression_text(options)?,
+        options.settings.key,
+    )?)
 }
 
-// We'll define a minimal chord progression:
-fn get_demo_chords() -> Vec<Chord> {
-    vec![
-        Chord { root: 60, intervals: vec![0,4,7] },  // C major
-        Chord { root: 67, intervals: vec![0,4,7] },  // G major
-        Chord { root: 65, intervals: vec![0,4,7] },  // F major
-    ]
+/// The song chart given with `--song`, if any.
+fn load_song(options: &GenerateOptions) -> Result<Option<Song>, Box<dyn Error>> {
+    Ok(match &options.song {
+        Some(path) => Some(Song::load(path, options.settings.key)?),
+        None => None,
+    })
 }
 
-// ---------------------------------------------------------------------
-// 4) The chord generation with debug logs
-// ---------------------------------------------------------------------
-
-/// We generate "strumming" events for each chord.  
-/// For debugging, we have `safe_sub_u28` calls and `eprintln!` logs.
-fn generate_chord_track_events(
-    chords: &[Chord],
-    start_tick: u28,
-    ticks_per_measure: u28,
-    channel: u4,
-    base_velocity: u8,
-) -> Vec<TrackEvent<'static>> {
-    let mut events = Vec::new();
-
-    let mut abs_time = start_tick;
-    let mut last_abs_time = start_tick;
-
-    // Hard-coded strum offsets
-    let pattern = [0, 120, 240, 360];
-
-    eprintln!("DEBUG: generate_chord_track_events() called.");
-    eprintln!("DEBUG:  start_tick={start_tick:?}, ticks_per_measure={ticks_per_measure:?}, base_vel={base_velocity}");
-    eprintln!("DEBUG:  channel={channel}");
-    eprintln!("DEBUG:  chords.len()={}", chords.len());
-
-    for (ch_idx, chord) in chords.iter().enumerate() {
-        eprintln!(
-            "DEBUG: chord index {ch_idx}, root={}, intervals={:?}, abs_time={abs_time:?}, last_abs_time={last_abs_time:?}",
-            chord.root, chord.intervals
-        );
-        for &offset in &pattern {
-            let note_on_abs = abs_time + u28::from(offset);
-            let note_off_abs = note_on_abs + u28::from(40);
+/// `options` with the groove taken from the `--groove-from` file, if given:
+/// one step per sixteenth of a measure, which must be as long in the file as
+/// in the song.
+fn with_groove(mut options: GenerateOptions) -> Result<GenerateOptions, Box<dyn Error>> {
+    if let Some(path) = &options.groove_from {
+        let (numerator, denominator) = options.settings.time_signature;
+        let steps = 16 * usize::from(numerator) / usize::from(denominator);
+        options.settings.groove = Some(Groove::from_midi_file(path, steps)?);
+    }
+    Ok(options)
+}
 
-            eprintln!(
-                "DEBUG:   offset={offset}, note_on_abs={note_on_abs:?}, note_off_abs={note_off_abs:?}, last_abs_time={last_abs_time:?}"
+/// Print the resolved chords and every note event with its absolute tick.
+fn render(options: &GenerateOptions) -> Result<(), Box<dyn Error>> {
+    let song = load_song(options)?;
+    let chords = match &song {
+        Some(song) => song.chords()?,
+        None => resolve_chords(options)?,
+    };
+    let settings = &options.settings;
+    let (numerator, denominator) = settings.time_signature;
+    println!(
+        "key {}, {} BPM, {numerator}/{denominator}, {} PPQ, channel {}, velocity {}, seed {}",
+        settings.key,
+        settings.tempo_bpm,
+        settings.ppq,
+        settings.channel + 1,
+        settings.velocity,
+        options
+            .seed
+            .map_or("none".to_string(), |seed| seed.to_string()),
+    );
+    if let Some(song) = &song {
+        let mut bar = 1;
+        for section in song.parts()? {
+            println!(
+                "section {}: bars {bar}-{}",
+                section.name,
+                bar + section.bars - 1
             );
-
-            // For simplicity, let's just use chord.intervals[0], ignoring chord.intervals[1..].
-            let midi_note = chord.root + chord.intervals[0];
-
-            // Note On
-            let delta_on = safe_sub_u28(note_on_abs, last_abs_time, "chord note_on delta");
-            events.push(note_on_event(
-                delta_on,
-                channel,
-                u7::from(midi_note),
-                u7::from(base_velocity),
-            ));
-            last_abs_time = note_on_abs;
-
-            // Note Off
-            let delta_off = safe_sub_u28(note_off_abs, last_abs_time, "chord note_off delta");
-            events.push(note_off_event(
-                delta_off,
-                channel,
-                u7::from(midi_note),
-                u7::from(64),
-            ));
-            last_abs_time = note_off_abs;
+            bar += section.bars;
         }
-
-        // Move forward one measure for the next chord
-        abs_time += ticks_per_measure;
+    } else if options.random_bars.is_some() || options.model.is_some() {
+        println!("progression {}", progression_text(options)?);
+    }
+    let voiced = settings.voiced_chords(&chords)?;
+    let meter = settings.meter();
+    let (ticks_per_measure, beat_ticks) = (meter.ticks_per_measure(), meter.beat_ticks());
+    let spans = chord_spans(&voiced, 0, meter);
+    for (chord, span) in voiced.iter().zip(&spans) {
+        let notes: Vec<String> = chord.notes()?.into_iter().map(midi_note_name).collect();
+        // Chords starting mid-bar also show the beat they fall on, and any
+        // ticks past it.
+        let bar = span.start / u64::from(ticks_per_measure) + 1;
+        let offset = (span.start % u64::from(ticks_per_measure)) as u32;
+        let (beat, ticks) = (offset / beat_ticks + 1, offset % beat_ticks);
+        let place = match (beat, ticks) {
+            (1, 0) => format!("bar {bar:>3}"),
+            (_, 0) => format!("bar {bar:>3} beat {beat}"),
+            _ => format!("bar {bar:>3} beat {beat} +{ticks} ticks"),
+        };
+        let push = if span.start < span.downbeat {
+            " (pushed)"
+        } else {
+            ""
+        };
+        println!("{place}: {}{push}", notes.join(" "));
     }
 
-    events
+    let smf = match &song {
+        Some(song) => build_song_smf(song, settings)?,
+        None => build_chord_smf(&chords, settings)?,
+    };
+    for (index, track) in smf.tracks.iter().enumerate() {
+        if smf.tracks.len() > 1 {
+            println!("track {index}:");
+        }
+        print_events(track);
+    }
+    Ok(())
 }
 
-// ---------------------------------------------------------------------
-// 5) Minimal main() that calls generate_chord_track_events
-// ---------------------------------------------------------------------
-
-fn main() -> IoResult<()> {
-    // We'll do a minimal example:
-    let header = Header {
-        format: Format::Parallel,
-        timing: Timing::Metrical(u15::from(480)), // 480 ticks/quarter
-    };
+/// Summarise the header and tracks of an existing MIDI file.
+fn inspect(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = std::fs::read(path)?;
+    let smf = Smf::parse(&bytes)?;
+    println!(
+        "{path}: {:?}, {:?}, {} track(s)",
+        smf.header.format,
+        smf.header.timing,
+        smf.tracks.len()
+    );
+    for (index, track) in smf.tracks.iter().enumerate() {
+        let length: u32 = track.iter().map(|ev| ev.delta.as_int()).sum();
+        let notes = track
+            .iter()
+            .filter(|ev| {
+                matches!(
+                    ev.kind,
+                    TrackEventKind::Midi {
+                        message: MidiMessage::NoteOn { vel, .. },
+                        ..
+                    } if vel > 0
+                )
+            })
+            .count();
+        let name = track.iter().find_map(|ev| match ev.kind {
+            TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
+                Some(String::from_utf8_lossy(name))
+            }
+            _ => None,
+        });
+        println!(
+            "track {index}: {} events, {notes} notes, {length} ticks{}",
+            track.len(),
+            name.map_or(String::new(), |name| format!(", \"{name}\"")),
+        );
+    }
+    Ok(())
+}
 
-    // We'll only have one track for demonstration
-    let mut track = Track::new();
+/// Print the key and the chords of a MIDI file as symbol and numeral charts.
+fn analyze_file(options: &AnalyzeOptions) -> Result<(), Box<dyn Error>> {
+    let bytes = std::fs::read(&options.path)?;
+    let smf = Smf::parse(&bytes)?;
+    let analysis = analyze(&smf, options.resolution, options.key)?;
+    println!(
+        "{}: key {}{}",
+        options.path,
+        analysis.key,
+        if analysis.key_detected {
+            " (detected)"
+        } else {
+            ""
+        }
+    );
+    println!("{}\n", analysis.chart());
+    println!("{}", analysis.numeral_chart());
+    Ok(())
+}
 
-    // A simple chord progression
-    let chords = get_demo_chords();
+/// Train a chord model on a directory of charts and save it.
+fn train(options: &TrainOptions) -> Result<(), Box<dyn Error>> {
+    let model = MarkovModel::train_dir(&options.dir, options.order)?;
+    if model.is_empty() {
+        return Err(format!("no chord charts found in {}", options.dir).into());
+    }
+    model.save(&options.output)?;
+    println!("{} created", options.output);
+    Ok(())
+}
 
-    // Let's generate chord events
-    // If you want to test "bad" logic, try messing with these values:
-    let chord_events = generate_chord_track_events(
-        &chords,
-        u28::from(0),
-        u28::from(1920), // 4/4 measure with 480 TQ
-        u4::from(0),
-        64,
-    );
-    track.extend(chord_events);
-
-    // End of track
-    track.push(TrackEvent {
-        delta: u28::from(0),
-        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
-    });
-
-    // Build an SMF
-    let smf = Smf {
-        header,
-        tracks: vec![track],
-    };
+/// One line per event: absolute tick followed by the event.
+fn print_events(track: &[TrackEvent]) {
+    let mut tick = 0u32;
+    for event in track {
+        tick += event.delta.as_int();
+        match event.kind {
+            TrackEventKind::Midi { channel, message } => match message {
+                MidiMessage::NoteOn { key, vel } => println!(
+                    "{tick:>8}  ch{:<2}  note on   {:<4} vel {vel}",
+                    channel.as_int() + 1,
+                    midi_note_name(key.as_int())
+                ),
+                MidiMessage::NoteOff { key, vel } => println!(
+                    "{tick:>8}  ch{:<2}  note off  {:<4} vel {vel}",
+                    channel.as_int() + 1,
+                    midi_note_name(key.as_int())
+                ),
+                other => println!("{tick:>8}  ch{:<2}  {other:?}", channel.as_int() + 1),
+            },
+            other => println!("{tick:>8}        {other:?}"),
+        }
+    }
+}
 
-    // Save it
-    smf.save("output.mid")?;
-    println!("Output.mid created");
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    match cli::parse_args(args)? {
+        Command::Generate(options) => {
+            let options = with_groove(with_seed(options))?;
+            match load_song(&options)? {
+                Some(song) => {
+                    write_smf(&build_song_smf(&song, &options.settings)?, &options.output)?
+                }
+                None => write_smf(
+                    &build_chord_smf(&resolve_chords(&options)?, &options.settings)?,
+                    &options.output,
+                )?,
+            }
+            match options.seed {
+                Some(seed) => println!("{} created (seed {seed})", options.output),
+                None => println!("{} created", options.output),
+            }
+        }
+        Command::Render(options) => render(&with_groove(with_seed(options))?)?,
+        Command::Inspect { path } => inspect(&path)?,
+        Command::Train(options) => train(&options)?,
+        Command::Analyze(options) => analyze_file(&options)?,
+        Command::Help => print!("{}", cli::USAGE),
+    }
     Ok(())
 }
 
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("error: {err}");
+        eprintln!("run `mdmidio1p help` for usage");
+        std::process::exit(1);
+    }
+}
+
 // ---------------------------------------------------------------------
-// 6) Tests
+// Tests
 // ---------------------------------------------------------------------
 #[cfg(test)]
 mod tests {
     use super::*;
+    use midly::num::{u15, u24};
+    use midly::Timing;
     use std::fs;
 
     #[test]
-    fn test_chord_track_events_not_empty() {
-        // We'll call the generator with typical values.
-        let chords = get_demo_chords();
-        let events = generate_chord_track_events(
-            &chords,
-            u28::from(0),
-            u28::from(1920),
-            u4::from(0),
-            64,
-        );
-        assert!(!events.is_empty());
+    fn test_midi_file_creation() {
+        let path = std::env::temp_dir().join("mdmidio1p_test_output.mid");
+        let path = path.to_str().unwrap().to_string();
+        let args: Vec<String> = [
+            "generate",
+            "--tempo",
+            "90",
+            "--time-sig",
+            "3/4",
+            "-o",
+            &path,
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        run(&args).unwrap();
+        assert!(fs::metadata(&path).is_ok());
+
+        let bytes = fs::read(&path).unwrap();
+        let smf = Smf::parse(&bytes).unwrap();
+        assert_eq!(smf.header.timing, Timing::Metrical(u15::from(480)));
+        assert!(smf.tracks[0]
+            .iter()
+            .any(|ev| ev.kind == TrackEventKind::Meta(MetaMessage::Tempo(u24::from(666_667)))));
+        inspect(&path).unwrap();
     }
 
     #[test]
-    fn test_midi_file_creation() {
-        let _ = main();
-        assert!(fs::metadata("output.mid").is_ok());
+    fn test_seed_reaches_every_seeded_part() {
+        let args: Vec<String> = [
+            "generate",
+            "--arp",
+            "random",
+            "--melody",
+            "--humanize",
+            "10",
+            "-s",
+            "7",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let Ok(Command::Generate(options)) = cli::parse_args(&args) else {
+            panic!("expected generate");
+        };
+        let settings = with_seed(options).settings;
+        assert_eq!(settings.arpeggio.unwrap().seed, 7);
+        assert_eq!(settings.melody.unwrap().seed, 7);
+        assert_eq!(settings.humanize.unwrap().seed, 7);
+    }
+
+    #[test]
+    fn test_same_seed_writes_the_same_file() {
+        let write = |name: &str, seed: &str| {
+            let path = std::env::temp_dir().join(name);
+            let path = path.to_str().unwrap().to_string();
+            let args: Vec<String> = ["generate", "--random", "8", "--seed", seed, "-o", &path]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            run(&args).unwrap();
+            fs::read(&path).unwrap()
+        };
+        let first = write("mdmidio1p_seed_a.mid", "11");
+        assert_eq!(first, write("mdmidio1p_seed_b.mid", "11"));
+        assert_ne!(first, write("mdmidio1p_seed_c.mid", "12"));
+    }
+
+    #[test]
+    fn test_groove_taken_from_a_file() {
+        let write = |name: &str, groove: &[&str]| {
+            let path = std::env::temp_dir().join(name);
+            let path = path.to_str().unwrap().to_string();
+            let mut args: Vec<String> = ["generate", "--pattern", "DUDUDUDU", "-o", &path]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            args.extend(groove.iter().map(|s| s.to_string()));
+            run(&args).unwrap();
+            let bytes = fs::read(&path).unwrap();
+            let smf = Smf::parse(&bytes).unwrap();
+            let mut tick = 0;
+            smf.tracks[0]
+                .iter()
+                .filter_map(|ev| {
+                    tick += ev.delta.as_int();
+                    match ev.kind {
+                        TrackEventKind::Midi {
+                            message: MidiMessage::NoteOn { .. },
+                            ..
+                        } => Some(tick),
+                        _ => None,
+                    }
+                })
+                .collect::<Vec<u32>>()
+        };
+        let shuffled = write("mdmidio1p_groove_a.mid", &["--shuffle"]);
+        assert!(shuffled.iter().any(|tick| tick % 480 == 320));
+        let source = std::env::temp_dir().join("mdmidio1p_groove_a.mid");
+        let copied = write(
+            "mdmidio1p_groove_b.mid",
+            &["--groove-from", source.to_str().unwrap()],
+        );
+        assert_eq!(copied, shuffled);
     }
 }
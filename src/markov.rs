@@ -0,0 +1,371 @@
+//! Chord models trained on charts and sampled as progressions.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::chord::Chord;
+use crate::chord_symbol::{parse_chord, parse_note_name, PITCH_CLASS_NAMES};
+use crate::error::{Error, Result};
+use crate::progression::{split_tokens, Key};
+
+// ---------------------------------------------------------------------
+// Markov-chain progressions learned from chord charts
+// ---------------------------------------------------------------------
+
+/// First line of a saved model.
+const HEADER: &str = "mdmidio1p-markov 1";
+
+/// A chord relative to the chart's key: semitones above the tonic plus the
+/// quality suffix as written, e.g. "9:m7" for Am7 in C. Slash basses are dropped.
+type State = String;
+
+/// Chord-to-chord transition counts learned from a corpus of charts.
+///
+/// Each chart is read relative to its key, so songs in different keys train
+/// the same states and samples can be played back in any key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarkovModel {
+    /// Number of previous chords the next one depends on.
+    order: usize,
+    /// How often each opening context starts a chart.
+    starts: BTreeMap<Vec<State>, u32>,
+    /// How often each chord follows each context.
+    transitions: BTreeMap<Vec<State>, BTreeMap<State, u32>>,
+}
+
+/// The relative state of a chord symbol, or `None` if `token` is not one.
+fn relative_state(token: &str, tonic: u8) -> Option<State> {
+    parse_chord(token).ok()?;
+    let (root, consumed) = parse_note_name(token)?;
+    // Saved models separate states with spaces, so "7(#5, b9)" is kept as "7(#5,b9)".
+    let quality: String = token[consumed..]
+        .split('/')
+        .next()
+        .unwrap_or("")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    Some(format!("{}:{quality}", (root + 12 - tonic) % 12))
+}
+
+impl MarkovModel {
+    /// An untrained model; `order` is clamped to at least 1.
+    pub fn new(order: usize) -> Self {
+        MarkovModel {
+            order: order.max(1),
+            ..MarkovModel::default()
+        }
+    }
+
+    /// Number of previous chords the next one depends on.
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Whether the model learned nothing (no chart had chords).
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Learn from one chart: chord symbols separated by spaces or bar lines.
+    ///
+    /// A `key: G` line names the key; otherwise the first chord is taken as
+    /// the tonic. Lines starting with `#` and tokens that are not chord
+    /// symbols (section names, repeat marks) are skipped. Charts shorter than
+    /// the model's order teach it nothing.
+    pub fn train_chart(&mut self, text: &str) {
+        let declared_key = text.lines().find_map(|line| {
+            let (label, key) = line.split_once(':')?;
+            label
+                .trim()
+                .eq_ignore_ascii_case("key")
+                .then(|| key.parse::<Key>().ok())?
+        });
+
+        let tokens: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .filter(|line| !line.to_ascii_lowercase().starts_with("key:"))
+            .flat_map(split_tokens)
+            .filter(|token| parse_chord(token).is_ok())
+            .collect();
+        let Some(first) = tokens.first() else {
+            return;
+        };
+        let tonic = match declared_key {
+            Some(key) => key.tonic,
+            None => parse_note_name(first).map_or(0, |(pc, _)| pc),
+        };
+        let states: Vec<State> = tokens
+            .iter()
+            .filter_map(|token| relative_state(token, tonic))
+            .collect();
+        if states.len() < self.order {
+            return;
+        }
+
+        *self
+            .starts
+            .entry(states[..self.order].to_vec())
+            .or_default() += 1;
+        for window in states.windows(self.order + 1) {
+            let (context, next) = window.split_at(self.order);
+            *self
+                .transitions
+                .entry(context.to_vec())
+                .or_default()
+                .entry(next[0].clone())
+                .or_default() += 1;
+        }
+    }
+
+    /// Train on every `.txt` chart in `dir`.
+    pub fn train_dir(dir: impl AsRef<Path>, order: usize) -> Result<Self> {
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()?;
+        paths.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "txt"));
+        paths.sort();
+
+        let mut model = MarkovModel::new(order);
+        for path in paths {
+            model.train_chart(&fs::read_to_string(path)?);
+        }
+        Ok(model)
+    }
+
+    /// Write the model as text (see [`fmt::Display`]).
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Read a model written by [`MarkovModel::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Sample `bars` chord symbols in `key`. The same model and seed always give
+    /// the same symbols. When a context was never followed by anything in the
+    /// corpus, the chain starts over from an opening context.
+    pub fn sample_symbols(&self, bars: usize, key: Key, seed: u64) -> Vec<String> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut pick = |counts: &BTreeMap<Vec<State>, u32>| {
+            let weights: Vec<u32> = counts.values().copied().collect();
+            let index = WeightedIndex::new(&weights)
+                .expect("trained counts are positive")
+                .sample(&mut rng);
+            counts
+                .keys()
+                .nth(index)
+                .cloned()
+                .expect("index is in range")
+        };
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let mut states: Vec<State> = Vec::with_capacity(bars + self.order);
+        while states.len() < bars {
+            let context = states
+                .len()
+                .checked_sub(self.order)
+                .map(|start| &states[start..]);
+            let next = context.and_then(|context| self.transitions.get(context));
+            match next {
+                Some(counts) => {
+                    let counts: BTreeMap<Vec<State>, u32> = counts
+                        .iter()
+                        .map(|(state, &count)| (vec![state.clone()], count))
+                        .collect();
+                    states.extend(pick(&counts));
+                }
+                None => states.extend(pick(&self.starts)),
+            }
+        }
+        states.truncate(bars);
+
+        states
+            .iter()
+            .map(|state| {
+                let (offset, quality) = state.split_once(':').unwrap_or(("0", ""));
+                let offset: u8 = offset.parse().unwrap_or(0);
+                let root = PITCH_CLASS_NAMES[usize::from((key.tonic + offset) % 12)];
+                format!("{root}{quality}")
+            })
+            .collect()
+    }
+
+    /// Sample `bars` chords in `key`, ready for the chord track generators.
+    pub fn sample(&self, bars: usize, key: Key, seed: u64) -> Result<Vec<Chord>> {
+        self.sample_symbols(bars, key, seed)
+            .iter()
+            .map(|symbol| Ok(parse_chord(symbol)?))
+            .collect()
+    }
+}
+
+/// The saved form: a header, the order, then one line per count.
+///
+/// ```text
+/// mdmidio1p-markov 1
+/// order 1
+/// start 3 0:
+/// next 5 0: -> 7:
+/// ```
+impl fmt::Display for MarkovModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        writeln!(f, "order {}", self.order)?;
+        for (context, count) in &self.starts {
+            writeln!(f, "start {count} {}", context.join(" "))?;
+        }
+        for (context, nexts) in &self.transitions {
+            for (next, count) in nexts {
+                writeln!(f, "next {count} {} -> {next}", context.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MarkovModel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()));
+        let invalid = |line: usize, reason: &str| Error::InvalidModel {
+            line,
+            reason: reason.to_string(),
+        };
+
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(invalid(1, "not a saved chord model")),
+        }
+        let order = match lines.next() {
+            Some((number, line)) => line
+                .strip_prefix("order ")
+                .and_then(|order| order.parse::<usize>().ok())
+                .filter(|&order| order > 0)
+                .ok_or_else(|| invalid(number, "expected \"order <n>\""))?,
+            None => return Err(invalid(2, "missing order")),
+        };
+
+        let mut model = MarkovModel::new(order);
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+            let mut words = line.split_whitespace();
+            let kind = words.next().unwrap_or("");
+            let count: u32 = words
+                .next()
+                .and_then(|count| count.parse().ok())
+                .filter(|&count| count > 0)
+                .ok_or_else(|| invalid(number, "expected a positive count"))?;
+            let rest: Vec<&str> = words.collect();
+            let context_of = |states: &[&str]| -> Result<Vec<State>> {
+                if states.len() != order {
+                    return Err(invalid(number, "context length does not match the order"));
+                }
+                Ok(states.iter().map(|state| state.to_string()).collect())
+            };
+            match (kind, rest.as_slice()) {
+                ("start", states) => {
+                    model.starts.insert(context_of(states)?, count);
+                }
+                ("next", [states @ .., "->", next]) => {
+                    model
+                        .transitions
+                        .entry(context_of(states)?)
+                        .or_default()
+                        .insert(next.to_string(), count);
+                }
+                _ => return Err(invalid(number, "expected a start or next line")),
+            }
+        }
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus() -> MarkovModel {
+        let mut model = MarkovModel::new(1);
+        model.train_chart("# Song one\nkey: G\n| G | Em | C | D |\n| G | Em | C D | G |");
+        model.train_chart("Verse: A F#m D E A");
+        model
+    }
+
+    #[test]
+    fn test_charts_in_different_keys_share_states() {
+        let model = corpus();
+        assert_eq!(model.starts.get(&vec!["0:".to_string()]), Some(&2));
+        let from_tonic = &model.transitions[&vec!["0:".to_string()]];
+        assert_eq!(from_tonic.get("9:m"), Some(&3));
+        // "Verse:" is not a chord and is skipped.
+        assert!(!model.transitions.keys().any(|k| k[0].contains("Verse")));
+    }
+
+    #[test]
+    fn test_parenthesised_extensions_are_one_state() {
+        let mut model = MarkovModel::new(1);
+        model.train_chart("C7(b9,#11) | F | G7(#5, b9) | C7(b9,#11)");
+        let from_tonic = &model.transitions[&vec!["0:7(b9,#11)".to_string()]];
+        assert_eq!(from_tonic.get("5:"), Some(&1));
+        assert!(model
+            .transitions
+            .contains_key(&vec!["7:7(#5,b9)".to_string()]));
+        assert_eq!(model.to_string().parse::<MarkovModel>().unwrap(), model);
+    }
+
+    #[test]
+    fn test_samples_are_seeded_and_in_key() {
+        let model = corpus();
+        let key: Key = "C".parse().unwrap();
+        let symbols = model.sample_symbols(12, key, 9);
+        assert_eq!(symbols.len(), 12);
+        assert_eq!(symbols, model.sample_symbols(12, key, 9));
+        assert!(symbols
+            .iter()
+            .all(|s| ["C", "Am", "F", "G"].contains(&s.as_str())));
+        assert_eq!(model.sample(4, key, 1).unwrap().len(), 4);
+        assert!(MarkovModel::new(1).sample_symbols(4, key, 0).is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let mut model = MarkovModel::new(2);
+        model.train_chart("C Am F G C Am Dm G C");
+        let text = model.to_string();
+        assert!(text.starts_with(HEADER));
+        let loaded: MarkovModel = text.parse().unwrap();
+        assert_eq!(loaded, model);
+
+        let dir = std::env::temp_dir().join("mdmidio1p_markov_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("one.txt"), "C Am F G").unwrap();
+        fs::write(dir.join("notes.md"), "ignored").unwrap();
+        let trained = MarkovModel::train_dir(&dir, 1).unwrap();
+        let path = dir.join("chords.model");
+        trained.save(&path).unwrap();
+        assert_eq!(MarkovModel::load(&path).unwrap(), trained);
+
+        assert!(matches!(
+            "mdmidio1p-markov 1\norder 1\nnext x 0: -> 7:".parse::<MarkovModel>(),
+            Err(Error::InvalidModel { line: 3, .. })
+        ));
+        assert!("hello".parse::<MarkovModel>().is_err());
+    }
+}
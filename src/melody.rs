@@ -0,0 +1,354 @@
+//! Seeded lead melodies over a progression.
+
+use midly::num::{u4, u7};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::chord::Chord;
+use crate::error::Result;
+use crate::events::{tick_u28, value_u7};
+use crate::harmonic_rhythm::{chord_at, chord_spans, measure_count};
+use crate::progression::Key;
+use crate::scale::Scale;
+use crate::tempo::Meter;
+use crate::timeline::Timeline;
+use crate::voicing::Register;
+
+// ---------------------------------------------------------------------
+// Lead melody over the chord track
+// ---------------------------------------------------------------------
+
+/// 4/4 rhythms, two steps to the beat: `x` starts a note, `-` holds it,
+/// `.` is a rest. A phrase repeats its main motif, contrasts it once, and
+/// closes on a cadence rhythm with a long last note.
+const MOTIFS: [&str; 6] = [
+    "x-x-x-x-", "x-xxx-x-", "x--xx-x-", "x-x-xxx-", "x---x-xx", "x-.xx-x-",
+];
+const CADENCE_MOTIFS: [&str; 3] = ["x-x-x---", "x---x---", "x-xx----"];
+
+/// Overall shape of a phrase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Contour {
+    Arch,
+    Valley,
+    Rising,
+    Falling,
+}
+
+impl Contour {
+    /// Target height at `t` (0.0..=1.0 through the phrase), in -1.0..=1.0.
+    fn height(self, t: f64) -> f64 {
+        match self {
+            Contour::Arch => 1.0 - (2.0 * t - 1.0).powi(2) * 2.0,
+            Contour::Valley => (2.0 * t - 1.0).powi(2) * 2.0 - 1.0,
+            Contour::Rising => 2.0 * t - 1.0,
+            Contour::Falling => 1.0 - 2.0 * t,
+        }
+    }
+}
+
+/// Lead melody settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Melody {
+    /// Every melody note is placed inside this range.
+    pub register: Register,
+    /// Zero-based MIDI channel.
+    pub channel: u8,
+    /// Note-on velocity of notes on the beat; notes between beats are a little softer.
+    pub velocity: u8,
+    /// Bars per phrase; each phrase gets its own contour and motifs.
+    pub phrase_bars: u32,
+    /// Seed for the contours, motifs and note choices.
+    pub seed: u64,
+}
+
+impl Default for Melody {
+    fn default() -> Self {
+        Melody {
+            register: Register { low: 60, high: 84 },
+            channel: 2,
+            velocity: 90,
+            phrase_bars: 4,
+            seed: 0,
+        }
+    }
+}
+
+/// The nearest note above (`direction` 1) or below (-1) `note` whose pitch
+/// class is in `pitch_classes`.
+fn step(note: i32, direction: i32, pitch_classes: &[i32]) -> i32 {
+    let mut next = note + direction;
+    while !pitch_classes.contains(&next.rem_euclid(12)) {
+        next += direction;
+    }
+    next
+}
+
+/// A chord tone inside `register` near `target`: the closest one, or now and
+/// then the second closest.
+fn chord_tone_near(target: f64, chord_pcs: &[i32], register: Register, rng: &mut StdRng) -> i32 {
+    let mut tones: Vec<i32> = (i32::from(register.low)..=i32::from(register.high))
+        .filter(|note| chord_pcs.contains(&note.rem_euclid(12)))
+        .collect();
+    tones.sort_by(|a, b| {
+        (f64::from(*a) - target)
+            .abs()
+            .total_cmp(&(f64::from(*b) - target).abs())
+    });
+    match tones.len() {
+        0 => target.round() as i32,
+        1 => tones[0],
+        _ => tones[usize::from(rng.gen_bool(0.25))],
+    }
+}
+
+/// Onsets of a motif as (step, length in steps).
+fn motif_notes(motif: &str) -> Vec<(u32, u32)> {
+    let mut notes: Vec<(u32, u32)> = Vec::new();
+    let mut holding = false;
+    for (step, c) in motif.chars().enumerate() {
+        match c {
+            'x' => {
+                notes.push((step as u32, 1));
+                holding = true;
+            }
+            '-' if holding => notes.last_mut().expect("holding a note").1 += 1,
+            _ => holding = false,
+        }
+    }
+    notes
+}
+
+/// `motif` fitted to a bar of `steps` steps: cut short, or repeated to fill a
+/// longer bar; a cadence holds its last note instead.
+fn bar_motif(motif: &str, steps: usize, cadence: bool) -> String {
+    let fill = if cadence { "-" } else { motif };
+    motif
+        .chars()
+        .chain(fill.chars().cycle())
+        .take(steps)
+        .collect()
+}
+
+/// A melody over `chords`, each lasting its own [`Chord::measures`] of
+/// `meter` from `start_tick`. Motifs and phrases follow the bars.
+///
+/// Notes on a beat are tones of the chord sounding there, chosen to follow
+/// the phrase's contour. Notes between beats step through `key`'s scale from
+/// the previous note: passing tones in the contour's direction, or now and
+/// then a neighbour tone against it. Each phrase ends on a held chord tone.
+pub fn melody_timeline(
+    chords: &[Chord],
+    key: Key,
+    melody: &Melody,
+    start_tick: u32,
+    meter: Meter,
+    channel: u4,
+) -> Result<Timeline<'static>> {
+    value_u7(i32::from(melody.velocity))?;
+    let ticks_per_measure = meter.ticks_per_measure();
+
+    let scale: Vec<i32> = Scale::from(key)
+        .pitch_classes()
+        .into_iter()
+        .map(i32::from)
+        .collect();
+    let low = f64::from(melody.register.low);
+    let high = f64::from(melody.register.high);
+    let (center, span) = ((low + high) / 2.0, (high - low) / 3.0);
+    let phrase_bars = melody.phrase_bars.max(1) as usize;
+    // Motifs run two steps to the beat, cut short or carried on to fill the bar.
+    let beat_ticks = meter.beat_ticks();
+    let steps = (2 * meter.beats_per_measure()).max(1) as usize;
+    let step_ticks = f64::from(beat_ticks) / 2.0;
+
+    let mut rng = StdRng::seed_from_u64(melody.seed);
+    let mut timeline = Timeline::new();
+    let mut previous: Option<i32> = None;
+    let mut contour = Contour::Arch;
+    let (mut main_motif, mut contrast_motif) = (MOTIFS[0], MOTIFS[1]);
+
+    let spans = chord_spans(chords, start_tick, meter);
+    let chord_pcs = chords
+        .iter()
+        .map(|chord| Ok(chord.notes()?.iter().map(|&n| i32::from(n % 12)).collect()))
+        .collect::<Result<Vec<Vec<i32>>>>()?;
+    let bars = measure_count(chords);
+
+    for bar in 0..bars {
+        let in_phrase = bar % phrase_bars;
+        if in_phrase == 0 {
+            contour = [
+                Contour::Arch,
+                Contour::Valley,
+                Contour::Rising,
+                Contour::Falling,
+            ][rng.gen_range(0..4)];
+            main_motif = MOTIFS[rng.gen_range(0..MOTIFS.len())];
+            contrast_motif = MOTIFS[rng.gen_range(0..MOTIFS.len())];
+        }
+        let phrase_end = in_phrase + 1 == phrase_bars || bar + 1 == bars;
+        let motif = if phrase_end {
+            CADENCE_MOTIFS[rng.gen_range(0..CADENCE_MOTIFS.len())]
+        } else if in_phrase % 4 == 2 {
+            contrast_motif
+        } else {
+            main_motif
+        };
+
+        let measure_start = u64::from(start_tick) + bar as u64 * u64::from(ticks_per_measure);
+        let notes = motif_notes(&bar_motif(motif, steps, phrase_end));
+        for (index, &(step_index, length)) in notes.iter().enumerate() {
+            let offset = (f64::from(step_index) * step_ticks).round() as u32;
+            let tick = measure_start + u64::from(offset);
+            // The chord sounding under this note; none past the progression's end.
+            let Some(chord_index) = chord_at(&spans, tick) else {
+                continue;
+            };
+            let chord = &chords[chord_index];
+            let duration = (f64::from(length) * step_ticks).round() as u32;
+            let t = (in_phrase as f64 + f64::from(step_index) / steps as f64) / phrase_bars as f64;
+            let target = center + span * contour.height(t);
+            let on_beat = offset.is_multiple_of(beat_ticks.max(1));
+            let last = phrase_end && index + 1 == notes.len();
+
+            let note = match previous {
+                Some(previous) if !on_beat && !last => {
+                    let toward = if target >= f64::from(previous) { 1 } else { -1 };
+                    let direction = if rng.gen_bool(0.2) { -toward } else { toward };
+                    let candidate = step(previous, direction, &scale);
+                    let fallback = step(previous, -direction, &scale);
+                    if melody.register.contains(candidate) {
+                        candidate
+                    } else if melody.register.contains(fallback) {
+                        fallback
+                    } else {
+                        previous
+                    }
+                }
+                _ if last => {
+                    // Close the phrase on the chord root.
+                    let root_pc = i32::from(chord.root % 12);
+                    chord_tone_near(target, &[root_pc], melody.register, &mut rng)
+                }
+                _ => chord_tone_near(target, &chord_pcs[chord_index], melody.register, &mut rng),
+            };
+            previous = Some(note);
+
+            let scale_velocity = if on_beat { 1.0 } else { 0.85 };
+            let velocity = (f64::from(melody.velocity) * scale_velocity)
+                .round()
+                .clamp(1.0, 127.0) as u8;
+            timeline.note(
+                tick_u28(tick)?.as_int(),
+                duration.max(1),
+                channel,
+                value_u7(note)?,
+                u7::from(velocity),
+            );
+        }
+    }
+    Ok(timeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progression::parse_progression;
+    use midly::{MidiMessage, TrackEventKind};
+
+    fn melody_notes(seed: u64, meter: Meter) -> (Vec<Chord>, Vec<(u32, i32)>) {
+        let key: Key = "G".parse().unwrap();
+        let chords = parse_progression("I vi IV V I iii ii V", key).unwrap();
+        let melody = Melody {
+            seed,
+            ..Melody::default()
+        };
+        let timeline = melody_timeline(&chords, key, &melody, 0, meter, u4::from(2)).unwrap();
+        let mut notes: Vec<(u32, i32)> = timeline
+            .iter()
+            .filter_map(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, .. },
+                    ..
+                } => Some((ev.tick, i32::from(key.as_int()))),
+                _ => None,
+            })
+            .collect();
+        notes.sort_unstable();
+        (chords, notes)
+    }
+
+    #[test]
+    fn test_melody_is_seeded() {
+        let meter = Meter::default();
+        assert_eq!(melody_notes(4, meter).1, melody_notes(4, meter).1);
+        assert_ne!(melody_notes(4, meter).1, melody_notes(5, meter).1);
+    }
+
+    #[test]
+    fn test_beats_use_chord_tones_and_the_rest_the_scale() {
+        let scale = [7, 9, 11, 0, 2, 4, 6];
+        for (seed, meter) in (0..10).flat_map(|seed| {
+            [(4, 4), (3, 4), (6, 8)].map(|signature| (seed, Meter::new(480, signature)))
+        }) {
+            let (chords, notes) = melody_notes(seed, meter);
+            assert!(!notes.is_empty());
+            let on_beat = notes
+                .iter()
+                .filter(|&&(tick, _)| tick.is_multiple_of(meter.beat_ticks()))
+                .count();
+            assert!(on_beat * 2 >= notes.len(), "seed {seed} in {meter:?}");
+            for &(tick, note) in &notes {
+                assert!((60..=84).contains(&note), "seed {seed}: {note}");
+                let chord = &chords[(tick / meter.ticks_per_measure()) as usize];
+                let chord_pcs: Vec<i32> = chord
+                    .notes()
+                    .unwrap()
+                    .iter()
+                    .map(|&n| i32::from(n % 12))
+                    .collect();
+                if tick.is_multiple_of(meter.beat_ticks()) {
+                    assert!(chord_pcs.contains(&(note % 12)), "seed {seed} at {tick}");
+                } else {
+                    assert!(scale.contains(&(note % 12)), "seed {seed} at {tick}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_motifs_and_steps() {
+        assert_eq!(
+            motif_notes("x-xxx-x-"),
+            vec![(0, 2), (2, 1), (3, 1), (4, 2), (6, 2)]
+        );
+        assert_eq!(motif_notes("x-.x"), vec![(0, 2), (3, 1)]);
+        let c_major = [0, 2, 4, 5, 7, 9, 11];
+        assert_eq!(step(64, 1, &c_major), 65);
+        assert_eq!(step(64, -1, &c_major), 62);
+        assert_eq!(step(68, 1, &c_major), 69);
+    }
+
+    #[test]
+    fn test_steps_stay_in_a_narrow_register() {
+        let key: Key = "C".parse().unwrap();
+        let chords = parse_progression("I IV V I", key).unwrap();
+        for seed in 0..10 {
+            let melody = Melody {
+                register: Register { low: 67, high: 67 },
+                seed,
+                ..Melody::default()
+            };
+            let timeline =
+                melody_timeline(&chords, key, &melody, 0, Meter::default(), u4::from(2)).unwrap();
+            assert!(timeline.iter().all(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, .. },
+                    ..
+                } => key == 67,
+                _ => true,
+            }));
+        }
+    }
+}
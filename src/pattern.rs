@@ -0,0 +1,240 @@
+//! Strum patterns, by name or in compact notation.
+
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::tempo::Meter;
+
+// ---------------------------------------------------------------------
+// Strum patterns: hits per measure with offset, length, velocity, direction
+// ---------------------------------------------------------------------
+
+/// Which way the pick travels across the strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the lowest string to the highest.
+    Down,
+    /// From the highest string to the lowest.
+    Up,
+}
+
+/// One strum within a measure, measured in grid steps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrumHit {
+    /// Grid step the hit starts on.
+    pub step: u32,
+    /// Length in steps; fractions give staccato chops.
+    pub length: f32,
+    /// Velocity relative to the base velocity (1.0 = base).
+    pub velocity: f32,
+    /// Which way the pick travels.
+    pub direction: Direction,
+}
+
+/// A 4/4 measure divided into `steps` equal grid steps, with hits on some of
+/// them. In other meters the steps keep their length (see
+/// [`StrumPattern::hit_ticks`]).
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrumPattern {
+    /// Grid steps in a 4/4 measure.
+    pub steps: u32,
+    /// Hits in step order.
+    pub hits: Vec<StrumHit>,
+}
+
+/// Velocity of lower-case (ghosted) hits relative to upper-case ones.
+const GHOST_VELOCITY: f32 = 0.6;
+
+/// Length of a muted chop ('x'), in steps.
+const CHOP_LENGTH: f32 = 0.25;
+
+/// Built-in patterns, by name, in compact notation.
+const LIBRARY: [(&str, &str); 8] = [
+    ("quarters", "DDDD"),
+    ("halves", "D-D-"),
+    ("eighths", "DuDuDuDu"),
+    ("pop", "D-DuDuDu"),
+    ("folk", "D.DU.UDU"),
+    ("reggae", "..X...X."),
+    ("bossa", "D--D--D---D--D--"),
+    ("charleston", "D-----D---------"),
+];
+
+impl StrumPattern {
+    /// Parse the compact notation, one character per grid step:
+    ///
+    /// - `D` / `U`: down / up strum; lower-case `d` / `u` is a ghosted hit
+    /// - `X` / `x`: short muted down-stroke chop (accented / ghosted)
+    /// - `-`: tie, extends the previous hit by one step
+    /// - `.`: rest
+    ///
+    /// The string length sets the grid, so "D.DU.UDU" is eight steps to a 4/4
+    /// measure: eighth notes.
+    pub fn parse(notation: &str) -> Result<Self> {
+        let invalid = |reason: String| Error::InvalidPattern {
+            pattern: notation.to_string(),
+            reason,
+        };
+
+        let mut hits: Vec<StrumHit> = Vec::new();
+        let mut tie_open = false;
+        let mut steps = 0;
+        for (step, c) in notation.trim().chars().enumerate() {
+            let step = step as u32;
+            steps = step + 1;
+            let (direction, length, velocity) = match c {
+                'D' => (Direction::Down, 1.0, 1.0),
+                'd' => (Direction::Down, 1.0, GHOST_VELOCITY),
+                'U' => (Direction::Up, 1.0, 1.0),
+                'u' => (Direction::Up, 1.0, GHOST_VELOCITY),
+                'X' => (Direction::Down, CHOP_LENGTH, 1.0),
+                'x' => (Direction::Down, CHOP_LENGTH, GHOST_VELOCITY),
+                '-' => {
+                    match hits.last_mut() {
+                        Some(hit) if tie_open => hit.length += 1.0,
+                        _ => return Err(invalid(format!("tie at step {step} follows no hit"))),
+                    }
+                    continue;
+                }
+                '.' => {
+                    tie_open = false;
+                    continue;
+                }
+                other => return Err(invalid(format!("unexpected {other:?} at step {step}"))),
+            };
+            tie_open = length >= 1.0;
+            hits.push(StrumHit {
+                step,
+                length,
+                velocity,
+                direction,
+            });
+        }
+
+        if hits.is_empty() {
+            return Err(invalid("pattern has no hits".to_string()));
+        }
+        Ok(StrumPattern { steps, hits })
+    }
+
+    /// A pattern from the built-in library.
+    pub fn named(name: &str) -> Option<Self> {
+        LIBRARY
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, notation)| Self::parse(notation).expect("library patterns are valid"))
+    }
+
+    /// Names of the built-in patterns.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        LIBRARY.iter().map(|(name, _)| *name)
+    }
+
+    /// Hit start offsets and lengths in ticks through one measure of `meter`.
+    /// The pattern spans four quarter notes whatever the meter, so it starts
+    /// again to fill a longer measure and stops at the bar line of a shorter
+    /// one: "quarters" strums three times in 3/4.
+    pub fn hit_ticks(&self, meter: Meter) -> impl Iterator<Item = (u32, u32, &StrumHit)> {
+        let cycle = u32::from(meter.ppq) * 4;
+        let ticks_per_measure = meter.ticks_per_measure();
+        let step_ticks = f64::from(cycle) / f64::from(self.steps);
+        (0..ticks_per_measure.div_ceil(cycle.max(1)))
+            .flat_map(move |repeat| {
+                self.hits.iter().map(move |hit| {
+                    let offset = repeat * cycle + (f64::from(hit.step) * step_ticks).round() as u32;
+                    let length = (f64::from(hit.length) * step_ticks).round().max(1.0) as u32;
+                    (offset, length, hit)
+                })
+            })
+            .filter(move |&(offset, _, _)| offset < ticks_per_measure)
+            .map(move |(offset, length, hit)| (offset, length.min(ticks_per_measure - offset), hit))
+    }
+}
+
+impl Default for StrumPattern {
+    fn default() -> Self {
+        Self::named("quarters").expect("default pattern exists")
+    }
+}
+
+impl FromStr for StrumPattern {
+    type Err = Error;
+
+    /// A library name ("folk", "bossa", ...) or compact notation ("D.DU.UDU").
+    fn from_str(s: &str) -> Result<Self> {
+        match Self::named(s.trim()) {
+            Some(pattern) => Ok(pattern),
+            None => Self::parse(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_compact_notation() {
+        let pattern = StrumPattern::parse("D.DU.UDU").unwrap();
+        assert_eq!(pattern.steps, 8);
+        let steps: Vec<u32> = pattern.hits.iter().map(|h| h.step).collect();
+        assert_eq!(steps, vec![0, 2, 3, 5, 6, 7]);
+        assert_eq!(pattern.hits[2].direction, Direction::Up);
+
+        let ticks: Vec<(u32, u32)> = pattern
+            .hit_ticks(Meter::default())
+            .map(|(o, l, _)| (o, l))
+            .collect();
+        assert_eq!(ticks[..3], [(0, 240), (480, 240), (720, 240)]);
+    }
+
+    #[test]
+    fn test_ties_ghosts_and_chops() {
+        let pattern = StrumPattern::parse("D-u.x...").unwrap();
+        assert_eq!(pattern.hits[0].length, 2.0);
+        assert_eq!(pattern.hits[1].velocity, GHOST_VELOCITY);
+        assert_eq!(pattern.hits[2].length, CHOP_LENGTH);
+
+        assert!(StrumPattern::parse("-D").is_err());
+        assert!(StrumPattern::parse("D.-").is_err());
+        assert!(StrumPattern::parse("x-").is_err());
+        assert!(StrumPattern::parse("....").is_err());
+        assert!(matches!(
+            StrumPattern::parse("DQ"),
+            Err(Error::InvalidPattern { .. })
+        ));
+    }
+
+    #[test]
+    fn test_library_patterns() {
+        for name in StrumPattern::names() {
+            assert!(StrumPattern::named(name).is_some(), "{name}");
+        }
+        let charleston: StrumPattern = "Charleston".parse().unwrap();
+        let offsets: Vec<u32> = charleston
+            .hit_ticks(Meter::default())
+            .map(|(o, _, _)| o)
+            .collect();
+        assert_eq!(offsets, vec![0, 720]);
+        assert_eq!(StrumPattern::default().hits.len(), 4);
+    }
+
+    #[test]
+    fn test_grid_keeps_its_step_length_in_other_meters() {
+        let ticks = |name: &str, time_signature| -> Vec<(u32, u32)> {
+            StrumPattern::named(name)
+                .unwrap()
+                .hit_ticks(Meter::new(480, time_signature))
+                .map(|(o, l, _)| (o, l))
+                .collect()
+        };
+        assert_eq!(
+            ticks("quarters", (3, 4)),
+            [(0, 480), (480, 480), (960, 480)]
+        );
+        let offsets: Vec<u32> = ticks("eighths", (6, 8)).iter().map(|t| t.0).collect();
+        assert_eq!(offsets, [0, 240, 480, 720, 960, 1200]);
+        // 5/4 starts the pattern again on beat 5; a tie stops at the bar line.
+        assert_eq!(ticks("halves", (5, 4)), [(0, 960), (960, 960), (1920, 480)]);
+    }
+}
@@ -0,0 +1,470 @@
+//! Keys, modes and key-relative progressions.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chord::Chord;
+use crate::chord_symbol::{
+    chord_from_pitch_classes, parse_chord, parse_note_name, parse_quality, ParseChordError,
+    PITCH_CLASS_NAMES,
+};
+
+// ---------------------------------------------------------------------
+// Keys, modes and key-relative progressions ("I vi IV V", "1 6m 4 5")
+// ---------------------------------------------------------------------
+
+/// Diatonic mode a key is built on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Ionian.
+    Major,
+    /// Natural minor (Aeolian).
+    Minor,
+    /// Minor with a raised sixth.
+    Dorian,
+    /// Minor with a flat second.
+    Phrygian,
+    /// Major with a raised fourth.
+    Lydian,
+    /// Major with a flat seventh.
+    Mixolydian,
+    /// Diminished tonic, flat second and fifth.
+    Locrian,
+}
+
+impl Mode {
+    /// Semitone offsets of the seven scale degrees above the tonic.
+    pub fn degrees(self) -> [u8; 7] {
+        match self {
+            Mode::Major => [0, 2, 4, 5, 7, 9, 11],
+            Mode::Minor => [0, 2, 3, 5, 7, 8, 10],
+            Mode::Dorian => [0, 2, 3, 5, 7, 9, 10],
+            Mode::Phrygian => [0, 1, 3, 5, 7, 8, 10],
+            Mode::Lydian => [0, 2, 4, 6, 7, 9, 11],
+            Mode::Mixolydian => [0, 2, 4, 5, 7, 9, 10],
+            Mode::Locrian => [0, 1, 3, 5, 6, 8, 10],
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "" | "major" | "maj" | "ionian" => Mode::Major,
+            "m" | "minor" | "min" | "aeolian" => Mode::Minor,
+            "dorian" => Mode::Dorian,
+            "phrygian" => Mode::Phrygian,
+            "lydian" => Mode::Lydian,
+            "mixolydian" | "mixo" => Mode::Mixolydian,
+            "locrian" => Mode::Locrian,
+            other => return Err(format!("unknown mode {other:?}")),
+        })
+    }
+}
+
+/// A tonic pitch class (0 = C .. 11 = B) together with its mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    /// Pitch class of the tonic (0 = C .. 11 = B).
+    pub tonic: u8,
+    /// Mode the scale is built on.
+    pub mode: Mode,
+}
+
+impl Key {
+    /// A key on `tonic` (taken modulo 12) in `mode`.
+    pub fn new(tonic: u8, mode: Mode) -> Self {
+        Key {
+            tonic: tonic % 12,
+            mode,
+        }
+    }
+
+    /// Pitch class of scale degree `degree` (1-based, 1..=7), or `None` for
+    /// any other degree.
+    pub fn degree_pitch_class(&self, degree: u8) -> Option<u8> {
+        if !(1..=7).contains(&degree) {
+            return None;
+        }
+        let offset = self.mode.degrees()[usize::from(degree - 1)];
+        Some((self.tonic + offset) % 12)
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    /// Parse "C", "Am", "F# minor", "Eb major" or "D dorian".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let (tonic, consumed) =
+            parse_note_name(text).ok_or_else(|| format!("cannot parse key {s:?}"))?;
+        let mode = text[consumed..].parse::<Mode>()?;
+        Ok(Key::new(tonic, mode))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?}",
+            PITCH_CLASS_NAMES[usize::from(self.tonic)],
+            self.mode
+        )
+    }
+}
+
+/// Split a progression into chord tokens.
+///
+/// Tokens are separated by whitespace, bar lines, commas and en/em dashes,
+/// except inside parentheses, so "C7(b9,#11)" stays one token. An ASCII '-' also separates degrees ("I-vi-IV-V", "1-6m-4-5") but is kept
+/// when it is part of a chord suffix ("C-7", "F#m7-b5", "4-"): it only splits
+/// before a Roman numeral, or before a Nashville degree that follows another
+/// degree.
+pub(crate) fn split_tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+    for (idx, c) in text.char_indices() {
+        let separator = match c {
+            '(' => {
+                depth += 1;
+                false
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            _ if depth > 0 => false,
+            c if c.is_whitespace() => true,
+            '|' | ',' | '–' | '—' => true,
+            '-' if idx > start => match degree_start(&text[idx + 1..]) {
+                Some('I' | 'V' | 'i' | 'v') => true,
+                Some(_) => degree_start(&text[start..idx]).is_some(),
+                None => false,
+            },
+            _ => false,
+        };
+        if separator {
+            if idx > start {
+                tokens.push(&text[start..idx]);
+            }
+            start = idx + c.len_utf8();
+        }
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// The numeral or digit `text` opens with when it starts a Roman numeral or
+/// Nashville degree (`[b#]?[IViv1-7]`).
+fn degree_start(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    let first = chars.next()?;
+    let c = match first {
+        'b' | '#' | '♭' | '♯' => chars.next()?,
+        c => c,
+    };
+    matches!(c, 'I' | 'V' | 'i' | 'v' | '1'..='7').then_some(c)
+}
+
+/// Resolve a whole progression against `key`.
+///
+/// Each token may be a Roman numeral ("ii7", "bVII", "V7/V"), a Nashville
+/// number ("6m", "4/5", "b7") or a literal chord symbol ("Am7", "D/F#").
+///
+/// Without bar lines every token lasts a bar. With them, the tokens of a bar
+/// share it evenly, so "| C G/B | Am . . . |" plays C and G/B for half a bar
+/// each and Am for a whole one: a "." holds the previous chord for its share
+/// of the bar, and a bar of nothing but dots holds it for the whole bar. A
+/// leading '<' pushes a chord so it lands early ("| C . . <F |").
+pub fn parse_progression(text: &str, key: Key) -> Result<Vec<Chord>, ParseChordError> {
+    let bars: Vec<&str> = if text.contains('|') {
+        text.split('|').collect()
+    } else {
+        split_tokens(text)
+    };
+
+    let mut chords: Vec<Chord> = Vec::new();
+    for bar in bars {
+        let tokens = split_tokens(bar);
+        let share = 1.0 / tokens.len().max(1) as f64;
+        for token in tokens {
+            if token == "." {
+                chords
+                    .last_mut()
+                    .ok_or_else(|| ParseChordError::new(token, "no chord before it to hold"))?
+                    .measures += share;
+                continue;
+            }
+            let (push, token) = match token.strip_prefix('<') {
+                Some(rest) => (true, rest),
+                None => (false, token),
+            };
+            chords.push(Chord {
+                measures: share,
+                push,
+                ..parse_token(token, key)?
+            });
+        }
+    }
+    Ok(chords)
+}
+
+/// Resolve a single progression token against `key`.
+pub fn parse_token(token: &str, key: Key) -> Result<Chord, ParseChordError> {
+    let body = token.trim_start_matches(['b', '#', '♭', '♯']);
+    match body.chars().next() {
+        Some(c) if c.is_ascii_digit() => parse_nashville(token, key),
+        Some('I' | 'V' | 'i' | 'v') => parse_roman(token, key),
+        _ => parse_chord(token),
+    }
+}
+
+/// Read leading accidentals, returning the semitone shift and the remainder.
+fn eat_accidentals(text: &str) -> (i8, &str) {
+    let mut shift = 0i8;
+    let mut rest = text;
+    loop {
+        if let Some(r) = rest.strip_prefix(['b', '♭']) {
+            shift -= 1;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix(['#', '♯']) {
+            shift += 1;
+            rest = r;
+        } else {
+            return (shift, rest);
+        }
+    }
+}
+
+/// Read a Roman numeral, returning its degree, whether it is upper case and the remainder.
+fn eat_roman(text: &str) -> Option<(u8, bool, &str)> {
+    const NUMERALS: [(&str, u8); 7] = [
+        ("VII", 7),
+        ("III", 3),
+        ("IV", 4),
+        ("VI", 6),
+        ("II", 2),
+        ("V", 5),
+        ("I", 1),
+    ];
+    for (numeral, degree) in NUMERALS {
+        if let Some(rest) = text.strip_prefix(numeral) {
+            return Some((degree, true, rest));
+        }
+        if let Some(rest) = text.strip_prefix(numeral.to_ascii_lowercase().as_str()) {
+            return Some((degree, false, rest));
+        }
+    }
+    None
+}
+
+/// Pitch class of an optionally altered degree. Unaltered degrees follow the
+/// key's mode; altered ones ("bVII", "b3") are measured from the major scale
+/// on the same tonic, so "i bVI bVII" reads the same in any minor mode.
+fn shifted_pitch_class(key: Key, degree: u8, shift: i8) -> Option<u8> {
+    if shift == 0 {
+        return key.degree_pitch_class(degree);
+    }
+    let major = Key::new(key.tonic, Mode::Major);
+    let natural = major.degree_pitch_class(degree)?;
+    Some((i16::from(natural) + i16::from(shift)).rem_euclid(12) as u8)
+}
+
+/// Parse a Roman numeral chord. Case gives the triad quality; the suffix
+/// uses chord symbol syntax ("7", "maj7", "°7", "ø7", "sus4"). A trailing
+/// "/V" makes it a secondary chord of that degree.
+fn parse_roman(token: &str, key: Key) -> Result<Chord, ParseChordError> {
+    let (primary, target) = match token.split_once('/') {
+        Some((primary, target)) => (primary, Some(target)),
+        None => (token, None),
+    };
+
+    // Secondary chords are spelled in the major key of their target degree.
+    let key = match target {
+        Some(target) => {
+            let (shift, rest) = eat_accidentals(target);
+            let tonic = eat_roman(rest)
+                .filter(|(_, _, rest)| rest.is_empty())
+                .and_then(|(degree, _, _)| shifted_pitch_class(key, degree, shift))
+                .ok_or_else(|| ParseChordError::new(token, "expected a Roman numeral after '/'"))?;
+            Key::new(tonic, Mode::Major)
+        }
+        None => key,
+    };
+
+    let (shift, rest) = eat_accidentals(primary);
+    let (root, upper, suffix) = eat_roman(rest)
+        .and_then(|(degree, upper, suffix)| {
+            Some((shifted_pitch_class(key, degree, shift)?, upper, suffix))
+        })
+        .ok_or_else(|| ParseChordError::new(token, "expected a Roman numeral"))?;
+
+    let diminished_suffix = ["°", "o", "dim", "ø", "Ø"]
+        .iter()
+        .any(|p| suffix.starts_with(p));
+    let quality = if upper || diminished_suffix {
+        suffix.to_string()
+    } else {
+        format!("m{suffix}")
+    };
+    let intervals =
+        parse_quality(&quality).map_err(|reason| ParseChordError::new(token, reason))?;
+
+    Ok(chord_from_pitch_classes(root, intervals, None))
+}
+
+/// Read an optionally altered degree number 1-7, returning its pitch class and the remainder.
+fn eat_nashville_degree(text: &str, key: Key) -> Option<(u8, &str)> {
+    let (shift, rest) = eat_accidentals(text);
+    let digit = rest.chars().next()?.to_digit(10)? as u8;
+    Some((shifted_pitch_class(key, digit, shift)?, &rest[1..]))
+}
+
+/// Parse a Nashville number chord ("1", "6m", "2m7", "b7", "4/5").
+/// Numbers are major unless the suffix says otherwise.
+fn parse_nashville(token: &str, key: Key) -> Result<Chord, ParseChordError> {
+    let (primary, bass) = match token.split_once('/') {
+        Some((primary, bass)) => (primary, Some(bass)),
+        None => (token, None),
+    };
+
+    let (root_pc, suffix) = eat_nashville_degree(primary, key)
+        .ok_or_else(|| ParseChordError::new(token, "expected a scale degree 1-7"))?;
+    let bass_pc = match bass {
+        Some(bass) => match eat_nashville_degree(bass, key) {
+            Some((pc, "")) => Some(pc),
+            _ => {
+                return Err(ParseChordError::new(
+                    token,
+                    "expected a bass degree 1-7 after '/'",
+                ))
+            }
+        },
+        None => None,
+    };
+    let intervals = parse_quality(suffix).map_err(|reason| ParseChordError::new(token, reason))?;
+
+    Ok(chord_from_pitch_classes(root_pc, intervals, bass_pc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roots(text: &str, key: &str) -> Vec<u8> {
+        parse_progression(text, key.parse().unwrap())
+            .unwrap()
+            .iter()
+            .map(|c| c.root)
+            .collect()
+    }
+
+    #[test]
+    fn test_roman_numerals_follow_key() {
+        assert_eq!(roots("I–vi–IV–V", "C"), vec![60, 69, 65, 67]);
+        assert_eq!(roots("I-vi-IV-V", "G"), vec![67, 64, 60, 62]);
+        assert_eq!(roots("i bVI bVII", "A minor"), vec![69, 65, 67]);
+        assert_eq!(roots("i VI VII", "Am"), vec![69, 65, 67]);
+
+        let key = Key::new(0, Mode::Major);
+        let chords = parse_progression("ii7 V7 Imaj7 vii°", key).unwrap();
+        assert_eq!(chords[0].intervals, vec![0, 3, 7, 10]);
+        assert_eq!(chords[1].intervals, vec![0, 4, 7, 10]);
+        assert_eq!(chords[2].intervals, vec![0, 4, 7, 11]);
+        assert_eq!(chords[3].intervals, vec![0, 3, 6]);
+    }
+
+    #[test]
+    fn test_degree_pitch_class_rejects_degrees_outside_the_scale() {
+        let key = Key::new(2, Mode::Minor);
+        assert_eq!(key.degree_pitch_class(1), Some(2));
+        assert_eq!(key.degree_pitch_class(7), Some(0));
+        assert_eq!(key.degree_pitch_class(0), None);
+        assert_eq!(key.degree_pitch_class(8), None);
+    }
+
+    #[test]
+    fn test_secondary_dominants() {
+        let key = Key::new(0, Mode::Major);
+        let v_of_v = parse_token("V7/V", key).unwrap();
+        assert_eq!(v_of_v.root, 62);
+        assert_eq!(v_of_v.intervals, vec![0, 4, 7, 10]);
+        // Leading tone of D is C#.
+        assert_eq!(parse_token("vii°/ii", key).unwrap().root, 61);
+    }
+
+    #[test]
+    fn test_nashville_numbers() {
+        assert_eq!(roots("1 6m 4 5", "C"), vec![60, 69, 65, 67]);
+        assert_eq!(roots("1 6m 4 5", "D"), vec![62, 71, 67, 69]);
+        let key: Key = "G".parse().unwrap();
+        let chords = parse_progression("2m7 b7 4/5", key).unwrap();
+        assert_eq!(chords[0].intervals, vec![0, 3, 7, 10]);
+        assert_eq!(chords[1].root, 65);
+        assert_eq!(chords[2].root, 60);
+        assert_eq!(chords[2].bass, Some(50));
+    }
+
+    #[test]
+    fn test_mixed_tokens_and_errors() {
+        let key = Key::new(0, Mode::Major);
+        assert_eq!(parse_progression("Am7 | IV | 5", key).unwrap().len(), 3);
+        assert!(parse_token("8", key).is_err());
+        assert!(parse_token("V/x", key).is_err());
+        assert!("H lydian".parse::<Key>().is_err());
+        assert!("C bogus".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn test_dash_splits_degrees_but_not_suffixes() {
+        let key = Key::new(0, Mode::Major);
+        assert_eq!(roots("I-vi-IV-V", "C"), vec![60, 69, 65, 67]);
+        assert_eq!(roots("1-6m-4-b7", "C"), vec![60, 69, 65, 70]);
+        let chords = parse_progression("F#m7-b5", key).unwrap();
+        assert_eq!(chords.len(), 1);
+        assert_eq!(
+            (chords[0].root, chords[0].intervals.as_slice()),
+            (66, &[0, 3, 6, 10][..])
+        );
+        assert_eq!(split_tokens("C7-b9 C-7 4-"), vec!["C7-b9", "C-7", "4-"]);
+    }
+
+    #[test]
+    fn test_commas_inside_parentheses_stay_in_the_chord() {
+        assert_eq!(
+            split_tokens("C7(b9,#11), F | G7(#5, b9)"),
+            vec!["C7(b9,#11)", "F", "G7(#5, b9)"]
+        );
+        let key = Key::new(0, Mode::Major);
+        assert_eq!(parse_progression("C7(b9,#11) F", key).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_bar_lines_set_the_harmonic_rhythm() {
+        let key = Key::new(0, Mode::Major);
+        let chords = parse_progression("| C G/B | Am . . . | . | IV . . <V |", key).unwrap();
+        let timing: Vec<(f64, bool)> = chords.iter().map(|c| (c.measures, c.push)).collect();
+        assert_eq!(
+            timing,
+            [
+                (0.5, false),
+                (0.5, false),
+                (2.0, false),
+                (0.75, false),
+                (0.25, true)
+            ]
+        );
+        assert_eq!(chords[1].bass, Some(59));
+        // Without bar lines a dot holds the chord for another bar.
+        let held = parse_progression("I . V", key).unwrap();
+        assert_eq!((held[0].measures, held[1].measures), (2.0, 1.0));
+        assert!(parse_progression("| . C |", key).is_err());
+    }
+}
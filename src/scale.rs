@@ -0,0 +1,312 @@
+//! Scales and modes, their diatonic chords, and pitch quantizing.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chord::Chord;
+use crate::chord_symbol::{chord_from_pitch_classes, parse_note_name, PITCH_CLASS_NAMES};
+use crate::progression::{Key, Mode};
+
+// ---------------------------------------------------------------------
+// Scales and modes: pitch sets, diatonic chords and quantizing
+// ---------------------------------------------------------------------
+
+/// Interval structure of a scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleKind {
+    /// Ionian.
+    Major,
+    /// Aeolian.
+    NaturalMinor,
+    /// Natural minor with a raised seventh.
+    HarmonicMinor,
+    /// The ascending form, also used descending in jazz.
+    MelodicMinor,
+    /// Minor with a raised sixth.
+    Dorian,
+    /// Minor with a flat second.
+    Phrygian,
+    /// Major with a raised fourth.
+    Lydian,
+    /// Major with a flat seventh.
+    Mixolydian,
+    /// Diminished tonic, flat second and fifth.
+    Locrian,
+    /// Major scale without the fourth and seventh.
+    MajorPentatonic,
+    /// Natural minor without the second and sixth.
+    MinorPentatonic,
+    /// Minor pentatonic plus the flat fifth.
+    Blues,
+    /// Six whole steps.
+    WholeTone,
+    /// Octatonic, starting with a whole step.
+    DiminishedWholeHalf,
+    /// Octatonic, starting with a half step (the dominant diminished scale).
+    DiminishedHalfWhole,
+}
+
+impl ScaleKind {
+    /// Semitone offsets of each scale step above the tonic, ascending.
+    pub fn intervals(self) -> &'static [u8] {
+        match self {
+            ScaleKind::Major => &[0, 2, 4, 5, 7, 9, 11],
+            ScaleKind::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+            ScaleKind::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            ScaleKind::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+            ScaleKind::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            ScaleKind::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            ScaleKind::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            ScaleKind::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            ScaleKind::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            ScaleKind::MajorPentatonic => &[0, 2, 4, 7, 9],
+            ScaleKind::MinorPentatonic => &[0, 3, 5, 7, 10],
+            ScaleKind::Blues => &[0, 3, 5, 6, 7, 10],
+            ScaleKind::WholeTone => &[0, 2, 4, 6, 8, 10],
+            ScaleKind::DiminishedWholeHalf => &[0, 2, 3, 5, 6, 8, 9, 11],
+            ScaleKind::DiminishedHalfWhole => &[0, 1, 3, 4, 6, 7, 9, 10],
+        }
+    }
+}
+
+impl From<Mode> for ScaleKind {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Major => ScaleKind::Major,
+            Mode::Minor => ScaleKind::NaturalMinor,
+            Mode::Dorian => ScaleKind::Dorian,
+            Mode::Phrygian => ScaleKind::Phrygian,
+            Mode::Lydian => ScaleKind::Lydian,
+            Mode::Mixolydian => ScaleKind::Mixolydian,
+            Mode::Locrian => ScaleKind::Locrian,
+        }
+    }
+}
+
+impl FromStr for ScaleKind {
+    type Err = String;
+
+    /// Names are case-insensitive; spaces, dashes and underscores are
+    /// interchangeable ("harmonic minor", "whole-tone", "half_whole").
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .trim()
+            .to_ascii_lowercase()
+            .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        Ok(match name.as_str() {
+            "" | "major" | "maj" | "ionian" => ScaleKind::Major,
+            "m" | "minor" | "min" | "aeolian" | "natural-minor" => ScaleKind::NaturalMinor,
+            "harmonic-minor" => ScaleKind::HarmonicMinor,
+            "melodic-minor" | "jazz-minor" => ScaleKind::MelodicMinor,
+            "dorian" => ScaleKind::Dorian,
+            "phrygian" => ScaleKind::Phrygian,
+            "lydian" => ScaleKind::Lydian,
+            "mixolydian" | "mixo" => ScaleKind::Mixolydian,
+            "locrian" => ScaleKind::Locrian,
+            "pentatonic" | "major-pentatonic" => ScaleKind::MajorPentatonic,
+            "minor-pentatonic" | "m-pentatonic" => ScaleKind::MinorPentatonic,
+            "blues" => ScaleKind::Blues,
+            "whole-tone" | "wholetone" => ScaleKind::WholeTone,
+            "diminished" | "whole-half" => ScaleKind::DiminishedWholeHalf,
+            "half-whole" | "dominant-diminished" => ScaleKind::DiminishedHalfWhole,
+            other => return Err(format!("unknown scale {other:?}")),
+        })
+    }
+}
+
+/// A scale on a tonic pitch class (0 = C .. 11 = B).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scale {
+    /// Pitch class of the tonic.
+    pub tonic: u8,
+    /// Interval structure.
+    pub kind: ScaleKind,
+}
+
+impl Scale {
+    /// A scale on `tonic` (taken modulo 12).
+    pub fn new(tonic: u8, kind: ScaleKind) -> Self {
+        Scale {
+            tonic: tonic % 12,
+            kind,
+        }
+    }
+
+    /// Number of notes per octave.
+    pub fn len(&self) -> usize {
+        self.kind.intervals().len()
+    }
+
+    /// Whether the scale has no notes (never true for the built-in kinds).
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pitch classes of the scale steps, starting from the tonic.
+    pub fn pitch_classes(&self) -> Vec<u8> {
+        self.kind
+            .intervals()
+            .iter()
+            .map(|&interval| (self.tonic + interval) % 12)
+            .collect()
+    }
+
+    /// Whether `note` (any MIDI note or pitch) belongs to the scale.
+    pub fn contains(&self, note: i32) -> bool {
+        let offset = (note - i32::from(self.tonic)).rem_euclid(12) as u8;
+        self.kind.intervals().contains(&offset)
+    }
+
+    /// The scale note nearest to `note`; a note halfway between two scale
+    /// notes moves down.
+    pub fn quantize(&self, note: i32) -> i32 {
+        (0..=6)
+            .flat_map(|distance| [note - distance, note + distance])
+            .find(|&candidate| self.contains(candidate))
+            .unwrap_or(note)
+    }
+
+    /// The chord stacked in thirds on step `degree` (1-based), taking every
+    /// other scale note: `size` 3 for a triad, 4 for a seventh chord. On
+    /// scales that are not heptatonic this still stacks alternate steps,
+    /// e.g. quartal shapes on a pentatonic scale.
+    pub fn chord_on(&self, degree: usize, size: usize) -> Chord {
+        let intervals = self.kind.intervals();
+        let count = intervals.len();
+        let index = (degree.max(1) - 1) % count;
+        let base = intervals[index];
+        let stack = (0..size)
+            .map(|tone| {
+                let step = index + 2 * tone;
+                intervals[step % count] + 12 * (step / count) as u8 - base
+            })
+            .collect();
+        chord_from_pitch_classes((self.tonic + base) % 12, stack, None)
+    }
+
+    /// The triad on every scale step, rooted in the octave starting at middle C.
+    pub fn triads(&self) -> Vec<Chord> {
+        (1..=self.len())
+            .map(|degree| self.chord_on(degree, 3))
+            .collect()
+    }
+
+    /// The seventh chord on every scale step.
+    pub fn sevenths(&self) -> Vec<Chord> {
+        (1..=self.len())
+            .map(|degree| self.chord_on(degree, 4))
+            .collect()
+    }
+}
+
+impl From<Key> for Scale {
+    fn from(key: Key) -> Self {
+        Scale::new(key.tonic, key.mode.into())
+    }
+}
+
+impl FromStr for Scale {
+    type Err = String;
+
+    /// Parse a tonic followed by a scale name: "C", "Am", "A harmonic minor",
+    /// "Eb blues", "F# whole-tone".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let (tonic, consumed) =
+            parse_note_name(text).ok_or_else(|| format!("cannot parse scale {s:?}"))?;
+        Ok(Scale::new(tonic, text[consumed..].parse()?))
+    }
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?}",
+            PITCH_CLASS_NAMES[usize::from(self.tonic)],
+            self.kind
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intervals(chords: &[Chord]) -> Vec<Vec<u8>> {
+        chords.iter().map(|chord| chord.intervals.clone()).collect()
+    }
+
+    #[test]
+    fn test_parse_and_pitch_classes() {
+        let scale: Scale = "A harmonic minor".parse().unwrap();
+        assert_eq!(scale.pitch_classes(), vec![9, 11, 0, 2, 4, 5, 8]);
+        assert_eq!("Eb blues".parse::<Scale>().unwrap().len(), 6);
+        assert_eq!(
+            "C half_whole".parse::<Scale>().unwrap().kind,
+            ScaleKind::DiminishedHalfWhole
+        );
+        assert_eq!(
+            "Am".parse::<Scale>().unwrap(),
+            Scale::new(9, ScaleKind::NaturalMinor)
+        );
+        let key: Key = "D dorian".parse().unwrap();
+        assert_eq!(Scale::from(key), Scale::new(2, ScaleKind::Dorian));
+        assert!("C bebop".parse::<Scale>().is_err());
+    }
+
+    #[test]
+    fn test_diatonic_triads_and_sevenths() {
+        let c_major = Scale::new(0, ScaleKind::Major);
+        let triads = c_major.triads();
+        assert_eq!(triads[0], Chord::new(60, vec![0, 4, 7]));
+        assert_eq!(triads[1], Chord::new(62, vec![0, 3, 7]));
+        assert_eq!(triads[6], Chord::new(71, vec![0, 3, 6]));
+        assert_eq!(
+            intervals(&c_major.sevenths()),
+            vec![
+                vec![0, 4, 7, 11],
+                vec![0, 3, 7, 10],
+                vec![0, 3, 7, 10],
+                vec![0, 4, 7, 11],
+                vec![0, 4, 7, 10],
+                vec![0, 3, 7, 10],
+                vec![0, 3, 6, 10],
+            ]
+        );
+
+        let a_harmonic = Scale::new(9, ScaleKind::HarmonicMinor);
+        let sevenths = a_harmonic.sevenths();
+        assert_eq!(sevenths[0].intervals, vec![0, 3, 7, 11]);
+        assert_eq!(sevenths[2].intervals, vec![0, 4, 8, 11]);
+        assert_eq!(
+            (sevenths[4].root, &sevenths[4].intervals[..]),
+            (64, &[0, 4, 7, 10][..])
+        );
+        assert_eq!(
+            (sevenths[6].root, &sevenths[6].intervals[..]),
+            (68, &[0, 3, 6, 9][..])
+        );
+        assert!(Scale::new(0, ScaleKind::WholeTone)
+            .triads()
+            .iter()
+            .all(|chord| chord.intervals == [0, 4, 8]));
+    }
+
+    #[test]
+    fn test_quantize() {
+        let c_major = Scale::new(0, ScaleKind::Major);
+        assert_eq!(c_major.quantize(61), 60);
+        assert_eq!(c_major.quantize(66), 65);
+        assert_eq!(c_major.quantize(64), 64);
+        assert_eq!(c_major.quantize(-1), -1);
+        let pentatonic = Scale::new(0, ScaleKind::MinorPentatonic);
+        assert_eq!(pentatonic.quantize(61), 60);
+        assert_eq!(pentatonic.quantize(62), 63);
+        assert_eq!(pentatonic.quantize(69), 70);
+        assert!(pentatonic.contains(-2));
+    }
+}
@@ -0,0 +1,828 @@
+//! Standard MIDI Files assembled from the generated parts.
+
+use std::path::Path;
+
+use midly::num::u15;
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+
+use crate::arpeggio::{arpeggio_timeline, Arpeggio};
+use crate::bass::{bass_track_timeline, BassLine};
+use crate::chord::Chord;
+use crate::drums::{drum_sections_timeline, DrumPart, DRUM_CHANNEL};
+use crate::dynamics::{AccentMap, Envelope};
+use crate::error::Result;
+use crate::events::{channel_u4, scale_note_on, tick_u28};
+use crate::groove::Groove;
+use crate::harmonic_rhythm::{chord_spans, measure_count};
+use crate::humanize::Humanize;
+use crate::melody::{melody_timeline, Melody};
+use crate::pattern::StrumPattern;
+use crate::progression::{Key, Mode};
+use crate::song::Song;
+use crate::strum::{chord_track_timeline, StrumFeel, StrumSpread};
+use crate::tempo::{
+    key_signature_event, micros_per_quarter, time_signature_event, Meter, TempoMap,
+};
+use crate::timeline::Timeline;
+use crate::voice_leading::{voice_lead, VoiceLeading};
+use crate::voicing::{voice_progression, Register, Voicing};
+
+// ---------------------------------------------------------------------
+// SMF assembly
+// ---------------------------------------------------------------------
+
+/// Timing and playback settings for a generated file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmfSettings {
+    /// Ticks per quarter note.
+    pub ppq: u16,
+    /// Tempo at the start of the song.
+    pub tempo_bpm: f64,
+    /// Later tempo changes as (zero-based measure, BPM).
+    pub tempo_changes: Vec<(u32, f64)>,
+    /// Numerator and denominator, e.g. `(6, 8)`.
+    pub time_signature: (u8, u8),
+    /// Written as the file's key signature.
+    pub key: Key,
+    /// Zero-based MIDI channel.
+    pub channel: u8,
+    /// Note-on velocity of the chord track, before accents and dynamics.
+    pub velocity: u8,
+    /// Strum pattern played in every measure.
+    pub pattern: StrumPattern,
+    /// Delay between successive strings of each strum.
+    pub strum_spread: StrumSpread,
+    /// Velocity drop from the first to the last string of each strum, 0.0..=1.0.
+    pub strum_taper: f32,
+    /// Voicing applied to every chord; `None` plays chords as written.
+    pub voicing: Option<Voicing>,
+    /// Note range the voiced chords are placed in.
+    pub register: Register,
+    /// Choose each chord's voicing to move the voices as little as possible;
+    /// takes precedence over `voicing`.
+    pub voice_leading: bool,
+    /// With `voice_leading`, steer away from parallel fifths and octaves.
+    pub avoid_parallels: bool,
+    /// Arpeggiate the chords instead of strumming them.
+    pub arpeggio: Option<Arpeggio>,
+    /// Bass part written to a second track, following the chords as written.
+    pub bass: Option<BassLine>,
+    /// Drum part written to its own track on the GM percussion channel.
+    pub drums: Option<DrumPart>,
+    /// Lead melody over the chords, in `key`, on its own track.
+    pub melody: Option<Melody>,
+    /// Beat accents applied to every generated part.
+    pub accents: Option<AccentMap>,
+    /// Swing, shuffle or groove template applied to every generated part.
+    pub groove: Option<Groove>,
+    /// Velocity envelope over the whole file, applied to every part.
+    pub envelope: Option<Envelope>,
+    /// Write `envelope` as CC11 expression curves instead of scaling velocities.
+    pub expression: bool,
+    /// Random timing and velocity variation, applied after the groove.
+    pub humanize: Option<Humanize>,
+}
+
+impl Default for SmfSettings {
+    fn default() -> Self {
+        SmfSettings {
+            ppq: 480,
+            tempo_bpm: 120.0,
+            tempo_changes: Vec::new(),
+            time_signature: (4, 4),
+            key: Key::new(0, Mode::Major),
+            channel: 0,
+            velocity: 64,
+            pattern: StrumPattern::default(),
+            strum_spread: StrumSpread::default(),
+            strum_taper: 0.0,
+            voicing: None,
+            register: Register::default(),
+            voice_leading: false,
+            avoid_parallels: false,
+            arpeggio: None,
+            bass: None,
+            drums: None,
+            melody: None,
+            accents: None,
+            groove: None,
+            envelope: None,
+            expression: false,
+            humanize: None,
+        }
+    }
+}
+
+/// Where one section of a song plays, and the dynamics it gives the parts
+/// that follow the whole song (melody, bass and drums).
+struct SectionRange {
+    /// First tick: the section's downbeat, or earlier when its first chord
+    /// is pushed.
+    start: u64,
+    bars: usize,
+    /// Velocity relative to the song-wide velocity.
+    scale: f64,
+    /// Crescendo or decrescendo across the section.
+    envelope: Option<Envelope>,
+}
+
+impl SmfSettings {
+    /// The configured time signature at this PPQ.
+    pub fn meter(&self) -> Meter {
+        Meter::new(self.ppq, self.time_signature)
+    }
+
+    /// Ticks in one measure of the configured time signature.
+    pub fn ticks_per_measure(&self) -> u32 {
+        self.meter().ticks_per_measure()
+    }
+
+    /// The strum feel with the spread converted to ticks at this tempo.
+    pub fn strum_feel(&self) -> StrumFeel {
+        StrumFeel {
+            spread_ticks: self.strum_spread.to_ticks(self.ppq, self.tempo_bpm),
+            taper: self.strum_taper,
+        }
+    }
+
+    /// Microseconds per quarter note for the starting tempo.
+    pub fn micros_per_quarter(&self) -> u32 {
+        micros_per_quarter(self.tempo_bpm)
+    }
+
+    /// The starting tempo plus every change, placed at the start of its measure.
+    pub fn tempo_map(&self) -> TempoMap {
+        let mut map = TempoMap::new(self.tempo_bpm);
+        for &(measure, bpm) in &self.tempo_changes {
+            map.set(measure.saturating_mul(self.ticks_per_measure()), bpm);
+        }
+        map
+    }
+
+    /// `chords` as they will sound: voice-led, voiced, or as written.
+    pub fn voiced_chords(&self, chords: &[Chord]) -> Result<Vec<Chord>> {
+        if self.voice_leading {
+            let options = VoiceLeading {
+                register: self.register,
+                avoid_parallels: self.avoid_parallels,
+            };
+            return voice_lead(chords, &options);
+        }
+        match self.voicing {
+            Some(voicing) => voice_progression(chords, voicing, self.register),
+            None => Ok(chords.to_vec()),
+        }
+    }
+
+    /// Tempo map, time signature and key signature events, failing for a
+    /// tempo a MIDI file cannot hold.
+    pub fn conductor_timeline(&self) -> Result<Timeline<'static>> {
+        let (numerator, denominator) = self.time_signature;
+        let mut timeline = Timeline::new();
+        timeline.push(0, time_signature_event(numerator, denominator));
+        timeline.push(0, key_signature_event(self.key));
+        timeline.merge(self.tempo_map().timeline()?);
+        Ok(timeline)
+    }
+
+    fn header(&self) -> Header {
+        Header {
+            format: Format::Parallel,
+            timing: Timing::Metrical(u15::from(self.ppq)),
+        }
+    }
+
+    /// `timeline` with the accents, then the groove, applied where set.
+    fn shaped<'a>(&self, mut timeline: Timeline<'a>) -> Timeline<'a> {
+        if let Some(accents) = &self.accents {
+            let beats = u32::from(self.time_signature.0);
+            accents.apply(&mut timeline, self.ticks_per_measure(), beats);
+        }
+        if let Some(groove) = &self.groove {
+            groove.apply(&mut timeline, self.ppq);
+        }
+        timeline
+    }
+
+    /// Apply the envelope and humanize `timeline` (where set) and convert it
+    /// to a track. `part` keeps each part's variation independent of which
+    /// other parts are present.
+    fn finish_track<'a>(
+        &self,
+        mut timeline: Timeline<'a>,
+        part: u64,
+    ) -> Result<Vec<TrackEvent<'a>>> {
+        if let Some(envelope) = &self.envelope {
+            if self.expression {
+                let step = u32::from(self.ppq) / 4;
+                let curve = envelope.expression(&timeline, self.ticks_per_measure(), step);
+                timeline.merge(curve);
+            } else {
+                envelope.apply(&mut timeline, self.ticks_per_measure());
+            }
+        }
+        if let Some(humanize) = &self.humanize {
+            humanize.apply(&mut timeline, part);
+        }
+        timeline.to_track()
+    }
+
+    /// Strummed or arpeggiated `voiced` chords from `start_tick`, shaped.
+    fn chord_part(
+        &self,
+        voiced: &[Chord],
+        pattern: &StrumPattern,
+        start_tick: u32,
+        velocity: u8,
+    ) -> Result<Timeline<'static>> {
+        Ok(self.shaped(self.chord_notes(voiced, pattern, start_tick, velocity)?))
+    }
+
+    /// Strummed or arpeggiated `voiced` chords from `start_tick`, unshaped.
+    fn chord_notes(
+        &self,
+        voiced: &[Chord],
+        pattern: &StrumPattern,
+        start_tick: u32,
+        velocity: u8,
+    ) -> Result<Timeline<'static>> {
+        let channel = channel_u4(self.channel)?;
+        let part = match &self.arpeggio {
+            Some(arpeggio) => arpeggio_timeline(
+                voiced,
+                arpeggio,
+                start_tick,
+                self.meter(),
+                channel,
+                velocity,
+            )?,
+            None => chord_track_timeline(
+                voiced,
+                pattern,
+                &self.strum_feel(),
+                start_tick,
+                self.meter(),
+                channel,
+                velocity,
+            )?,
+        };
+        Ok(part)
+    }
+
+    /// Scale the note-ons of `timeline` by the dynamics of the section each
+    /// falls in.
+    fn section_dynamics(&self, timeline: &mut Timeline, sections: &[SectionRange]) {
+        let ticks_per_measure = f64::from(self.ticks_per_measure());
+        for event in timeline.iter_mut() {
+            let index = sections.partition_point(|section| section.start <= u64::from(event.tick));
+            let Some(section) = index.checked_sub(1).map(|index| &sections[index]) else {
+                continue;
+            };
+            let hairpin = section.envelope.as_ref().map_or(1.0, |envelope| {
+                envelope.scale_at(f64::from(event.tick) / ticks_per_measure)
+            });
+            scale_note_on(&mut event.kind, section.scale * hairpin);
+        }
+    }
+
+    /// The melody, bass and drum tracks that are configured, following `chords`
+    /// through `sections`.
+    fn part_tracks(
+        &self,
+        chords: &[Chord],
+        sections: &[SectionRange],
+    ) -> Result<Vec<Vec<TrackEvent<'static>>>> {
+        let mut tracks = Vec::new();
+
+        if let Some(melody) = &self.melody {
+            let mut timeline = Timeline::new();
+            timeline.push(0, TrackEventKind::Meta(MetaMessage::TrackName(b"Melody")));
+            let mut notes = melody_timeline(
+                chords,
+                self.key,
+                melody,
+                0,
+                self.meter(),
+                channel_u4(melody.channel)?,
+            )?;
+            self.section_dynamics(&mut notes, sections);
+            timeline.merge(self.shaped(notes));
+            tracks.push(self.finish_track(timeline, 1)?);
+        }
+
+        if let Some(bass) = &self.bass {
+            let mut timeline = Timeline::new();
+            timeline.push(0, TrackEventKind::Meta(MetaMessage::TrackName(b"Bass")));
+            let mut notes =
+                bass_track_timeline(chords, bass, 0, self.meter(), channel_u4(bass.channel)?)?;
+            self.section_dynamics(&mut notes, sections);
+            timeline.merge(self.shaped(notes));
+            tracks.push(self.finish_track(timeline, 2)?);
+        }
+
+        if let Some(drums) = &self.drums {
+            let mut timeline = Timeline::new();
+            timeline.push(0, TrackEventKind::Meta(MetaMessage::TrackName(b"Drums")));
+            let bars: Vec<usize> = sections.iter().map(|section| section.bars).collect();
+            let mut notes =
+                drum_sections_timeline(&bars, drums, 0, self.meter(), channel_u4(DRUM_CHANNEL)?)?;
+            self.section_dynamics(&mut notes, sections);
+            timeline.merge(self.shaped(notes));
+            tracks.push(self.finish_track(timeline, 3)?);
+        }
+
+        Ok(tracks)
+    }
+}
+
+/// Build a `Format::Parallel` SMF strumming (or arpeggiating) `chords`, each
+/// lasting its own [`Chord::measures`] and landing early when pushed, with the
+/// tempo map, time signature and key signature at the head of the first track.
+/// Melody, bass and drum parts, if configured, each get a track of their own; a
+/// groove, if set, moves every part onto it.
+pub fn build_chord_smf(chords: &[Chord], settings: &SmfSettings) -> Result<Smf<'static>> {
+    let voiced = settings.voiced_chords(chords)?;
+    let mut timeline = settings.conductor_timeline()?;
+    timeline.merge(settings.chord_part(&voiced, &settings.pattern, 0, settings.velocity)?);
+    let whole = SectionRange {
+        start: 0,
+        bars: measure_count(chords),
+        scale: 1.0,
+        envelope: None,
+    };
+    let mut tracks = vec![settings.finish_track(timeline, 0)?];
+    tracks.extend(settings.part_tracks(chords, &[whole])?);
+    Ok(Smf {
+        header: settings.header(),
+        tracks,
+    })
+}
+
+/// Build an SMF playing `song` section by section, as [`build_chord_smf`]
+/// does for a single progression. Each section uses its own pattern and
+/// dynamics (including any crescendo or decrescendo) where it sets them, and
+/// starts with a marker carrying its name. Melody, bass and drums follow the
+/// section dynamics too, scaled by the section's velocity over the song-wide
+/// one, and the drums fill at the end of every section. A section whose first
+/// chord is pushed starts that early, cutting the section before it short.
+pub fn build_song_smf<'a>(song: &'a Song, settings: &SmfSettings) -> Result<Smf<'a>> {
+    let chords = song.chords()?;
+    let voiced = settings.voiced_chords(&chords)?;
+    let ticks_per_measure = settings.ticks_per_measure();
+    let spans = chord_spans(&voiced, 0, settings.meter());
+
+    let mut timeline = settings.conductor_timeline()?;
+    let mut sections = Vec::new();
+    let (mut bar, mut first) = (0, 0);
+    for section in song.parts()? {
+        let count = section.played_chords().len();
+        let downbeat = bar as u64 * u64::from(ticks_per_measure);
+        timeline.push(
+            tick_u28(downbeat)?.as_int(),
+            TrackEventKind::Meta(MetaMessage::Marker(section.name.as_bytes())),
+        );
+        let velocity = section.dynamic.map_or(settings.velocity, |d| d.velocity());
+        sections.push(SectionRange {
+            start: if count > 0 {
+                spans[first].start
+            } else {
+                downbeat
+            },
+            bars: section.bars,
+            scale: f64::from(velocity) / f64::from(settings.velocity.max(1)),
+            envelope: section.envelope(bar as f64),
+        });
+        bar += section.bars;
+        first += count;
+    }
+
+    // Each section's pattern and velocity play the whole song, so the chords
+    // on either side of a section boundary (pushed or not) are those of the
+    // song; only the section's own stretch is kept.
+    let mut chord_track = Timeline::new();
+    for (index, (section, range)) in song.parts()?.into_iter().zip(&sections).enumerate() {
+        let end = sections.get(index + 1).map_or(u64::MAX, |next| next.start);
+        let mut part = notes_between(
+            &settings.chord_notes(
+                &voiced,
+                section.pattern.as_ref().unwrap_or(&settings.pattern),
+                0,
+                section.dynamic.map_or(settings.velocity, |d| d.velocity()),
+            )?,
+            range.start,
+            end,
+        );
+        if let Some(envelope) = &range.envelope {
+            envelope.apply(&mut part, ticks_per_measure);
+        }
+        chord_track.merge(part);
+    }
+    timeline.merge(settings.shaped(chord_track));
+
+    let mut tracks = vec![settings.finish_track(timeline, 0)?];
+    tracks.extend(settings.part_tracks(&chords, &sections)?);
+    Ok(Smf {
+        header: settings.header(),
+        tracks,
+    })
+}
+
+/// The notes of `timeline` starting in `from..to`. Notes never sound across
+/// a chord change, so a note-off at `from` belongs to an earlier note and one
+/// at `to` to a kept note.
+fn notes_between<'a>(timeline: &Timeline<'a>, from: u64, to: u64) -> Timeline<'a> {
+    timeline
+        .iter()
+        .filter(|event| {
+            let tick = u64::from(event.tick);
+            match event.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { vel, .. },
+                    ..
+                } if vel > 0 => (from..to).contains(&tick),
+                _ => from < tick && tick <= to,
+            }
+        })
+        .copied()
+        .collect()
+}
+
+/// Write `smf` to `path`.
+pub fn write_smf(smf: &Smf, path: impl AsRef<Path>) -> Result<()> {
+    smf.save(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticks_per_measure_follows_time_signature() {
+        let mut settings = SmfSettings::default();
+        assert_eq!(settings.ticks_per_measure(), 1920);
+        settings.time_signature = (6, 8);
+        assert_eq!(settings.ticks_per_measure(), 1440);
+        settings.tempo_bpm = 90.0;
+        assert_eq!(settings.micros_per_quarter(), 666_667);
+    }
+
+    #[test]
+    fn test_conductor_events_lead_the_track() {
+        let settings = SmfSettings {
+            time_signature: (3, 4),
+            tempo_changes: vec![(2, 90.0)],
+            key: "E minor".parse().unwrap(),
+            ..SmfSettings::default()
+        };
+        let chords = vec![Chord::new(64, vec![0, 3, 7]); 3];
+        let smf = build_chord_smf(&chords, &settings).unwrap();
+        let track = &smf.tracks[0];
+
+        let metas: Vec<(u32, MetaMessage)> = {
+            let mut tick = 0;
+            track
+                .iter()
+                .filter_map(|ev| {
+                    tick += ev.delta.as_int();
+                    match ev.kind {
+                        TrackEventKind::Meta(meta) => Some((tick, meta)),
+                        _ => None,
+                    }
+                })
+                .collect()
+        };
+        assert_eq!(metas[0], (0, MetaMessage::TimeSignature(3, 2, 24, 8)));
+        assert_eq!(metas[1], (0, MetaMessage::KeySignature(1, true)));
+        assert_eq!(metas[2], (0, MetaMessage::Tempo(500_000.into())));
+        assert_eq!(metas[3], (2880, MetaMessage::Tempo(666_667.into())));
+        assert_eq!(metas.last().unwrap().1, MetaMessage::EndOfTrack);
+    }
+
+    #[test]
+    fn test_bass_gets_its_own_track_and_channel() {
+        let settings = SmfSettings {
+            bass: Some(BassLine::default()),
+            ..SmfSettings::default()
+        };
+        let chords = vec![Chord::new(60, vec![0, 4, 7]); 2];
+        let smf = build_chord_smf(&chords, &settings).unwrap();
+        assert_eq!(smf.tracks.len(), 2);
+        let bass = &smf.tracks[1];
+        assert_eq!(
+            bass[0].kind,
+            TrackEventKind::Meta(MetaMessage::TrackName(b"Bass"))
+        );
+        assert!(bass.iter().all(|ev| match ev.kind {
+            TrackEventKind::Midi { channel, .. } => channel == 1,
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn test_drums_follow_the_chord_measures() {
+        let settings = SmfSettings {
+            drums: Some(DrumPart::default()),
+            ..SmfSettings::default()
+        };
+        let chords = vec![Chord::new(60, vec![0, 4, 7]); 3];
+        let smf = build_chord_smf(&chords, &settings).unwrap();
+        assert_eq!(smf.tracks.len(), 2);
+        let drums = &smf.tracks[1];
+        let length: u32 = drums.iter().map(|ev| ev.delta.as_int()).sum();
+        assert!(length <= 3 * 1920);
+        assert!(drums.iter().all(|ev| match ev.kind {
+            TrackEventKind::Midi { channel, .. } => channel == DRUM_CHANNEL,
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn test_melody_track_comes_before_bass() {
+        let settings = SmfSettings {
+            melody: Some(Melody::default()),
+            bass: Some(BassLine::default()),
+            ..SmfSettings::default()
+        };
+        let chords = vec![Chord::new(60, vec![0, 4, 7]); 4];
+        let smf = build_chord_smf(&chords, &settings).unwrap();
+        assert_eq!(smf.tracks.len(), 3);
+        assert_eq!(
+            smf.tracks[1][0].kind,
+            TrackEventKind::Meta(MetaMessage::TrackName(b"Melody"))
+        );
+        assert!(smf.tracks[1].iter().any(|ev| matches!(
+            ev.kind,
+            TrackEventKind::Midi { channel, .. } if channel == 2
+        )));
+    }
+
+    #[test]
+    fn test_song_sections_get_markers_and_dynamics() {
+        let key: Key = "C".parse().unwrap();
+        let song = Song::parse(
+            "[verse] dynamics=p\nI IV\n[chorus] bars=1 dynamics=f\nV\n[outro] dynamics=f>p\nIV I\n\
+             arrangement: verse chorus verse outro",
+            key,
+        )
+        .unwrap();
+        let smf = build_song_smf(&song, &SmfSettings::default()).unwrap();
+        let mut tick = 0;
+        let mut markers = Vec::new();
+        let mut velocities = Vec::new();
+        for ev in &smf.tracks[0] {
+            tick += ev.delta.as_int();
+            match ev.kind {
+                TrackEventKind::Meta(MetaMessage::Marker(name)) => markers.push((tick, name)),
+                TrackEventKind::Midi {
+                    message: midly::MidiMessage::NoteOn { vel, .. },
+                    ..
+                } => velocities.push((tick, vel.as_int())),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            markers,
+            [
+                (0, &b"verse"[..]),
+                (3840, &b"chorus"[..]),
+                (5760, &b"verse"[..]),
+                (9600, &b"outro"[..])
+            ]
+        );
+        assert!(velocities.iter().all(|&(tick, vel)| match tick {
+            3840..=5759 => vel == 96,
+            9600 => vel == 96,
+            9601.. => vel < 96 && vel > 49,
+            _ => vel == 49,
+        }));
+        assert_eq!(tick, 7 * 1920);
+    }
+
+    #[test]
+    fn test_song_parts_follow_sections() {
+        let song = Song::parse(
+            "[verse] dynamics=p\nI IV\n[chorus] dynamics=f\n<V I",
+            "C".parse().unwrap(),
+        )
+        .unwrap();
+        let settings = SmfSettings {
+            bass: Some(BassLine::default()),
+            drums: Some(DrumPart::default()),
+            ..SmfSettings::default()
+        };
+        let smf = build_song_smf(&song, &settings).unwrap();
+        let note_ons = |track: &[TrackEvent]| -> Vec<(u32, u8, u8)> {
+            let mut tick = 0;
+            let mut ons = Vec::new();
+            for ev in track {
+                tick += ev.delta.as_int();
+                if let TrackEventKind::Midi {
+                    message: midly::MidiMessage::NoteOn { key, vel },
+                    ..
+                } = ev.kind
+                {
+                    ons.push((tick, key.as_int(), vel.as_int()));
+                }
+            }
+            ons
+        };
+
+        // The chorus's pushed V lands an eighth early in the chords and bass,
+        // at the chorus's dynamic.
+        let chords = note_ons(&smf.tracks[0]);
+        assert!(chords.contains(&(3600, 67, 96)));
+        assert!(chords
+            .iter()
+            .all(|&(tick, _, vel)| vel == if tick < 3600 { 49 } else { 96 }));
+
+        let bass: Vec<(u32, u8)> = note_ons(&smf.tracks[1])
+            .iter()
+            .map(|&(tick, _, vel)| (tick, vel))
+            .collect();
+        assert_eq!(
+            bass,
+            [
+                (0, 61),
+                (960, 55),
+                (1920, 61),
+                (2880, 55),
+                (3600, 120),
+                (4800, 108),
+                (5760, 120),
+                (6720, 108)
+            ]
+        );
+
+        // The drums fill at the end of the verse and crash into the chorus.
+        let crashes: Vec<u32> = note_ons(&smf.tracks[2])
+            .iter()
+            .filter(|on| on.1 == 49)
+            .map(|on| on.0)
+            .collect();
+        assert_eq!(crashes, [3840]);
+    }
+
+    #[test]
+    fn test_section_hairpins_reach_the_parts() {
+        let song = Song::parse("[outro] dynamics=f>p\nI IV V I", "C".parse().unwrap()).unwrap();
+        let settings = SmfSettings {
+            bass: Some(BassLine::default()),
+            ..SmfSettings::default()
+        };
+        let smf = build_song_smf(&song, &settings).unwrap();
+        let mut tick = 0;
+        let mut downbeats = Vec::new();
+        for ev in &smf.tracks[1] {
+            tick += ev.delta.as_int();
+            if let TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { vel, .. },
+                ..
+            } = ev.kind
+            {
+                if tick % 1920 == 0 {
+                    downbeats.push(vel.as_int());
+                }
+            }
+        }
+        assert_eq!(downbeats.len(), 4);
+        assert_eq!(downbeats[0], 120);
+        assert!(downbeats.windows(2).all(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    fn test_groove_moves_every_part() {
+        let settings = SmfSettings {
+            pattern: "DUDUDUDU".parse().unwrap(),
+            drums: Some(DrumPart {
+                fill_every: 0,
+                ..DrumPart::default()
+            }),
+            groove: Some(Groove::shuffle()),
+            ..SmfSettings::default()
+        };
+        let chords = vec![Chord::new(60, vec![0, 4, 7])];
+        let smf = build_chord_smf(&chords, &settings).unwrap();
+        for track in &smf.tracks {
+            let mut tick = 0;
+            let onsets: Vec<u32> = track
+                .iter()
+                .filter_map(|ev| {
+                    tick += ev.delta.as_int();
+                    match ev.kind {
+                        TrackEventKind::Midi {
+                            message: midly::MidiMessage::NoteOn { .. },
+                            ..
+                        } => Some(tick % 480),
+                        _ => None,
+                    }
+                })
+                .collect();
+            assert!(!onsets.is_empty());
+            assert!(onsets.iter().all(|&offset| offset == 0 || offset == 320));
+        }
+    }
+
+    #[test]
+    fn test_humanize_varies_each_part_reproducibly() {
+        let settings = SmfSettings {
+            bass: Some(BassLine::default()),
+            humanize: Some(Humanize {
+                seed: 3,
+                ..Humanize::default()
+            }),
+            ..SmfSettings::default()
+        };
+        let chords = vec![Chord::new(60, vec![0, 4, 7]); 4];
+        let smf = build_chord_smf(&chords, &settings).unwrap();
+        assert_eq!(smf, build_chord_smf(&chords, &settings).unwrap());
+        let plain = build_chord_smf(&chords, &SmfSettings::default()).unwrap();
+        assert_ne!(smf.tracks[0], plain.tracks[0]);
+        // The chord part is the same with or without the bass beside it.
+        let alone = SmfSettings {
+            bass: None,
+            ..settings.clone()
+        };
+        assert_eq!(
+            build_chord_smf(&chords, &alone).unwrap().tracks[0],
+            smf.tracks[0]
+        );
+    }
+
+    #[test]
+    fn test_accents_and_envelope_shape_the_velocities() {
+        let chords = vec![Chord::new(60, vec![0, 4, 7]); 4];
+        let note_ons = |smf: &Smf| {
+            let mut tick = 0;
+            let mut ons = Vec::new();
+            let mut expression = Vec::new();
+            for ev in &smf.tracks[0] {
+                tick += ev.delta.as_int();
+                match ev.kind {
+                    TrackEventKind::Midi {
+                        message: midly::MidiMessage::NoteOn { vel, .. },
+                        ..
+                    } => ons.push((tick, vel.as_int())),
+                    TrackEventKind::Midi {
+                        message: midly::MidiMessage::Controller { controller, value },
+                        ..
+                    } if controller == 11 => expression.push((tick, value.as_int())),
+                    _ => {}
+                }
+            }
+            (ons, expression)
+        };
+
+        let settings = SmfSettings {
+            velocity: 100,
+            accents: "downbeat".parse().ok(),
+            envelope: "1:0.5 4:1".parse().ok(),
+            ..SmfSettings::default()
+        };
+        let (ons, expression) = note_ons(&build_chord_smf(&chords, &settings).unwrap());
+        assert!(expression.is_empty());
+        assert_eq!(ons[0], (0, 60));
+        assert_eq!(ons[3], (480, 54));
+        assert_eq!(ons.last(), Some(&(7200, 100)));
+
+        let settings = SmfSettings {
+            expression: true,
+            ..settings
+        };
+        let (ons, expression) = note_ons(&build_chord_smf(&chords, &settings).unwrap());
+        assert_eq!((ons[0], ons[3]), ((0, 120), (480, 100)));
+        assert_eq!(expression[0], (0, 64));
+        assert_eq!(expression.last(), Some(&(5760, 127)));
+    }
+
+    #[test]
+    fn test_build_chord_smf_rejects_out_of_range_tempos() {
+        let chords = vec![Chord::new(60, vec![0, 4, 7])];
+        let settings = SmfSettings {
+            tempo_bpm: 0.0,
+            ..SmfSettings::default()
+        };
+        assert!(matches!(
+            build_chord_smf(&chords, &settings),
+            Err(crate::error::Error::InvalidTempo { .. })
+        ));
+        let settings = SmfSettings {
+            tempo_changes: vec![(1, -90.0)],
+            ..SmfSettings::default()
+        };
+        assert!(build_chord_smf(&chords, &settings).is_err());
+    }
+
+    #[test]
+    fn test_build_chord_smf_rejects_bad_channel() {
+        let settings = SmfSettings {
+            channel: 16,
+            ..SmfSettings::default()
+        };
+        let chords = vec![Chord::new(60, vec![0, 4, 7])];
+        assert!(matches!(
+            build_chord_smf(&chords, &settings),
+            Err(crate::error::Error::InvalidChannel { channel: 16 })
+        ));
+    }
+}
@@ -0,0 +1,388 @@
+//! Songs as named sections played in an arrangement.
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::chord::Chord;
+use crate::dynamics::Envelope;
+use crate::error::{Error, Result};
+use crate::harmonic_rhythm::{measure_count, total_measures};
+use crate::pattern::StrumPattern;
+use crate::progression::{parse_progression, Key};
+
+// ---------------------------------------------------------------------
+// Song structure: named sections played in an arrangement
+// ---------------------------------------------------------------------
+
+/// Loudness of a section, from pianissimo to fortissimo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dynamic {
+    /// `pp`, very soft.
+    Pianissimo,
+    /// `p`, soft.
+    Piano,
+    /// `mp`, moderately soft.
+    MezzoPiano,
+    /// `mf`, moderately loud.
+    MezzoForte,
+    /// `f`, loud.
+    Forte,
+    /// `ff`, very loud.
+    Fortissimo,
+}
+
+impl Dynamic {
+    /// Note-on velocity the section is played at.
+    pub fn velocity(self) -> u8 {
+        match self {
+            Dynamic::Pianissimo => 33,
+            Dynamic::Piano => 49,
+            Dynamic::MezzoPiano => 64,
+            Dynamic::MezzoForte => 80,
+            Dynamic::Forte => 96,
+            Dynamic::Fortissimo => 112,
+        }
+    }
+}
+
+impl FromStr for Dynamic {
+    type Err = String;
+
+    /// "pp", "p", "mp", "mf", "f" or "ff".
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "pp" => Dynamic::Pianissimo,
+            "p" => Dynamic::Piano,
+            "mp" => Dynamic::MezzoPiano,
+            "mf" => Dynamic::MezzoForte,
+            "f" => Dynamic::Forte,
+            "ff" => Dynamic::Fortissimo,
+            other => return Err(format!("unknown dynamic {other:?}")),
+        })
+    }
+}
+
+/// A named part of a song (intro, verse, chorus, ...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// Name used in the arrangement and the section's marker.
+    pub name: String,
+    /// The progression, each chord lasting its own [`Chord::measures`].
+    pub chords: Vec<Chord>,
+    /// Length in bars; the chords repeat (or are cut short) to fill it.
+    pub bars: usize,
+    /// Strum pattern for this section; `None` uses the song-wide pattern.
+    pub pattern: Option<StrumPattern>,
+    /// Loudness for this section; `None` uses the song-wide velocity.
+    pub dynamic: Option<Dynamic>,
+    /// Loudness reached by the end of the section, in a crescendo or
+    /// decrescendo from `dynamic`.
+    pub dynamic_end: Option<Dynamic>,
+}
+
+impl Section {
+    /// A section as long as its chords, with the song-wide pattern and velocity.
+    pub fn new(name: impl Into<String>, chords: Vec<Chord>) -> Self {
+        Section {
+            name: name.into(),
+            bars: measure_count(&chords),
+            chords,
+            pattern: None,
+            dynamic: None,
+            dynamic_end: None,
+        }
+    }
+
+    /// The crescendo or decrescendo across the section, for a section
+    /// starting at `start_measure`, relative to its starting velocity.
+    pub fn envelope(&self, start_measure: f64) -> Option<Envelope> {
+        let (start, end) = (self.dynamic?, self.dynamic_end?);
+        let to = f64::from(end.velocity()) / f64::from(start.velocity());
+        Some(Envelope::ramp(start_measure, self.bars as f64, 1.0, to))
+    }
+
+    /// The chords in playing order, repeated (and the last one cut short) to
+    /// fill the section's bars.
+    pub fn played_chords(&self) -> Vec<Chord> {
+        let bars = self.bars as f64;
+        if total_measures(&self.chords) <= 0.0 {
+            return Vec::new();
+        }
+        let mut played: Vec<Chord> = Vec::new();
+        let mut filled = 0.0;
+        for chord in self.chords.iter().cycle() {
+            if filled >= bars - 1e-9 {
+                break;
+            }
+            let mut chord = chord.clone();
+            chord.measures = chord.measures.min(bars - filled);
+            filled += chord.measures;
+            played.push(chord);
+        }
+        played
+    }
+}
+
+/// Sections plus the order they are played in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Song {
+    /// Every section the chart defines, in the order written.
+    pub sections: Vec<Section>,
+    /// Section names in playing order, repeats written out; empty plays every
+    /// section once, in order.
+    pub arrangement: Vec<String>,
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidSong {
+        reason: reason.into(),
+    }
+}
+
+/// Expand an arrangement such as "intro verse*2 chorus, outro" into one
+/// name per section played.
+pub fn parse_arrangement(text: &str) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for token in text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+    {
+        let (name, repeats) = match token.split_once('*') {
+            Some((name, count)) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => (name, count),
+                _ => return Err(invalid(format!("bad repeat count in {token:?}"))),
+            },
+            None => (token, 1),
+        };
+        names.extend(std::iter::repeat_n(name.to_string(), repeats));
+    }
+    Ok(names)
+}
+
+/// A section whose header has been read and whose progression is still
+/// being collected.
+struct PendingSection {
+    line: usize,
+    section: Section,
+    bars: Option<usize>,
+    progression: String,
+}
+
+impl PendingSection {
+    /// Read `name] bars=.. pattern=.. dynamics=..` (the text after '[');
+    /// dynamics are a single mark or a hairpin such as `p<f` or `ff>mp`.
+    fn parse_header(line: usize, header: &str) -> Result<Self> {
+        let bad = |reason: String| invalid(format!("line {line}: {reason}"));
+        let (name, settings) = header
+            .split_once(']')
+            .ok_or_else(|| bad("missing ']'".to_string()))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(bad("empty section name".to_string()));
+        }
+
+        let mut pending = PendingSection {
+            line,
+            section: Section::new(name, Vec::new()),
+            bars: None,
+            progression: String::new(),
+        };
+        for setting in settings.split_whitespace() {
+            match setting.split_once('=') {
+                Some(("bars", value)) => {
+                    let bars = value
+                        .parse()
+                        .map_err(|_| bad(format!("bad bars {value:?}")))?;
+                    pending.bars = Some(bars);
+                }
+                Some(("pattern", value)) => pending.section.pattern = Some(value.parse()?),
+                Some(("dynamics", value)) => {
+                    let section = &mut pending.section;
+                    match value.split_once(['<', '>']) {
+                        Some((start, end)) => {
+                            let start: Dynamic = start.parse().map_err(bad)?;
+                            let end: Dynamic = end.parse().map_err(bad)?;
+                            if value.contains('<') != (end.velocity() > start.velocity()) {
+                                return Err(bad(format!(
+                                    "{value:?}: '<' must get louder, '>' softer"
+                                )));
+                            }
+                            (section.dynamic, section.dynamic_end) = (Some(start), Some(end));
+                        }
+                        None => section.dynamic = Some(value.parse().map_err(bad)?),
+                    }
+                }
+                _ => return Err(bad(format!("unknown section setting {setting:?}"))),
+            }
+        }
+        Ok(pending)
+    }
+
+    fn finish(self, key: Key) -> Result<Section> {
+        let mut section = self.section;
+        section.chords = parse_progression(&self.progression, key)?;
+        if section.chords.is_empty() {
+            return Err(invalid(format!(
+                "line {}: section {:?} has no chords",
+                self.line, section.name
+            )));
+        }
+        section.bars = self.bars.unwrap_or_else(|| measure_count(&section.chords));
+        Ok(section)
+    }
+}
+
+impl Song {
+    /// The section called `name`, if the chart defines one.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// The sections in playing order.
+    pub fn parts(&self) -> Result<Vec<&Section>> {
+        if self.arrangement.is_empty() {
+            return Ok(self.sections.iter().collect());
+        }
+        self.arrangement
+            .iter()
+            .map(|name| {
+                self.section(name)
+                    .ok_or_else(|| invalid(format!("arrangement names unknown section {name:?}")))
+            })
+            .collect()
+    }
+
+    /// Every chord of the song, in playing order.
+    pub fn chords(&self) -> Result<Vec<Chord>> {
+        Ok(self
+            .parts()?
+            .into_iter()
+            .flat_map(Section::played_chords)
+            .collect())
+    }
+
+    /// Parse a song chart. Each section starts with a `[name]` header,
+    /// optionally followed by `bars=`, `pattern=` and `dynamics=` settings
+    /// (`dynamics=p<f` for a crescendo across the section),
+    /// and lists its progression (resolved against `key`) on the lines below.
+    /// An `arrangement:` line sets the playing order; lines starting with `#`
+    /// are comments.
+    ///
+    /// ```text
+    /// [verse] pattern=folk dynamics=mp
+    /// I vi IV V
+    /// [chorus] bars=8 dynamics=mf<ff
+    /// IV V I I
+    /// arrangement: verse*2 chorus verse chorus*2
+    /// ```
+    pub fn parse(text: &str, key: Key) -> Result<Song> {
+        let mut song = Song::default();
+        let mut pending: Option<PendingSection> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                if let Some(done) = pending.take() {
+                    song.sections.push(done.finish(key)?);
+                }
+                let next = PendingSection::parse_header(number, header)?;
+                if song.section(&next.section.name).is_some() {
+                    return Err(invalid(format!(
+                        "line {number}: section {:?} is defined twice",
+                        next.section.name
+                    )));
+                }
+                pending = Some(next);
+            } else if let Some(order) = line.strip_prefix("arrangement:") {
+                song.arrangement.extend(parse_arrangement(order)?);
+            } else if let Some(pending) = pending.as_mut() {
+                pending.progression.push(' ');
+                pending.progression.push_str(line);
+            } else {
+                return Err(invalid(format!(
+                    "line {number}: chords before the first [section]"
+                )));
+            }
+        }
+        if let Some(done) = pending {
+            song.sections.push(done.finish(key)?);
+        }
+
+        if song.sections.is_empty() {
+            return Err(invalid("no sections"));
+        }
+        song.parts()?;
+        Ok(song)
+    }
+
+    /// Read a song chart from a file (see [`Song::parse`]).
+    pub fn load(path: impl AsRef<Path>, key: Key) -> Result<Song> {
+        Song::parse(&fs::read_to_string(path)?, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHART: &str = "\
+# A short song
+[intro] bars=2 dynamics=p
+I
+[verse] pattern=folk
+I vi
+IV F#m
+[chorus] dynamics=ff
+IV V I
+arrangement: intro verse*2, chorus
+";
+
+    #[test]
+    fn test_parse_song_chart() {
+        let song = Song::parse(CHART, "G".parse().unwrap()).unwrap();
+        assert_eq!(song.sections.len(), 3);
+        assert_eq!(song.arrangement, ["intro", "verse", "verse", "chorus"]);
+        let intro = song.section("intro").unwrap();
+        assert_eq!((intro.bars, intro.dynamic), (2, Some(Dynamic::Piano)));
+        assert_eq!(intro.played_chords().len(), 2);
+        let verse = song.section("verse").unwrap();
+        assert_eq!(verse.bars, 4);
+        assert_eq!(verse.pattern, StrumPattern::named("folk"));
+        assert_eq!(song.chords().unwrap().len(), 2 + 4 + 4 + 3);
+        assert_eq!(song.chords().unwrap()[2].root % 12, 7);
+    }
+
+    #[test]
+    fn test_arrangement_and_errors() {
+        assert_eq!(parse_arrangement("a*3 b").unwrap(), ["a", "a", "a", "b"]);
+        assert!(parse_arrangement("a*0").is_err());
+        let key: Key = "C".parse().unwrap();
+        assert!(matches!(
+            Song::parse("[verse]\nI\narrangement: verse bridge", key),
+            Err(Error::InvalidSong { .. })
+        ));
+        assert!(Song::parse("I IV\n[verse]\nV", key).is_err());
+        assert!(Song::parse("[verse] dynamics=loud\nI", key).is_err());
+        assert!(Song::parse("[verse] dynamics=f<p\nI", key).is_err());
+        let song = Song::parse("[outro] dynamics=f>pp\nI IV", key).unwrap();
+        let outro = &song.sections[0];
+        assert_eq!(outro.dynamic_end, Some(Dynamic::Pianissimo));
+        let envelope = outro.envelope(8.0).unwrap();
+        assert_eq!(envelope.points, [(8.0, 1.0), (10.0, 33.0 / 96.0)]);
+        assert!(Song::parse("[verse]\n", key).is_err());
+        // Without an arrangement every section plays once.
+        let song = Song::parse("[a]\nI\n[b] bars=3\nIV V", key).unwrap();
+        assert_eq!(song.chords().unwrap().len(), 4);
+        // Sections keep their chords' durations and fill whole bars.
+        let song = Song::parse("[a] bars=2\n| I . . IV | V |", key).unwrap();
+        let measures: Vec<f64> = song.chords().unwrap().iter().map(|c| c.measures).collect();
+        assert_eq!(measures, [0.75, 0.25, 1.0]);
+        let song = Song::parse("[a] bars=1\n| I . . IV | V |", key).unwrap();
+        assert_eq!(song.chords().unwrap().len(), 2);
+    }
+}
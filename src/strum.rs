@@ -0,0 +1,453 @@
+//! Strummed chord-track note events.
+
+use midly::num::{u28, u4, u7};
+use midly::TrackEvent;
+
+use crate::chord::Chord;
+use crate::error::Result;
+use crate::events::{delta_ticks, tick_u28, value_u7};
+use crate::harmonic_rhythm::{chord_at, chord_spans, measure_count};
+use crate::pattern::{Direction, StrumHit, StrumPattern};
+use crate::tempo::Meter;
+use crate::timeline::Timeline;
+
+// ---------------------------------------------------------------------
+// The chord generation
+// ---------------------------------------------------------------------
+
+/// Time between successive strings of one strum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrumSpread {
+    /// Ticks, whatever the tempo.
+    Ticks(u32),
+    /// Milliseconds, converted to ticks using the tempo and PPQ.
+    Millis(f64),
+}
+
+impl StrumSpread {
+    /// The spread in ticks at `tempo_bpm` with `ppq` ticks per quarter note.
+    pub fn to_ticks(self, ppq: u16, tempo_bpm: f64) -> u32 {
+        match self {
+            StrumSpread::Ticks(ticks) => ticks,
+            StrumSpread::Millis(ms) => (ms * f64::from(ppq) * tempo_bpm / 60_000.0)
+                .round()
+                .max(0.0) as u32,
+        }
+    }
+}
+
+impl Default for StrumSpread {
+    fn default() -> Self {
+        StrumSpread::Ticks(0)
+    }
+}
+
+/// How the pick travels across the strings within one hit.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StrumFeel {
+    /// Delay between successive strings, in ticks (0 = all notes together).
+    pub spread_ticks: u32,
+    /// Velocity drop from the first to the last string struck, 0.0..=1.0.
+    pub taper: f32,
+}
+
+impl StrumFeel {
+    /// Order `notes` the way the pick meets them: low to high on a
+    /// down-strum, high to low on an up-strum.
+    pub fn string_order(notes: &[u8], direction: Direction) -> Vec<u8> {
+        let mut ordered = notes.to_vec();
+        ordered.sort_unstable();
+        if direction == Direction::Up {
+            ordered.reverse();
+        }
+        ordered
+    }
+
+    /// Onset delay and velocity scale of the `index`-th of `count` strings.
+    fn string_offset(&self, index: usize, count: usize) -> (u32, f32) {
+        let delay = self.spread_ticks.saturating_mul(index as u32);
+        let position = if count > 1 {
+            index as f32 / (count - 1) as f32
+        } else {
+            0.0
+        };
+        (delay, 1.0 - self.taper.clamp(0.0, 1.0) * position)
+    }
+}
+
+/// We generate "strumming" events for `chords`, each lasting its own
+/// [`Chord::measures`], placed on a [`Timeline`] at absolute ticks. `pattern`
+/// repeats every measure and each hit strums whichever chord is sounding,
+/// scaling hit velocities from `base_velocity`; `feel` rolls each hit across
+/// the strings in the hit's direction. Every string stops together at the end
+/// of the hit, or where the next chord takes over. A pushed chord is also
+/// struck where it lands.
+pub fn chord_track_timeline(
+    chords: &[Chord],
+    pattern: &StrumPattern,
+    feel: &StrumFeel,
+    start_tick: u32,
+    meter: Meter,
+    channel: u4,
+    base_velocity: u8,
+) -> Result<Timeline<'static>> {
+    let mut timeline = Timeline::new();
+    let ticks_per_measure = meter.ticks_per_measure();
+
+    value_u7(i32::from(base_velocity))?;
+
+    // Every interval of the chord (and any slash bass) sounds on each hit.
+    let chord_notes = chords
+        .iter()
+        .map(Chord::notes)
+        .collect::<Result<Vec<_>>>()?;
+    let spans = chord_spans(chords, start_tick, meter);
+
+    // The pattern's hits through the whole progression, as (tick, length, hit).
+    let mut hits: Vec<(u64, u32, &StrumHit)> = Vec::new();
+    for measure in 0..measure_count(chords) as u64 {
+        let measure_start = u64::from(start_tick) + measure * u64::from(ticks_per_measure);
+        for (offset, length, hit) in pattern.hit_ticks(meter) {
+            hits.push((measure_start + u64::from(offset), length, hit));
+        }
+    }
+    let pushes = spans
+        .iter()
+        .zip(chords)
+        .filter(|(span, chord)| chord.push && span.start < span.downbeat)
+        .filter(|(span, _)| !hits.iter().any(|&(tick, _, _)| tick == span.start))
+        .map(|(span, _)| {
+            let next_hit = hits
+                .iter()
+                .map(|&(tick, _, _)| tick)
+                .find(|&tick| tick > span.start)
+                .unwrap_or(span.end);
+            (span.start, (next_hit - span.start) as u32, &PUSH_HIT)
+        })
+        .collect::<Vec<_>>();
+
+    for (tick, length, hit) in hits.into_iter().chain(pushes) {
+        let Some(index) = chord_at(&spans, tick) else {
+            continue;
+        };
+        let length = length.min((spans[index].end - tick) as u32);
+        let note_on_abs = tick_u28(tick)?.as_int();
+        let strings = StrumFeel::string_order(&chord_notes[index], hit.direction);
+        for (index, &midi_note) in strings.iter().enumerate() {
+            let (delay, taper) = feel.string_offset(index, strings.len());
+            // Late strings still sound for at least a tick.
+            let delay = delay.min(length - 1);
+            let velocity = hit_velocity(base_velocity, hit.velocity * taper);
+            timeline.note(
+                note_on_abs + delay,
+                length - delay,
+                channel,
+                u7::from(midi_note),
+                velocity,
+            );
+        }
+    }
+
+    Ok(timeline)
+}
+
+/// The strum given to a pushed chord that the pattern does not hit.
+const PUSH_HIT: StrumHit = StrumHit {
+    step: 0,
+    length: 1.0,
+    velocity: 1.0,
+    direction: Direction::Down,
+};
+
+/// Scale `base` by a pattern's relative velocity, keeping it audible and in range.
+fn hit_velocity(base: u8, scale: f32) -> u7 {
+    u7::from((f32::from(base) * scale).round().clamp(1.0, 127.0) as u8)
+}
+
+/// Delta-timed strum events for `chords`; see [`chord_track_timeline`].
+pub fn generate_chord_track_events(
+    chords: &[Chord],
+    pattern: &StrumPattern,
+    feel: &StrumFeel,
+    start_tick: u28,
+    meter: Meter,
+    channel: u4,
+    base_velocity: u8,
+) -> Result<Vec<TrackEvent<'static>>> {
+    // Deltas are measured from `start_tick`, so the first hit carries no lead-in.
+    let timeline = chord_track_timeline(
+        chords,
+        pattern,
+        feel,
+        start_tick.as_int(),
+        meter,
+        channel,
+        base_velocity,
+    )?;
+    let mut events = timeline.to_track_events()?;
+    if let Some(first) = events.first_mut() {
+        first.delta = delta_ticks(
+            first.delta.as_int(),
+            start_tick.as_int(),
+            "chord track start",
+        )?;
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::progression::parse_progression;
+    use midly::{MidiMessage, TrackEventKind};
+
+    // Four sixteenth-note hits at the top of the bar.
+    fn sixteenths() -> StrumPattern {
+        StrumPattern::parse("DDDD............").unwrap()
+    }
+
+    // The default progression (C G F):
+    fn get_demo_chords() -> Vec<Chord> {
+        parse_progression("I V IV", "C".parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_chord_track_events_not_empty() {
+        // We'll call the generator with typical values.
+        let chords = get_demo_chords();
+        let events = generate_chord_track_events(
+            &chords,
+            &sixteenths(),
+            &StrumFeel::default(),
+            u28::from(0),
+            Meter::default(),
+            u4::from(0),
+            64,
+        )
+        .unwrap();
+        assert!(!events.is_empty());
+    }
+
+    #[test]
+    fn test_chord_track_events_sound_every_interval() {
+        let chords = vec![Chord {
+            root: 60,
+            intervals: vec![0, 4, 7],
+            bass: None,
+            measures: 1.0,
+            push: false,
+        }];
+        let events = generate_chord_track_events(
+            &chords,
+            &sixteenths(),
+            &StrumFeel::default(),
+            u28::from(0),
+            Meter::default(),
+            u4::from(0),
+            64,
+        )
+        .unwrap();
+
+        // 4 strum hits x 3 notes x (on + off)
+        assert_eq!(events.len(), 4 * 3 * 2);
+
+        let mut abs = 0u32;
+        let mut on_keys = Vec::new();
+        for ev in &events {
+            abs += ev.delta.as_int();
+            if let TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { key, .. },
+                ..
+            } = ev.kind
+            {
+                on_keys.push((abs, key.as_int()));
+            }
+        }
+        assert_eq!(&on_keys[..3], &[(0, 60), (0, 64), (0, 67)]);
+        assert_eq!(&on_keys[3..6], &[(120, 60), (120, 64), (120, 67)]);
+        // Last note off lands one sixteenth after the last hit.
+        assert_eq!(abs, 360 + 120);
+    }
+
+    #[test]
+    fn test_pattern_sets_offsets_lengths_and_velocity() {
+        let chords = vec![Chord::new(60, vec![0])];
+        let pattern = StrumPattern::parse("D.u.").unwrap();
+        let timeline = chord_track_timeline(
+            &chords,
+            &pattern,
+            &StrumFeel::default(),
+            0,
+            Meter::default(),
+            u4::from(0),
+            100,
+        )
+        .unwrap();
+        let hits: Vec<(u32, u8)> = timeline
+            .iter()
+            .filter_map(|ev| match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { vel, .. },
+                    ..
+                } => Some((ev.tick, vel.as_int())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(hits, vec![(0, 100), (960, 60)]);
+        assert_eq!(timeline.end_tick(), 960 + 480);
+    }
+
+    #[test]
+    fn test_chords_follow_their_durations_and_pushes() {
+        let chords = parse_progression("| C G | Am . . <F |", "C".parse().unwrap()).unwrap();
+        let timeline = chord_track_timeline(
+            &chords,
+            &StrumPattern::default(),
+            &StrumFeel::default(),
+            0,
+            Meter::default(),
+            u4::from(0),
+            100,
+        )
+        .unwrap();
+        // (tick, root) of every strum, and where each ends.
+        let mut strums: Vec<(u32, u8)> = Vec::new();
+        let mut ends: Vec<u32> = Vec::new();
+        for ev in timeline.iter() {
+            match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, .. },
+                    ..
+                } if !strums.iter().any(|&(tick, _)| tick == ev.tick) => {
+                    strums.push((ev.tick, key.as_int()))
+                }
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOff { .. },
+                    ..
+                } if !ends.contains(&ev.tick) => ends.push(ev.tick),
+                _ => {}
+            }
+        }
+        strums.sort_unstable();
+        ends.sort_unstable();
+        assert_eq!(
+            strums,
+            [
+                (0, 60),
+                (480, 60),
+                (960, 67),
+                (1440, 67),
+                (1920, 69),
+                (2400, 69),
+                (2880, 69),
+                (3120, 65),
+                (3360, 65)
+            ]
+        );
+        // Am is cut off where the pushed F lands, and F rings until the next hit.
+        assert!(ends.contains(&3120) && ends.contains(&3360));
+        assert_eq!(timeline.end_tick(), 3840);
+    }
+
+    #[test]
+    fn test_chord_track_events_report_errors() {
+        let too_high = vec![Chord::new(126, vec![0, 4, 7])];
+        assert!(matches!(
+            generate_chord_track_events(
+                &too_high,
+                &sixteenths(),
+                &StrumFeel::default(),
+                u28::from(0),
+                Meter::default(),
+                u4::from(0),
+                64
+            ),
+            Err(Error::NoteOutOfRange { value: 130 })
+        ));
+
+        let chords = get_demo_chords();
+        assert!(matches!(
+            generate_chord_track_events(
+                &chords,
+                &sixteenths(),
+                &StrumFeel::default(),
+                u28::from(0),
+                Meter::default(),
+                u4::from(0),
+                200
+            ),
+            Err(Error::NoteOutOfRange { value: 200 })
+        ));
+        assert!(matches!(
+            generate_chord_track_events(
+                &chords,
+                &sixteenths(),
+                &StrumFeel::default(),
+                u28::max_value(),
+                Meter::default(),
+                u4::from(0),
+                64
+            ),
+            Err(Error::TickOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_strum_spread_follows_direction_and_tapers() {
+        let chords = vec![Chord::new(60, vec![0, 4, 7])];
+        let pattern = StrumPattern::parse("D.U.").unwrap();
+        let feel = StrumFeel {
+            spread_ticks: 10,
+            taper: 0.5,
+        };
+        let timeline = chord_track_timeline(
+            &chords,
+            &pattern,
+            &feel,
+            0,
+            Meter::default(),
+            u4::from(0),
+            100,
+        )
+        .unwrap();
+
+        let mut ons: Vec<(u32, u8, u8)> = Vec::new();
+        let mut offs: Vec<(u32, u8)> = Vec::new();
+        for ev in timeline.iter() {
+            match ev.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, vel },
+                    ..
+                } => ons.push((ev.tick, key.as_int(), vel.as_int())),
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOff { key, .. },
+                    ..
+                } => offs.push((ev.tick, key.as_int())),
+                _ => {}
+            }
+        }
+        // Down-strum rolls low to high, up-strum high to low.
+        assert_eq!(
+            ons,
+            vec![
+                (0, 60, 100),
+                (10, 64, 75),
+                (20, 67, 50),
+                (960, 67, 100),
+                (970, 64, 75),
+                (980, 60, 50),
+            ]
+        );
+        // All strings of a hit stop together.
+        assert!(offs[..3].iter().all(|&(tick, _)| tick == 480));
+    }
+
+    #[test]
+    fn test_spread_in_millis_follows_tempo() {
+        assert_eq!(StrumSpread::Millis(10.0).to_ticks(480, 120.0), 10);
+        assert_eq!(StrumSpread::Millis(10.0).to_ticks(960, 60.0), 10);
+        assert_eq!(StrumSpread::Millis(25.0).to_ticks(480, 120.0), 24);
+        assert_eq!(StrumSpread::Ticks(7).to_ticks(480, 120.0), 7);
+    }
+}
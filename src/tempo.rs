@@ -0,0 +1,261 @@
+//! Tempo maps and conductor meta events.
+
+use midly::num::u24;
+use midly::{MetaMessage, TrackEventKind};
+
+use crate::error::{Error, Result};
+use crate::progression::{Key, Mode};
+use crate::timeline::Timeline;
+
+// ---------------------------------------------------------------------
+// Conductor meta events: tempo map, time signature, key signature
+// ---------------------------------------------------------------------
+
+/// Measure lengths in ticks for a time signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Meter {
+    /// Ticks per quarter note.
+    pub ppq: u16,
+    /// Beats per measure.
+    pub numerator: u8,
+    /// Note value of one beat: 4 for quarters, 8 for eighths.
+    pub denominator: u8,
+}
+
+impl Meter {
+    /// The meter of `time_signature` (numerator, denominator) at `ppq`.
+    pub fn new(ppq: u16, time_signature: (u8, u8)) -> Self {
+        let (numerator, denominator) = time_signature;
+        Meter {
+            ppq,
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Ticks in one measure.
+    pub fn ticks_per_measure(&self) -> u32 {
+        u32::from(self.ppq) * 4 * u32::from(self.numerator) / u32::from(self.denominator.max(1))
+    }
+
+    /// Ticks in one beat.
+    pub fn beat_ticks(&self) -> u32 {
+        u32::from(self.ppq) * 4 / u32::from(self.denominator.max(1))
+    }
+
+    /// Beats in one measure.
+    pub fn beats_per_measure(&self) -> u32 {
+        u32::from(self.numerator)
+    }
+
+    /// How early a pushed chord lands: an eighth note in any meter.
+    pub fn push_ticks(&self) -> u32 {
+        u32::from(self.ppq) / 2
+    }
+}
+
+impl Default for Meter {
+    /// 4/4 at 480 PPQ.
+    fn default() -> Self {
+        Meter::new(480, (4, 4))
+    }
+}
+
+/// A tempo that takes effect at an absolute tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoChange {
+    /// Absolute tick the tempo starts at.
+    pub tick: u32,
+    /// Quarter notes per minute.
+    pub bpm: f64,
+}
+
+/// Tempo changes over a song, always starting with a tempo at tick 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    changes: Vec<TempoChange>,
+}
+
+impl TempoMap {
+    /// A map holding a single tempo from the start.
+    pub fn new(bpm: f64) -> Self {
+        TempoMap {
+            changes: vec![TempoChange { tick: 0, bpm }],
+        }
+    }
+
+    /// Set the tempo from `tick` onwards, replacing any change at the same tick.
+    pub fn set(&mut self, tick: u32, bpm: f64) {
+        match self.changes.binary_search_by_key(&tick, |c| c.tick) {
+            Ok(index) => self.changes[index].bpm = bpm,
+            Err(index) => self.changes.insert(index, TempoChange { tick, bpm }),
+        }
+    }
+
+    /// Tempo in effect at `tick`.
+    pub fn bpm_at(&self, tick: u32) -> f64 {
+        self.changes
+            .iter()
+            .take_while(|c| c.tick <= tick)
+            .last()
+            .map_or(self.changes[0].bpm, |c| c.bpm)
+    }
+
+    /// Every tempo, in tick order, starting at tick 0.
+    pub fn changes(&self) -> &[TempoChange] {
+        &self.changes
+    }
+
+    /// Wall-clock seconds from the start of the song to `tick`.
+    pub fn seconds_at(&self, tick: u32, ppq: u16) -> f64 {
+        let mut seconds = 0.0;
+        for (index, change) in self.changes.iter().enumerate() {
+            if change.tick >= tick {
+                break;
+            }
+            let end = self
+                .changes
+                .get(index + 1)
+                .map_or(tick, |next| next.tick.min(tick));
+            let quarters = f64::from(end - change.tick) / f64::from(ppq);
+            seconds += quarters * 60.0 / change.bpm;
+        }
+        seconds
+    }
+
+    /// One `Tempo` meta event per change, failing for a tempo the event
+    /// cannot hold.
+    pub fn timeline(&self) -> Result<Timeline<'static>> {
+        let mut timeline = Timeline::new();
+        for change in &self.changes {
+            timeline.push(change.tick, tempo_event(change.bpm)?);
+        }
+        Ok(timeline)
+    }
+}
+
+/// Microseconds per quarter note at `bpm`.
+pub fn micros_per_quarter(bpm: f64) -> u32 {
+    (60_000_000.0 / bpm).round() as u32
+}
+
+/// `Tempo` meta event for `bpm`, which must be positive and give between 1 and
+/// 2^24 - 1 microseconds per quarter note.
+fn tempo_event(bpm: f64) -> Result<TrackEventKind<'static>> {
+    let micros = 60_000_000.0 / bpm;
+    if !(bpm > 0.0 && (0.5..=f64::from(u24::max_value().as_int())).contains(&micros)) {
+        return Err(Error::InvalidTempo { bpm });
+    }
+    Ok(TrackEventKind::Meta(MetaMessage::Tempo(u24::from(
+        micros_per_quarter(bpm),
+    ))))
+}
+
+/// `TimeSignature` meta event for `numerator`/`denominator`.
+///
+/// The metronome clicks once per beat: a dotted quarter in compound meters
+/// such as 6/8 and 12/8, otherwise one denominator note.
+pub fn time_signature_event(numerator: u8, denominator: u8) -> TrackEventKind<'static> {
+    let compound = denominator >= 8 && numerator > 3 && numerator.is_multiple_of(3);
+    let clocks_per_click = 96 / u32::from(denominator) * if compound { 3 } else { 1 };
+    TrackEventKind::Meta(MetaMessage::TimeSignature(
+        numerator,
+        denominator.trailing_zeros() as u8,
+        clocks_per_click.min(255) as u8,
+        8,
+    ))
+}
+
+/// Sharps (positive) or flats (negative) of each major key, indexed by tonic pitch class.
+const MAJOR_ACCIDENTALS: [i8; 12] = [0, -5, 2, -3, 4, -1, 6, 1, -4, 3, -2, 5];
+
+/// `KeySignature` meta event for `key`. Church modes use the signature of
+/// their parent major scale (D dorian is written with no sharps or flats).
+pub fn key_signature_event(key: Key) -> TrackEventKind<'static> {
+    let mode_offset = match key.mode {
+        Mode::Major => 0,
+        Mode::Dorian => 2,
+        Mode::Phrygian => 4,
+        Mode::Lydian => 5,
+        Mode::Mixolydian => 7,
+        Mode::Minor => 9,
+        Mode::Locrian => 11,
+    };
+    let parent = (key.tonic + 12 - mode_offset) % 12;
+    TrackEventKind::Meta(MetaMessage::KeySignature(
+        MAJOR_ACCIDENTALS[usize::from(parent)],
+        key.mode == Mode::Minor,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tempo_map_lookup_and_seconds() {
+        let mut map = TempoMap::new(120.0);
+        map.set(1920, 60.0);
+        map.set(960, 90.0);
+        map.set(960, 100.0);
+        assert_eq!(map.changes().len(), 3);
+        assert_eq!(map.bpm_at(0), 120.0);
+        assert_eq!(map.bpm_at(959), 120.0);
+        assert_eq!(map.bpm_at(960), 100.0);
+        assert_eq!(map.bpm_at(5000), 60.0);
+
+        // Two quarters at 120 BPM, then two at 100 BPM, then one at 60 BPM.
+        let seconds = map.seconds_at(2400, 480);
+        assert!((seconds - (1.0 + 1.2 + 1.0)).abs() < 1e-9);
+        assert_eq!(map.timeline().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_tempos_a_tempo_event_cannot_hold_are_rejected() {
+        assert!(TempoMap::new(4.0).timeline().is_ok());
+        assert!(TempoMap::new(60_000_000.0).timeline().is_ok());
+        for bpm in [3.5, 0.0, -120.0, f64::NAN, 1.0e9] {
+            assert!(matches!(
+                TempoMap::new(bpm).timeline(),
+                Err(Error::InvalidTempo { .. })
+            ));
+        }
+        let mut map = TempoMap::new(120.0);
+        map.set(1920, 0.0);
+        assert!(map.timeline().is_err());
+    }
+
+    #[test]
+    fn test_time_and_key_signatures() {
+        assert_eq!(
+            time_signature_event(4, 4),
+            TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, 24, 8))
+        );
+        assert_eq!(
+            time_signature_event(6, 8),
+            TrackEventKind::Meta(MetaMessage::TimeSignature(6, 3, 36, 8))
+        );
+        assert_eq!(
+            time_signature_event(3, 8),
+            TrackEventKind::Meta(MetaMessage::TimeSignature(3, 3, 12, 8))
+        );
+
+        let sig = |key: &str| key_signature_event(key.parse().unwrap());
+        assert_eq!(
+            sig("C"),
+            TrackEventKind::Meta(MetaMessage::KeySignature(0, false))
+        );
+        assert_eq!(
+            sig("Eb"),
+            TrackEventKind::Meta(MetaMessage::KeySignature(-3, false))
+        );
+        assert_eq!(
+            sig("F# minor"),
+            TrackEventKind::Meta(MetaMessage::KeySignature(3, true))
+        );
+        assert_eq!(
+            sig("D dorian"),
+            TrackEventKind::Meta(MetaMessage::KeySignature(0, false))
+        );
+    }
+}
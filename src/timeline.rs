@@ -0,0 +1,210 @@
+//! Events at absolute ticks, sorted and converted to deltas at the end.
+
+use midly::num::{u4, u7};
+use midly::{MetaMessage, MidiMessage, TrackEvent, TrackEventKind};
+
+use crate::error::Result;
+use crate::events::{delta_ticks, tick_u28};
+
+// ---------------------------------------------------------------------
+// Absolute-time event timeline
+// ---------------------------------------------------------------------
+
+/// An event placed at an absolute tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedEvent<'a> {
+    /// Absolute tick, from the start of the track.
+    pub tick: u32,
+    /// The event itself.
+    pub kind: TrackEventKind<'a>,
+}
+
+/// Events at absolute ticks, added in any order and converted to delta-timed
+/// track events once everything is in place.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timeline<'a> {
+    events: Vec<TimedEvent<'a>>,
+}
+
+impl<'a> Timeline<'a> {
+    /// An empty timeline.
+    pub fn new() -> Self {
+        Timeline { events: Vec::new() }
+    }
+
+    /// Add an event at `tick`.
+    pub fn push(&mut self, tick: u32, kind: TrackEventKind<'a>) {
+        self.events.push(TimedEvent { tick, kind });
+    }
+
+    /// Add a note-on at `tick` and its note-off `duration` ticks later.
+    pub fn note(&mut self, tick: u32, duration: u32, channel: u4, key: u7, vel: u7) {
+        self.push(
+            tick,
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, vel },
+            },
+        );
+        self.push(
+            tick.saturating_add(duration),
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff {
+                    key,
+                    vel: u7::from(64),
+                },
+            },
+        );
+    }
+
+    /// Add every event from `other`, keeping their absolute ticks.
+    pub fn merge(&mut self, other: Timeline<'a>) {
+        self.events.extend(other.events);
+    }
+
+    /// Number of events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether there are no events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// The events in the order they were added (or last sorted).
+    pub fn iter(&self) -> impl Iterator<Item = &TimedEvent<'a>> {
+        self.events.iter()
+    }
+
+    /// The events, mutably, to move or reshape them in place.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut TimedEvent<'a>> {
+        self.events.iter_mut()
+    }
+
+    /// Tick of the latest event, or 0 when empty.
+    pub fn end_tick(&self) -> u32 {
+        self.events.iter().map(|ev| ev.tick).max().unwrap_or(0)
+    }
+
+    /// Stable sort by tick. At the same tick, meta events come first, then
+    /// note-offs, then other channel messages, then note-ons, so a note that
+    /// ends where the next one starts never cuts it off.
+    pub fn sort(&mut self) {
+        self.events
+            .sort_by_key(|ev| (ev.tick, event_order(&ev.kind)));
+    }
+
+    /// Sort and convert to delta-timed track events.
+    pub fn to_track_events(&self) -> Result<Vec<TrackEvent<'a>>> {
+        let mut sorted = self.clone();
+        sorted.sort();
+
+        let mut previous = 0;
+        sorted
+            .events
+            .iter()
+            .map(|ev| {
+                tick_u28(u64::from(ev.tick))?;
+                let delta = delta_ticks(ev.tick, previous, "timeline")?;
+                previous = ev.tick;
+                Ok(TrackEvent {
+                    delta,
+                    kind: ev.kind,
+                })
+            })
+            .collect()
+    }
+
+    /// Sort, convert, and terminate with `EndOfTrack`.
+    pub fn to_track(&self) -> Result<Vec<TrackEvent<'a>>> {
+        let mut track = self.to_track_events()?;
+        track.push(TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+        Ok(track)
+    }
+}
+
+impl<'a> Extend<TimedEvent<'a>> for Timeline<'a> {
+    fn extend<I: IntoIterator<Item = TimedEvent<'a>>>(&mut self, iter: I) {
+        self.events.extend(iter);
+    }
+}
+
+impl<'a> FromIterator<TimedEvent<'a>> for Timeline<'a> {
+    fn from_iter<I: IntoIterator<Item = TimedEvent<'a>>>(iter: I) -> Self {
+        Timeline {
+            events: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Ordering of events that share a tick.
+fn event_order(kind: &TrackEventKind) -> u8 {
+    match kind {
+        TrackEventKind::Meta(MetaMessage::EndOfTrack) => 5,
+        TrackEventKind::Meta(_) => 0,
+        TrackEventKind::Midi { message, .. } => match message {
+            MidiMessage::NoteOff { .. } => 1,
+            MidiMessage::NoteOn { vel, .. } if vel.as_int() == 0 => 1,
+            MidiMessage::NoteOn { .. } => 3,
+            _ => 2,
+        },
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_of(kind: &TrackEventKind) -> (bool, u8) {
+        match kind {
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { key, .. },
+                ..
+            } => (true, key.as_int()),
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOff { key, .. },
+                ..
+            } => (false, key.as_int()),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_out_of_order_events_become_deltas() {
+        let mut timeline = Timeline::new();
+        // Second note added first; it starts where the first one ends.
+        timeline.note(480, 480, 0.into(), 62.into(), 90.into());
+        timeline.note(0, 480, 0.into(), 60.into(), 90.into());
+
+        let events = timeline.to_track_events().unwrap();
+        let deltas: Vec<u32> = events.iter().map(|ev| ev.delta.as_int()).collect();
+        let keys: Vec<(bool, u8)> = events.iter().map(|ev| key_of(&ev.kind)).collect();
+        assert_eq!(deltas, vec![0, 480, 0, 480]);
+        assert_eq!(keys, vec![(true, 60), (false, 60), (true, 62), (false, 62)]);
+    }
+
+    #[test]
+    fn test_merge_keeps_absolute_ticks_and_ends_track() {
+        let mut chords = Timeline::new();
+        chords.note(0, 100, 0.into(), 60.into(), 64.into());
+        let mut bass = Timeline::new();
+        bass.note(50, 100, 1.into(), 36.into(), 64.into());
+        chords.merge(bass);
+
+        assert_eq!(chords.len(), 4);
+        assert_eq!(chords.end_tick(), 150);
+        let track = chords.to_track().unwrap();
+        let total: u32 = track.iter().map(|ev| ev.delta.as_int()).sum();
+        assert_eq!(total, 150);
+        assert_eq!(
+            track.last().unwrap().kind,
+            TrackEventKind::Meta(MetaMessage::EndOfTrack)
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Voicings chosen to move each voice as little as possible.
+
+use crate::chord::Chord;
+use crate::error::{Error, Result};
+use crate::voicing::{
+    chord_tones, shape, voice_chord, voiced_chord, Register, Voicing, VoicingStyle,
+};
+
+// ---------------------------------------------------------------------
+// Voice leading: pick voicings that move as little as possible
+// ---------------------------------------------------------------------
+
+/// Settings for [`voice_lead`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VoiceLeading {
+    /// Every voice stays inside this range when any voicing fits.
+    pub register: Register,
+    /// Steer away from parallel fifths and octaves between successive chords.
+    pub avoid_parallels: bool,
+}
+
+/// Extra cost of a move with parallel fifths or octaves when they are avoided;
+/// larger than any realistic voice movement, so they are only kept when
+/// nothing else fits.
+const PARALLEL_PENALTY: u32 = 1000;
+
+/// Close and drop-2 voicings of `chord`, in every inversion and octave that
+/// fits inside `register`. Falls back to the best-placed close voicing when
+/// none fits entirely.
+fn candidates(chord: &Chord, register: Register) -> Result<Vec<Vec<i32>>> {
+    if chord.intervals.is_empty() {
+        return Err(Error::InvalidChord {
+            reason: format!("chord rooted at {} has no intervals", chord.root),
+        });
+    }
+
+    let root = i32::from(chord.root);
+    let mut found: Vec<Vec<i32>> = Vec::new();
+    for style in [VoicingStyle::Close, VoicingStyle::Drop2] {
+        for inversion in 0..chord_tones(chord).len() {
+            let shape = shape(chord, Voicing::new(style, inversion as u8));
+            for octave in -10..=10 {
+                let notes: Vec<i32> = shape.iter().map(|&n| root + n + octave * 12).collect();
+                if notes.iter().all(|&n| register.contains(n)) && !found.contains(&notes) {
+                    found.push(notes);
+                }
+            }
+        }
+    }
+
+    if found.is_empty() {
+        let fallback = voice_chord(chord, Voicing::new(VoicingStyle::Close, 0), register)?;
+        let root = i32::from(fallback.root);
+        found.push(
+            fallback
+                .intervals
+                .iter()
+                .map(|&i| root + i32::from(i))
+                .collect(),
+        );
+    }
+    Ok(found)
+}
+
+/// Total semitones the voices travel from `from` to `to`.
+///
+/// Chords with the same number of voices pair them from the bottom up; otherwise
+/// every note is measured to the nearest note of the other chord, both ways.
+pub fn movement(from: &[i32], to: &[i32]) -> u32 {
+    let mut from = from.to_vec();
+    let mut to = to.to_vec();
+    from.sort_unstable();
+    to.sort_unstable();
+
+    if from.len() == to.len() {
+        return from.iter().zip(&to).map(|(a, b)| a.abs_diff(*b)).sum();
+    }
+    let nearest =
+        |note: i32, chord: &[i32]| chord.iter().map(|n| n.abs_diff(note)).min().unwrap_or(0);
+    let forward: u32 = to.iter().map(|&n| nearest(n, &from)).sum();
+    let backward: u32 = from.iter().map(|&n| nearest(n, &to)).sum();
+    forward + backward
+}
+
+/// Whether any two voices a fifth or octave apart move in the same direction
+/// to the same interval. Voices are paired from the bottom up, so only chords
+/// with the same number of voices are compared.
+pub fn has_parallels(from: &[i32], to: &[i32]) -> bool {
+    if from.len() != to.len() {
+        return false;
+    }
+    let mut from = from.to_vec();
+    let mut to = to.to_vec();
+    from.sort_unstable();
+    to.sort_unstable();
+
+    let perfect = |interval: i32| matches!(interval.rem_euclid(12), 0 | 7);
+    for lower in 0..from.len() {
+        for upper in lower + 1..from.len() {
+            let before = from[upper] - from[lower];
+            let after = to[upper] - to[lower];
+            let lower_motion = to[lower] - from[lower];
+            let upper_motion = to[upper] - from[upper];
+            if perfect(before)
+                && before.rem_euclid(12) == after.rem_euclid(12)
+                && lower_motion != 0
+                && lower_motion.signum() == upper_motion.signum()
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Voice `chords` so the whole progression moves as little as possible.
+///
+/// Each chord is tried in every close and drop-2 voicing that fits the
+/// register; the cheapest path through them is found over the whole
+/// progression, starting near the middle of the register. The returned chords
+/// keep their root pitch class and slash bass (see [`crate::voicing::voice_chord`]).
+pub fn voice_lead(chords: &[Chord], options: &VoiceLeading) -> Result<Vec<Chord>> {
+    let options_per_chord = chords
+        .iter()
+        .map(|chord| candidates(chord, options.register))
+        .collect::<Result<Vec<_>>>()?;
+    let Some(first) = options_per_chord.first() else {
+        return Ok(Vec::new());
+    };
+
+    let center = i32::from(options.register.low) + i32::from(options.register.high);
+    let start_cost = |notes: &[i32]| {
+        let mean_twice = 2 * notes.iter().sum::<i32>() / notes.len() as i32;
+        mean_twice.abs_diff(center) / 2
+    };
+    let transition = |from: &[i32], to: &[i32]| {
+        let penalty = if options.avoid_parallels && has_parallels(from, to) {
+            PARALLEL_PENALTY
+        } else {
+            0
+        };
+        movement(from, to) + penalty
+    };
+
+    // Cheapest cost of reaching each candidate, and the candidate it came from.
+    let mut costs: Vec<u32> = first.iter().map(|notes| start_cost(notes)).collect();
+    let mut back: Vec<Vec<usize>> = Vec::with_capacity(chords.len());
+    for pair in options_per_chord.windows(2) {
+        let (previous, current) = (&pair[0], &pair[1]);
+        let mut next_costs = Vec::with_capacity(current.len());
+        let mut next_back = Vec::with_capacity(current.len());
+        for notes in current {
+            let (from, cost) = previous
+                .iter()
+                .enumerate()
+                .map(|(index, before)| (index, costs[index] + transition(before, notes)))
+                .min_by_key(|&(_, cost)| cost)
+                .expect("every chord has a candidate");
+            next_costs.push(cost);
+            next_back.push(from);
+        }
+        costs = next_costs;
+        back.push(next_back);
+    }
+
+    // Walk back from the cheapest final voicing.
+    let mut choice = (0..costs.len())
+        .min_by_key(|&index| costs[index])
+        .expect("every chord has a candidate");
+    let mut path = vec![choice; chords.len()];
+    for (step, links) in back.iter().enumerate().rev() {
+        choice = links[choice];
+        path[step] = choice;
+    }
+
+    chords
+        .iter()
+        .zip(&options_per_chord)
+        .zip(path)
+        .map(|((chord, candidates), index)| voiced_chord(chord, &candidates[index]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progression::parse_progression;
+
+    fn upper_voices(chord: &Chord) -> Vec<i32> {
+        let notes = chord.notes().unwrap();
+        let skip = usize::from(chord.bass.is_some());
+        notes[skip..].iter().map(|&n| i32::from(n)).collect()
+    }
+
+    #[test]
+    fn test_demo_progression_moves_by_step() {
+        let chords = parse_progression("I V IV", "C".parse().unwrap()).unwrap();
+        let literal: Vec<Vec<i32>> = chords.iter().map(upper_voices).collect();
+        let literal_total: u32 = literal.windows(2).map(|w| movement(&w[0], &w[1])).sum();
+
+        let led = voice_lead(&chords, &VoiceLeading::default()).unwrap();
+        let voices: Vec<Vec<i32>> = led.iter().map(upper_voices).collect();
+        let total: u32 = voices.windows(2).map(|w| movement(&w[0], &w[1])).sum();
+
+        assert_eq!(literal_total, 27);
+        assert!(total <= 9, "{voices:?}");
+        let roots: Vec<u8> = led.iter().map(|c| c.root % 12).collect();
+        assert_eq!(roots, vec![0, 7, 5]);
+        assert!(voices.iter().flatten().all(|&n| (48..=79).contains(&n)));
+    }
+
+    #[test]
+    fn test_parallels_detected_and_avoided() {
+        assert!(has_parallels(&[60, 64, 67], &[62, 66, 69]));
+        assert!(!has_parallels(&[60, 64, 67], &[59, 62, 67]));
+        assert!(!has_parallels(&[60, 64, 67], &[60, 64, 67]));
+
+        let chords = parse_progression("C D E F G", "C".parse().unwrap()).unwrap();
+        let options = VoiceLeading {
+            avoid_parallels: true,
+            ..VoiceLeading::default()
+        };
+        let led = voice_lead(&chords, &options).unwrap();
+        for pair in led.windows(2) {
+            let (from, to) = (upper_voices(&pair[0]), upper_voices(&pair[1]));
+            assert!(!has_parallels(&from, &to), "{from:?} -> {to:?}");
+        }
+    }
+
+    #[test]
+    fn test_register_and_mixed_voice_counts() {
+        let chords = parse_progression("Dm7 G7 Cmaj7 A7/C#", "C".parse().unwrap()).unwrap();
+        let options = VoiceLeading {
+            register: "C3-C5".parse().unwrap(),
+            avoid_parallels: false,
+        };
+        let led = voice_lead(&chords, &options).unwrap();
+        assert_eq!(led.len(), 4);
+        for chord in &led {
+            assert!(upper_voices(chord).iter().all(|&n| (48..=72).contains(&n)));
+        }
+        assert_eq!(led[3].bass.map(|b| b % 12), Some(1));
+        assert_eq!(movement(&[60, 64, 67], &[60, 64, 67, 70]), 3);
+        assert!(voice_lead(&[], &options).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,424 @@
+//! Inversions, drop, spread, shell and quartal voicings in a register.
+
+use std::str::FromStr;
+
+use crate::chord::Chord;
+use crate::chord_symbol::parse_note_name;
+use crate::error::{Error, Result};
+
+// ---------------------------------------------------------------------
+// Chord voicings: inversions, drops, spread, shell and quartal shapes
+// ---------------------------------------------------------------------
+
+/// How the chord tones are stacked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoicingStyle {
+    /// Every tone within an octave, in the order given by the inversion.
+    Close,
+    /// Close voicing with the second voice from the top dropped an octave.
+    Drop2,
+    /// Close voicing with the third voice from the top dropped an octave.
+    Drop3,
+    /// Open spread: every other voice of the close voicing raised an octave (1-5-3-7).
+    Spread,
+    /// Jazz shell: root, third and seventh (or sixth) only.
+    Shell,
+    /// The chord's own tones stacked as near to perfect fourths as they allow,
+    /// one voice per chord tone: C7sus4 is G C F Bb, Dm7 is C F A D.
+    Quartal,
+}
+
+impl FromStr for VoicingStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "close" => VoicingStyle::Close,
+            "drop2" | "drop-2" => VoicingStyle::Drop2,
+            "drop3" | "drop-3" => VoicingStyle::Drop3,
+            "spread" | "open" => VoicingStyle::Spread,
+            "shell" => VoicingStyle::Shell,
+            "quartal" => VoicingStyle::Quartal,
+            other => return Err(format!("unknown voicing {other:?}")),
+        })
+    }
+}
+
+/// A voicing style plus the inversion it starts from (0 = root position).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Voicing {
+    /// How the chord tones are spread.
+    pub style: VoicingStyle,
+    /// Which chord tone is at the bottom of the close voicing the style starts
+    /// from. Ignored by `Shell` and `Quartal`.
+    pub inversion: u8,
+}
+
+impl Voicing {
+    /// `style` starting from `inversion`.
+    pub fn new(style: VoicingStyle, inversion: u8) -> Self {
+        Voicing { style, inversion }
+    }
+}
+
+/// Inclusive MIDI note range the voiced chord should sit in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register {
+    /// Lowest note allowed.
+    pub low: u8,
+    /// Highest note allowed.
+    pub high: u8,
+}
+
+impl Register {
+    /// Where a keyboard player's left and right hand comp: C3 to G5.
+    pub const KEYBOARD: Register = Register { low: 48, high: 79 };
+    /// Open-position guitar: E2 to E5.
+    pub const GUITAR: Register = Register { low: 40, high: 76 };
+    /// Four-string bass: E1 to G3.
+    pub const BASS: Register = Register { low: 28, high: 55 };
+
+    /// Whether `note` falls inside the range.
+    pub fn contains(&self, note: i32) -> bool {
+        (i32::from(self.low)..=i32::from(self.high)).contains(&note)
+    }
+}
+
+impl Default for Register {
+    fn default() -> Self {
+        Register::KEYBOARD
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    /// Parse "C3-G5" or "48-79".
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (low, high) = s
+            .split_once('-')
+            .ok_or_else(|| format!("register {s:?} should look like C3-G5"))?;
+        let note = |text: &str| {
+            parse_midi_note(text).ok_or_else(|| format!("cannot parse note {text:?} in register"))
+        };
+        let (low, high) = (note(low)?, note(high)?);
+        if low >= high {
+            return Err(format!("register {s:?} is empty"));
+        }
+        Ok(Register { low, high })
+    }
+}
+
+/// Parse a MIDI note number ("60") or a note name with octave ("C4", "F#2").
+pub fn parse_midi_note(text: &str) -> Option<u8> {
+    let text = text.trim();
+    if let Ok(number) = text.parse::<u8>() {
+        return (number <= 127).then_some(number);
+    }
+    let (pitch_class, consumed) = parse_note_name(text)?;
+    let octave: i32 = text[consumed..].parse().ok()?;
+    let note = (octave + 1) * 12 + i32::from(pitch_class);
+    u8::try_from(note).ok().filter(|&n| n <= 127)
+}
+
+/// Distinct pitch-class intervals of `chord`, root first, ascending within the octave.
+pub(crate) fn chord_tones(chord: &Chord) -> Vec<i32> {
+    let mut tones: Vec<i32> = chord.intervals.iter().map(|&i| i32::from(i % 12)).collect();
+    tones.sort_unstable();
+    tones.dedup();
+    tones
+}
+
+/// Close voicing of `tones` with `inversion` at the bottom, relative to the root.
+fn close_shape(tones: &[i32], inversion: u8) -> Vec<i32> {
+    let start = usize::from(inversion) % tones.len();
+    let mut shape: Vec<i32> = Vec::with_capacity(tones.len());
+    for &tone in tones[start..].iter().chain(&tones[..start]) {
+        let mut note = tone;
+        if let Some(&previous) = shape.last() {
+            while note <= previous {
+                note += 12;
+            }
+        }
+        shape.push(note);
+    }
+    shape
+}
+
+/// Drop the `nth` voice from the top an octave, if the shape has that many voices.
+fn drop_voice(mut shape: Vec<i32>, nth: usize) -> Vec<i32> {
+    if shape.len() > nth {
+        let index = shape.len() - nth;
+        shape[index] -= 12;
+        shape.sort_unstable();
+    }
+    shape
+}
+
+/// Relative shape of `chord` under `voicing`, before register placement.
+pub(crate) fn shape(chord: &Chord, voicing: Voicing) -> Vec<i32> {
+    let tones = chord_tones(chord);
+    match voicing.style {
+        VoicingStyle::Close => close_shape(&tones, voicing.inversion),
+        VoicingStyle::Drop2 => drop_voice(close_shape(&tones, voicing.inversion), 2),
+        VoicingStyle::Drop3 => drop_voice(close_shape(&tones, voicing.inversion), 3),
+        VoicingStyle::Spread => {
+            let mut shape = close_shape(&tones, voicing.inversion);
+            for note in shape.iter_mut().skip(1).step_by(2) {
+                *note += 12;
+            }
+            shape.sort_unstable();
+            shape
+        }
+        VoicingStyle::Shell => {
+            let third = tones.iter().copied().find(|&t| t == 3 || t == 4);
+            let seventh = tones
+                .iter()
+                .copied()
+                .find(|&t| t == 10 || t == 11)
+                .or_else(|| tones.iter().copied().find(|&t| t == 9))
+                .or_else(|| tones.iter().copied().find(|&t| (6..=8).contains(&t)));
+            let mut shape = vec![0];
+            shape.extend(third);
+            shape.extend(seventh);
+            shape
+        }
+        VoicingStyle::Quartal => quartal_shape(&tones),
+    }
+}
+
+/// `tones` stacked upwards, each note the one left whose interval above the
+/// note before is nearest a perfect fourth. Every tone is tried at the bottom,
+/// and the stack straying least from fourths wins.
+fn quartal_shape(tones: &[i32]) -> Vec<i32> {
+    (0..tones.len())
+        .map(|bottom| {
+            let mut rest = tones.to_vec();
+            let mut shape = vec![rest.remove(bottom)];
+            while let Some(&top) = shape.last().filter(|_| !rest.is_empty()) {
+                let above = |tone: i32| (tone - top - 1).rem_euclid(12) + 1;
+                let next = (0..rest.len())
+                    .min_by_key(|&i| ((above(rest[i]) - 5).abs(), above(rest[i])))
+                    .unwrap_or(0);
+                shape.push(top + above(rest.remove(next)));
+            }
+            shape
+        })
+        .min_by_key(|shape| {
+            shape
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0] - 5).abs())
+                .sum::<i32>()
+        })
+        .unwrap_or_default()
+}
+
+/// Octave shift (in semitones) that puts `shape` above `root` best inside `register`:
+/// fewest notes outside, then closest to the middle of the range.
+fn place(root: i32, shape: &[i32], register: Register) -> i32 {
+    let center = (i32::from(register.low) + i32::from(register.high)) as f64 / 2.0;
+    (-11..=11)
+        .map(|octave| octave * 12)
+        .min_by(|&a, &b| {
+            let score = |shift: i32| {
+                let notes = shape.iter().map(|&n| root + n + shift);
+                let outside = notes.clone().filter(|&n| !register.contains(n)).count();
+                let mean = notes.map(f64::from).sum::<f64>() / shape.len() as f64;
+                (outside, (mean - center).abs())
+            };
+            score(a).partial_cmp(&score(b)).expect("scores are finite")
+        })
+        .expect("octave range is not empty")
+}
+
+/// Voice `chord` in `register`.
+///
+/// The result is a `Chord` with the same root pitch class whose `root` sits at
+/// or below the lowest voiced note, so `notes()` returns the voicing from the
+/// bottom up. A slash bass stays below the voicing.
+pub fn voice_chord(chord: &Chord, voicing: Voicing, register: Register) -> Result<Chord> {
+    if chord.intervals.is_empty() {
+        return Err(Error::InvalidChord {
+            reason: format!("chord rooted at {} has no intervals", chord.root),
+        });
+    }
+
+    let shape = shape(chord, voicing);
+    let root = i32::from(chord.root);
+    let shift = place(root, &shape, register);
+    let notes: Vec<i32> = shape.iter().map(|&n| root + n + shift).collect();
+    voiced_chord(chord, &notes)
+}
+
+/// `chord` rewritten to sound exactly `notes` (absolute, at least one), keeping
+/// its root pitch class and timing and putting any slash bass below them.
+pub(crate) fn voiced_chord(chord: &Chord, notes: &[i32]) -> Result<Chord> {
+    let root = i32::from(chord.root);
+    let lowest = notes.iter().copied().min().unwrap_or(root);
+
+    // Root of the voiced chord: the root's pitch class at or below the lowest note.
+    let voiced_root = lowest - (lowest - root).rem_euclid(12);
+    let intervals = notes
+        .iter()
+        .map(|&note| {
+            u8::try_from(note - voiced_root).map_err(|_| Error::NoteOutOfRange { value: note })
+        })
+        .collect::<Result<Vec<u8>>>()?;
+
+    let bass = chord.bass.map(|bass| {
+        let below = (lowest - i32::from(bass)).rem_euclid(12);
+        lowest - if below == 0 { 12 } else { below }
+    });
+
+    let check = |note: i32| {
+        u8::try_from(note)
+            .ok()
+            .filter(|&n| n <= 127)
+            .ok_or(Error::NoteOutOfRange { value: note })
+    };
+    Ok(Chord {
+        root: check(voiced_root)?,
+        intervals,
+        bass: bass.map(check).transpose()?,
+        measures: chord.measures,
+        push: chord.push,
+    })
+}
+
+/// Voice every chord of a progression the same way.
+pub fn voice_progression(
+    chords: &[Chord],
+    voicing: Voicing,
+    register: Register,
+) -> Result<Vec<Chord>> {
+    chords
+        .iter()
+        .map(|chord| voice_chord(chord, voicing, register))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chord_symbol::parse_chord;
+
+    fn voiced(symbol: &str, style: VoicingStyle, inversion: u8, register: &str) -> Vec<u8> {
+        let chord = parse_chord(symbol).unwrap();
+        let voicing = Voicing::new(style, inversion);
+        voice_chord(&chord, voicing, register.parse().unwrap())
+            .unwrap()
+            .notes()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_close_voicings_and_inversions() {
+        assert_eq!(
+            voiced("C", VoicingStyle::Close, 0, "C4-C5"),
+            vec![60, 64, 67]
+        );
+        assert_eq!(
+            voiced("C", VoicingStyle::Close, 1, "C4-C5"),
+            vec![64, 67, 72]
+        );
+        assert_eq!(
+            voiced("C", VoicingStyle::Close, 2, "C4-C5"),
+            vec![67, 72, 76]
+        );
+
+        let first_inversion = voice_chord(
+            &parse_chord("C").unwrap(),
+            Voicing::new(VoicingStyle::Close, 1),
+            Register::KEYBOARD,
+        )
+        .unwrap();
+        assert_eq!(first_inversion.root % 12, 0);
+    }
+
+    #[test]
+    fn test_drop_spread_shell_and_quartal() {
+        // Close Cmaj7 is C E G B; drop-2 lowers G, drop-3 lowers E.
+        assert_eq!(
+            voiced("Cmaj7", VoicingStyle::Drop2, 0, "G3-C5"),
+            vec![55, 60, 64, 71]
+        );
+        assert_eq!(
+            voiced("Cmaj7", VoicingStyle::Drop3, 0, "E3-C5"),
+            vec![52, 60, 67, 71]
+        );
+        assert_eq!(
+            voiced("Cmaj7", VoicingStyle::Spread, 0, "C3-C5"),
+            vec![48, 55, 64, 71]
+        );
+        assert_eq!(
+            voiced("G7", VoicingStyle::Shell, 0, "C3-C5"),
+            vec![55, 59, 65]
+        );
+        assert_eq!(
+            voiced("C7sus4", VoicingStyle::Quartal, 0, "C3-C5"),
+            vec![55, 60, 65, 70]
+        );
+        assert_eq!(
+            voiced("Dm7", VoicingStyle::Quartal, 0, "C3-C5"),
+            vec![48, 53, 57, 62]
+        );
+    }
+
+    #[test]
+    fn test_quartal_voicings_keep_the_chord_tones() {
+        let major = voiced("C", VoicingStyle::Quartal, 0, "C3-C5");
+        let minor = voiced("Cm", VoicingStyle::Quartal, 0, "C3-C5");
+        assert_ne!(major, minor);
+        assert!(major.iter().any(|n| n % 12 == 4) && minor.iter().any(|n| n % 12 == 3));
+        for symbol in ["C", "Cm", "C7", "Cmaj7", "Cm7b5", "C9"] {
+            let chord = parse_chord(symbol).unwrap();
+            let mut chord_pcs: Vec<u8> = chord.intervals.iter().map(|i| i % 12).collect();
+            chord_pcs.sort_unstable();
+            chord_pcs.dedup();
+            let mut voiced_pcs: Vec<u8> = voiced(symbol, VoicingStyle::Quartal, 0, "C3-C5")
+                .iter()
+                .map(|n| n % 12)
+                .collect();
+            voiced_pcs.sort_unstable();
+            assert_eq!(voiced_pcs, chord_pcs, "{symbol}");
+        }
+    }
+
+    #[test]
+    fn test_register_placement_and_slash_bass() {
+        for symbol in ["C", "F#m7", "Bb13", "D/F#"] {
+            let chord = parse_chord(symbol).unwrap();
+            let voiced = voice_chord(
+                &chord,
+                Voicing::new(VoicingStyle::Close, 0),
+                Register::GUITAR,
+            )
+            .unwrap();
+            let notes = voiced.notes().unwrap();
+            let upper = if chord.bass.is_some() {
+                &notes[1..]
+            } else {
+                &notes[..]
+            };
+            assert!(
+                upper.iter().all(|&n| (40..=76).contains(&n)),
+                "{symbol}: {notes:?}"
+            );
+        }
+
+        let d_over_f_sharp = voiced("D/F#", VoicingStyle::Close, 0, "C4-C5");
+        assert_eq!(d_over_f_sharp, vec![54, 62, 66, 69]);
+    }
+
+    #[test]
+    fn test_parse_register_and_style() {
+        assert_eq!("C3-G5".parse::<Register>().unwrap(), Register::KEYBOARD);
+        assert_eq!("40-76".parse::<Register>().unwrap(), Register::GUITAR);
+        assert!("G5-C3".parse::<Register>().is_err());
+        assert_eq!(
+            "drop-2".parse::<VoicingStyle>().unwrap(),
+            VoicingStyle::Drop2
+        );
+        assert!("cluster".parse::<VoicingStyle>().is_err());
+    }
+}